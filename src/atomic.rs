@@ -0,0 +1,151 @@
+//! Writing files so they are never left half written.
+
+use failure::Error;
+use std::ffi::{OsStr,OsString};
+use std::fs;
+use std::fs::{File,OpenOptions};
+use std::io::{ErrorKind,Write};
+use std::path::{Path,PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize,Ordering};
+use std::time::{SystemTime,UNIX_EPOCH};
+
+pub fn write(path : &Path, contents : &[u8]) -> Result<(),Error> {
+    //! Replaces the file at `path` with `contents`, either all of it is
+    //! written or the file is left as it was.
+    //!
+    //! The contents go into a temporary file next to it first, which is
+    //! synced and then renamed over the file. The file keeps its
+    //! permissions, and if it is a symlink the file it points to is replaced.
+
+    // writing where the link points, so the link stays
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let name = match path.file_name() {
+        Some(name) => name,
+        None => return Err(format_err!("{} isn't a file",path.display())),
+    };
+
+    let (temp,file) = create_temp(&dir,name)?;
+    let permissions = fs::metadata(&path).ok().map(|metadata| metadata.permissions());
+    let result = write_temp(file,contents,permissions)
+        .and_then(|_| fs::rename(&temp,&path).map_err(Error::from))
+        .and_then(|_| sync_dir(&dir));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+fn create_temp(dir : &Path, name : &OsStr) -> Result<(PathBuf,File),Error> {
+    //! Creates a new temporary file for `name` in `dir`, named
+    //! `.{name}.{pid}.{count}.{nanos}.tmp` so other threads and processes
+    //! writing the same file never get the same one.
+
+    static COUNT : AtomicUsize = AtomicUsize::new(0);
+
+    loop {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.subsec_nanos()).unwrap_or(0);
+        let mut temp_name = OsString::from(".");
+        temp_name.push(name);
+        temp_name.push(format!(".{}.{}.{}.tmp",process::id(),COUNT.fetch_add(1,Ordering::Relaxed),nanos));
+        let temp = dir.join(temp_name);
+
+        match OpenOptions::new().write(true).create_new(true).open(&temp) {
+            Ok(file) => return Ok((temp,file)),
+            Err(ref error) if error.kind() == ErrorKind::AlreadyExists => continue,
+            Err(error) => return Err(error.into()),
+        }
+    }
+}
+
+fn write_temp(mut file : File, contents : &[u8], permissions : Option<fs::Permissions>) -> Result<(),Error> {
+    if let Some(permissions) = permissions {
+        file.set_permissions(permissions)?;
+    }
+    file.write_all(contents)?;
+    file.sync_all()?;
+    Ok(())
+}
+
+#[cfg(unix)]
+fn sync_dir(dir : &Path) -> Result<(),Error> {
+    //! Syncs the folder so the rename is kept after a crash.
+
+    File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_ : &Path) -> Result<(),Error> {
+    // folders can't be opened on windows, the rename is already durable
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::write;
+    use std::fs;
+
+    #[test]
+    fn writing() {
+        let dir = ::tempfile::tempdir().unwrap();
+        let path = dir.path().join("settings");
+        assert!(write(&path,b"first").is_ok());
+        assert_eq!(fs::read_to_string(&path).unwrap(),"first");
+        assert!(write(&path,b"second").is_ok());
+        assert_eq!(fs::read_to_string(&path).unwrap(),"second");
+
+        // no temporary files are left
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(),1);
+
+        assert!(write(&dir.path().join("missing").join("settings"),b"").is_err());
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(),1);
+    }
+
+    #[test]
+    fn writing_from_threads() {
+        use std::sync::Arc;
+        use std::thread;
+
+        let dir = Arc::new(::tempfile::tempdir().unwrap());
+        let threads : Vec<_> = (0..8).map(|i| {
+            let dir = dir.clone();
+            thread::spawn(move || {
+                let contents = format!("thread {}",i).repeat(1000);
+                for _ in 0..20 {
+                    write(&dir.path().join("settings"),contents.as_bytes()).unwrap();
+                }
+            })
+        }).collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        // one of the writes, never a mix of them
+        let contents = fs::read_to_string(dir.path().join("settings")).unwrap();
+        assert!((0..8).any(|i| contents == format!("thread {}",i).repeat(1000)));
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(),1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn keeps_permissions_and_links() {
+        use std::os::unix::fs::{PermissionsExt,symlink};
+
+        let dir = ::tempfile::tempdir().unwrap();
+        let path = dir.path().join("settings");
+        fs::write(&path,"old").unwrap();
+        fs::set_permissions(&path,fs::Permissions::from_mode(0o600)).unwrap();
+        let link = dir.path().join("link");
+        symlink(&path,&link).unwrap();
+
+        assert!(write(&link,b"new").is_ok());
+        assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert_eq!(fs::read_to_string(&path).unwrap(),"new");
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777,0o600);
+    }
+}
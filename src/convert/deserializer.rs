@@ -0,0 +1,212 @@
+use Type;
+use UNSET_KEY;
+use super::ConvertError;
+
+use serde::de::{self,Visitor,IntoDeserializer,Unexpected};
+use std::collections::{HashMap,hash_map};
+use std::vec;
+
+fn unexpected(value : &Type) -> Unexpected<'_> {
+    match *value {
+        Type::Text(ref text) => Unexpected::Str(text),
+        Type::Switch(switch) => Unexpected::Bool(switch),
+        Type::Int(int) => Unexpected::Signed(i64::from(int)),
+        Type::Float(float) => Unexpected::Float(f64::from(float)),
+        Type::Complex(_) => Unexpected::Map,
+        Type::Array(_) => Unexpected::Seq,
+        Type::None => Unexpected::Unit,
+        Type::Unset => Unexpected::Other("unset marker"),
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Type {
+    type Error = ConvertError;
+
+    fn deserialize_any<V>(self, visitor : V) -> Result<V::Value,ConvertError> where V : Visitor<'de> {
+        match self {
+            Type::Text(text) => visitor.visit_string(text),
+            Type::Switch(switch) => visitor.visit_bool(switch),
+            Type::Int(int) => visitor.visit_i32(int),
+            // going through the text form so a stored `1.1` reads back as `1.1`
+            // in a `f64` instead of `1.100000023841858`
+            Type::Float(float) => match float.to_string().parse::<f64>() {
+                Ok(wide) => visitor.visit_f64(wide),
+                Err(_) => visitor.visit_f32(float),
+            },
+            Type::Complex(hash) => {
+                let mut access = ComplexAccess { iter : hash.into_iter(), value : None };
+                let result = visitor.visit_map(&mut access)?;
+                match access.iter.len() {
+                    0 => Ok(result),
+                    remaining => Err(de::Error::custom(format!("{} unexpected extra entries in map",remaining))),
+                }
+            },
+            Type::Array(array) => {
+                let length = array.len();
+                let mut access = ArrayAccess { iter : array.into_iter(), index : 0 };
+                let result = visitor.visit_seq(&mut access)?;
+                match access.iter.len() {
+                    0 => Ok(result),
+                    _ => Err(de::Error::invalid_length(length,&"fewer elements in array")),
+                }
+            },
+            Type::None => visitor.visit_unit(),
+            // read the same way as it is written in a file
+            Type::Unset => {
+                let mut hash = HashMap::new();
+                hash.insert(UNSET_KEY.to_string(),Type::Switch(true));
+                de::Deserializer::deserialize_any(Type::Complex(hash),visitor)
+            },
+        }
+    }
+
+    fn deserialize_option<V>(self, visitor : V) -> Result<V::Value,ConvertError> where V : Visitor<'de> {
+        match self {
+            Type::None => visitor.visit_none(),
+            other => visitor.visit_some(other),
+        }
+    }
+
+    fn deserialize_newtype_struct<V>(self, _ : &'static str, visitor : V) -> Result<V::Value,ConvertError>
+        where V : Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V>(self, _ : &'static str, _ : &'static [&'static str], visitor : V) -> Result<V::Value,ConvertError>
+        where V : Visitor<'de>,
+    {
+        match self {
+            Type::Text(variant) => visitor.visit_enum(EnumAccess { variant, value : None }),
+            Type::Complex(hash) => {
+                if hash.len() != 1 {
+                    return Err(de::Error::invalid_value(Unexpected::Map,&"map with a single key"));
+                }
+                let (variant,value) = hash.into_iter().next().unwrap();
+                visitor.visit_enum(EnumAccess { variant, value : Some(value) })
+            },
+            other => Err(de::Error::invalid_type(unexpected(&other),&"string or map")),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
+
+struct ComplexAccess {
+    iter : hash_map::IntoIter<String,Type>,
+    value : Option<(String,Type)>,
+}
+
+impl<'de> de::MapAccess<'de> for ComplexAccess {
+    type Error = ConvertError;
+
+    fn next_key_seed<K>(&mut self, seed : K) -> Result<Option<K::Value>,ConvertError> where K : de::DeserializeSeed<'de> {
+        match self.iter.next() {
+            None => Ok(None),
+            Some((key,value)) => {
+                let result = seed.deserialize(key.clone().into_deserializer()).map_err(|error : ConvertError| error.at(&key))?;
+                self.value = Some((key,value));
+                Ok(Some(result))
+            }
+        }
+    }
+
+    fn next_value_seed<V>(&mut self, seed : V) -> Result<V::Value,ConvertError> where V : de::DeserializeSeed<'de> {
+        match self.value.take() {
+            None => Err(de::Error::custom("value is missing")),
+            Some((key,value)) => seed.deserialize(value).map_err(|error| error.at(&key)),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> { Some(self.iter.len()) }
+}
+
+struct ArrayAccess {
+    iter : vec::IntoIter<Type>,
+    index : usize,
+}
+
+impl<'de> de::SeqAccess<'de> for ArrayAccess {
+    type Error = ConvertError;
+
+    fn next_element_seed<A>(&mut self, seed : A) -> Result<Option<A::Value>,ConvertError> where A : de::DeserializeSeed<'de> {
+        match self.iter.next() {
+            None => Ok(None),
+            Some(value) => {
+                let index = self.index.to_string();
+                self.index += 1;
+                seed.deserialize(value).map(Some).map_err(|error| error.at(&index))
+            }
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> { Some(self.iter.len()) }
+}
+
+struct EnumAccess {
+    variant : String,
+    value : Option<Type>,
+}
+
+impl<'de> de::EnumAccess<'de> for EnumAccess {
+    type Error = ConvertError;
+    type Variant = VariantAccess;
+
+    fn variant_seed<V>(self, seed : V) -> Result<(V::Value,VariantAccess),ConvertError> where V : de::DeserializeSeed<'de> {
+        let variant = self.variant;
+        let value = seed.deserialize(variant.clone().into_deserializer())?;
+        Ok((value,VariantAccess { variant, value : self.value }))
+    }
+}
+
+struct VariantAccess {
+    variant : String,
+    value : Option<Type>,
+}
+
+impl<'de> de::VariantAccess<'de> for VariantAccess {
+    type Error = ConvertError;
+
+    fn unit_variant(self) -> Result<(),ConvertError> {
+        match self.value {
+            None | Some(Type::None) => Ok(()),
+            Some(other) => Err(de::Error::invalid_type(unexpected(&other),&"unit variant")),
+        }
+    }
+
+    fn newtype_variant_seed<A>(self, seed : A) -> Result<A::Value,ConvertError> where A : de::DeserializeSeed<'de> {
+        let VariantAccess { variant, value } = self;
+        match value {
+            None => Err(de::Error::invalid_type(Unexpected::UnitVariant,&"newtype variant")),
+            Some(value) => seed.deserialize(value).map_err(|error| error.at(&variant)),
+        }
+    }
+
+    fn tuple_variant<V>(self, _ : usize, visitor : V) -> Result<V::Value,ConvertError> where V : Visitor<'de> {
+        let VariantAccess { variant, value } = self;
+        match value {
+            Some(Type::Array(array)) => {
+                de::Deserializer::deserialize_any(Type::Array(array),visitor).map_err(|error| error.at(&variant))
+            },
+            Some(other) => Err(de::Error::invalid_type(unexpected(&other),&"tuple variant")),
+            None => Err(de::Error::invalid_type(Unexpected::UnitVariant,&"tuple variant")),
+        }
+    }
+
+    fn struct_variant<V>(self, _ : &'static [&'static str], visitor : V) -> Result<V::Value,ConvertError>
+        where V : Visitor<'de>,
+    {
+        let VariantAccess { variant, value } = self;
+        match value {
+            Some(Type::Complex(hash)) => {
+                de::Deserializer::deserialize_any(Type::Complex(hash),visitor).map_err(|error| error.at(&variant))
+            },
+            Some(other) => Err(de::Error::invalid_type(unexpected(&other),&"struct variant")),
+            None => Err(de::Error::invalid_type(Unexpected::UnitVariant,&"struct variant")),
+        }
+    }
+}
@@ -0,0 +1,200 @@
+//! Conversions between a [Type](enum.Type.html) tree and any `serde`
+//! compatible data structure.
+//!
+//! `Type` can act as both a serde `Serializer` output and a `Deserializer`
+//! input, much like `serde_json::Value`, so any struct that implements
+//! `Serialize` / `Deserialize` can be stored in or read out of a `Settings`
+//! without implementing [SupportedType](trait.SupportedType.html) by hand.
+
+use Type;
+
+use failure::Error;
+use serde::ser::{self,Serialize};
+use serde::de::{self,DeserializeOwned};
+use std::error;
+use std::fmt;
+
+mod serializer;
+mod deserializer;
+
+/// Error returned when converting to or from a `Type` fails.
+///
+/// Keeps track of the path to the field that failed, so a
+/// message will look like `` `ui.theme.color`: invalid type: integer `3`, expected a string ``
+#[derive(Debug,Clone,PartialEq)]
+pub struct ConvertError {
+    path : Vec<String>,
+    message : String,
+}
+
+impl ConvertError {
+    pub fn new<M:fmt::Display>(message : M) -> ConvertError {
+        ConvertError { path : Vec::new(), message : message.to_string() }
+    }
+
+    pub fn path(&self) -> String {
+        //! The dot notation path to the field that failed, relative to
+        //! the value being converted.
+
+        self.path.join(".")
+    }
+
+    pub fn message(&self) -> &str {
+        //! The error message without the path.
+
+        &self.message
+    }
+
+    pub fn at(mut self, field : &str) -> ConvertError {
+        //! Places the error inside of `field`, used while walking
+        //! back up the tree so the path is built from the inside out.
+
+        self.path.insert(0,field.to_string());
+        self
+    }
+
+    pub fn under(mut self, key_path : &str) -> ConvertError {
+        //! Places the error under a complete `key_path` in dot notation.
+
+        let mut path : Vec<String> = key_path.split('.').map(|part| part.to_string()).collect();
+        path.append(&mut self.path);
+        self.path = path;
+        self
+    }
+}
+
+impl fmt::Display for ConvertError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f,"{}",self.message)
+        } else {
+            write!(f,"`{}`: {}",self.path(),self.message)
+        }
+    }
+}
+
+impl error::Error for ConvertError { }
+
+impl ser::Error for ConvertError {
+    fn custom<M:fmt::Display>(message : M) -> ConvertError { ConvertError::new(message) }
+}
+
+impl de::Error for ConvertError {
+    fn custom<M:fmt::Display>(message : M) -> ConvertError { ConvertError::new(message) }
+}
+
+pub fn to_type<A>(value : &A) -> Result<Type,ConvertError> where A : Serialize + ?Sized {
+    //! Serializes any `Serialize` value into a `Type` tree.
+    //!
+    //! ```rust
+    //! # extern crate settingsfile;
+    //! # #[macro_use] extern crate serde_derive;
+    //! use settingsfile::{to_type,Type};
+    //!
+    //! #[derive(Serialize)]
+    //! struct Theme { name : String, size : i32 }
+    //!
+    //! # fn main() {
+    //! let theme = to_type(&Theme { name : "dark".to_string(), size : 12 }).unwrap();
+    //! assert_eq!(theme.to_complex().unwrap().get("size"),Some(&Type::Int(12)));
+    //! # }
+    //! ```
+
+    value.serialize(serializer::Serializer)
+}
+
+pub fn from_type<A>(value : Type) -> Result<A,ConvertError> where A : DeserializeOwned {
+    //! Deserializes a `Type` tree into any `Deserialize` type.
+    //!
+    //! The returned error will contain the path to the field that
+    //! could not be deserialized.
+
+    A::deserialize(value)
+}
+
+pub(crate) fn from_value<A>(key_path : &str, value : Option<Type>) -> Result<A,Error> where A : DeserializeOwned {
+    //! Deserializes the value found at `key_path`, what the `get`
+    //! functions use. A missing key is read as `Type::None`, so it
+    //! only works for types like `Option` and errors otherwise.
+
+    match value {
+        Some(value) => Ok(from_type(value).map_err(|error| error.under(key_path))?),
+        None => match from_type(Type::None) {
+            Ok(value) => Ok(value),
+            Err(_) => Err(format_err!("`{}` is not set",key_path)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use Type;
+    use super::{to_type,from_type};
+    use std::collections::HashMap;
+
+    #[derive(Serialize,Deserialize,Debug,PartialEq)]
+    enum Mode { Light, Dark, Custom(String), Sized { width : i32 } }
+
+    #[derive(Serialize,Deserialize,Debug,PartialEq)]
+    struct Theme {
+        name : String,
+        size : u16,
+        scale : f64,
+        enabled : bool,
+        fonts : Vec<String>,
+        accent : Option<String>,
+        mode : Mode,
+        other : Mode,
+        last : Mode,
+    }
+
+    fn theme() -> Theme {
+        Theme {
+            name : "solarized".to_string(),
+            size : 12,
+            scale : 1.25,
+            enabled : true,
+            fonts : vec!["mono".to_string(),"sans".to_string()],
+            accent : None,
+            mode : Mode::Dark,
+            other : Mode::Custom("purple".to_string()),
+            last : Mode::Sized { width : 80 },
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let value = to_type(&theme()).unwrap();
+
+        let hash = value.to_complex().unwrap();
+        assert_eq!(hash.get("name"),Some(&Type::Text("solarized".to_string())));
+        assert_eq!(hash.get("size"),Some(&Type::Int(12)));
+        assert_eq!(hash.get("accent"),Some(&Type::None));
+        assert_eq!(hash.get("mode"),Some(&Type::Text("Dark".to_string())));
+
+        assert_eq!(from_type::<Theme>(value).unwrap(),theme());
+    }
+
+    #[test]
+    fn errors_name_the_field() {
+        let mut value = to_type(&theme()).unwrap();
+        if let Type::Complex(ref mut hash) = value {
+            hash.insert("fonts".to_string(),Type::Array(vec![Type::Text("mono".to_string()),Type::Int(3)]));
+        }
+
+        let error = from_type::<Theme>(value).unwrap_err();
+        assert_eq!(error.path(),"fonts.1");
+
+        let mut hash = HashMap::new();
+        hash.insert("name".to_string(),Type::Text("only a name".to_string()));
+        let error = from_type::<Theme>(Type::Complex(hash)).unwrap_err().under("ui.theme");
+        assert_eq!(error.path(),"ui.theme");
+        assert!(format!("{}",error).contains("size"));
+    }
+
+    #[test]
+    fn integers_out_of_range() {
+        assert_eq!(to_type(&12u64).unwrap(),Type::Int(12));
+        assert!(to_type(&(i64::MAX)).is_err());
+    }
+}
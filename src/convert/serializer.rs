@@ -0,0 +1,257 @@
+use Type;
+use super::{ConvertError,to_type};
+
+use serde::ser::{self,Serialize};
+use std::collections::HashMap;
+
+/// Serializer whose output is a `Type` tree.
+///
+/// Structs and maps become `Type::Complex`, sequences and tuples become
+/// `Type::Array`. Enums follow the externally tagged layout that serde
+/// uses by default, so `Mode::Dark` becomes `"Dark"` and
+/// `Mode::Custom(12)` becomes `{ "Custom" : 12 }`.
+pub struct Serializer;
+
+fn integer<I>(value : I) -> Result<Type,ConvertError> where I : Copy + Into<i64> {
+    //! `Type::Int` only holds an `i32`, so anything larger must fail
+    //! instead of silently wrapping.
+
+    let wide : i64 = value.into();
+    if wide < i64::from(i32::MIN) || wide > i64::from(i32::MAX) {
+        Err(ConvertError::new(format!("integer `{}` does not fit in a `Type::Int`",wide)))
+    } else {
+        Ok(Type::Int(wide as i32))
+    }
+}
+
+fn key(value : Type) -> Result<String,ConvertError> {
+    //! Only string-like keys can be used in a `Type::Complex`.
+
+    match value {
+        Type::Text(text) => Ok(text),
+        Type::Int(int) => Ok(int.to_string()),
+        Type::Switch(switch) => Ok(switch.to_string()),
+        other => Err(ConvertError::new(format!("map keys must be strings, not `{}`",other))),
+    }
+}
+
+impl ser::Serializer for Serializer {
+    type Ok = Type;
+    type Error = ConvertError;
+
+    type SerializeSeq = SerializeArray;
+    type SerializeTuple = SerializeArray;
+    type SerializeTupleStruct = SerializeArray;
+    type SerializeTupleVariant = SerializeTupleVariant;
+    type SerializeMap = SerializeComplex;
+    type SerializeStruct = SerializeComplex;
+    type SerializeStructVariant = SerializeStructVariant;
+
+    fn serialize_bool(self, value : bool) -> Result<Type,ConvertError> { Ok(Type::Switch(value)) }
+    fn serialize_i8(self, value : i8) -> Result<Type,ConvertError> { Ok(Type::Int(i32::from(value))) }
+    fn serialize_i16(self, value : i16) -> Result<Type,ConvertError> { Ok(Type::Int(i32::from(value))) }
+    fn serialize_i32(self, value : i32) -> Result<Type,ConvertError> { Ok(Type::Int(value)) }
+    fn serialize_i64(self, value : i64) -> Result<Type,ConvertError> { integer(value) }
+    fn serialize_u8(self, value : u8) -> Result<Type,ConvertError> { Ok(Type::Int(i32::from(value))) }
+    fn serialize_u16(self, value : u16) -> Result<Type,ConvertError> { Ok(Type::Int(i32::from(value))) }
+    fn serialize_u32(self, value : u32) -> Result<Type,ConvertError> { integer(value) }
+
+    fn serialize_u64(self, value : u64) -> Result<Type,ConvertError> {
+        if value > i32::MAX as u64 {
+            Err(ConvertError::new(format!("integer `{}` does not fit in a `Type::Int`",value)))
+        } else {
+            Ok(Type::Int(value as i32))
+        }
+    }
+
+    fn serialize_f32(self, value : f32) -> Result<Type,ConvertError> { Ok(Type::Float(value)) }
+    fn serialize_f64(self, value : f64) -> Result<Type,ConvertError> { Ok(Type::Float(value as f32)) }
+    fn serialize_char(self, value : char) -> Result<Type,ConvertError> { Ok(Type::Text(value.to_string())) }
+    fn serialize_str(self, value : &str) -> Result<Type,ConvertError> { Ok(Type::Text(value.to_string())) }
+
+    fn serialize_bytes(self, value : &[u8]) -> Result<Type,ConvertError> {
+        Ok(Type::Array(value.iter().map(|byte| Type::Int(i32::from(*byte))).collect()))
+    }
+
+    fn serialize_none(self) -> Result<Type,ConvertError> { Ok(Type::None) }
+
+    fn serialize_some<A>(self, value : &A) -> Result<Type,ConvertError> where A : Serialize + ?Sized {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Type,ConvertError> { Ok(Type::None) }
+    fn serialize_unit_struct(self, _ : &'static str) -> Result<Type,ConvertError> { Ok(Type::None) }
+
+    fn serialize_unit_variant(self, _ : &'static str, _ : u32, variant : &'static str) -> Result<Type,ConvertError> {
+        Ok(Type::Text(variant.to_string()))
+    }
+
+    fn serialize_newtype_struct<A>(self, _ : &'static str, value : &A) -> Result<Type,ConvertError>
+        where A : Serialize + ?Sized,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<A>(self, _ : &'static str, _ : u32, variant : &'static str, value : &A) -> Result<Type,ConvertError>
+        where A : Serialize + ?Sized,
+    {
+        let mut hash = HashMap::new();
+        hash.insert(variant.to_string(),to_type(value).map_err(|error| error.at(variant))?);
+        Ok(Type::Complex(hash))
+    }
+
+    fn serialize_seq(self, len : Option<usize>) -> Result<SerializeArray,ConvertError> {
+        Ok(SerializeArray { array : Vec::with_capacity(len.unwrap_or(0)) })
+    }
+
+    fn serialize_tuple(self, len : usize) -> Result<SerializeArray,ConvertError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _ : &'static str, len : usize) -> Result<SerializeArray,ConvertError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(self, _ : &'static str, _ : u32, variant : &'static str, len : usize) -> Result<SerializeTupleVariant,ConvertError> {
+        Ok(SerializeTupleVariant { variant, array : Vec::with_capacity(len) })
+    }
+
+    fn serialize_map(self, _ : Option<usize>) -> Result<SerializeComplex,ConvertError> {
+        Ok(SerializeComplex { hash : HashMap::new(), next_key : None })
+    }
+
+    fn serialize_struct(self, _ : &'static str, len : usize) -> Result<SerializeComplex,ConvertError> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(self, _ : &'static str, _ : u32, variant : &'static str, _ : usize) -> Result<SerializeStructVariant,ConvertError> {
+        Ok(SerializeStructVariant { variant, hash : HashMap::new() })
+    }
+}
+
+pub struct SerializeArray {
+    array : Vec<Type>,
+}
+
+impl ser::SerializeSeq for SerializeArray {
+    type Ok = Type;
+    type Error = ConvertError;
+
+    fn serialize_element<A>(&mut self, value : &A) -> Result<(),ConvertError> where A : Serialize + ?Sized {
+        let index = self.array.len().to_string();
+        self.array.push(to_type(value).map_err(|error| error.at(&index))?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Type,ConvertError> { Ok(Type::Array(self.array)) }
+}
+
+impl ser::SerializeTuple for SerializeArray {
+    type Ok = Type;
+    type Error = ConvertError;
+
+    fn serialize_element<A>(&mut self, value : &A) -> Result<(),ConvertError> where A : Serialize + ?Sized {
+        ser::SerializeSeq::serialize_element(self,value)
+    }
+
+    fn end(self) -> Result<Type,ConvertError> { ser::SerializeSeq::end(self) }
+}
+
+impl ser::SerializeTupleStruct for SerializeArray {
+    type Ok = Type;
+    type Error = ConvertError;
+
+    fn serialize_field<A>(&mut self, value : &A) -> Result<(),ConvertError> where A : Serialize + ?Sized {
+        ser::SerializeSeq::serialize_element(self,value)
+    }
+
+    fn end(self) -> Result<Type,ConvertError> { ser::SerializeSeq::end(self) }
+}
+
+pub struct SerializeTupleVariant {
+    variant : &'static str,
+    array : Vec<Type>,
+}
+
+impl ser::SerializeTupleVariant for SerializeTupleVariant {
+    type Ok = Type;
+    type Error = ConvertError;
+
+    fn serialize_field<A>(&mut self, value : &A) -> Result<(),ConvertError> where A : Serialize + ?Sized {
+        let index = self.array.len().to_string();
+        self.array.push(to_type(value).map_err(|error| error.at(&index).at(self.variant))?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Type,ConvertError> {
+        let mut hash = HashMap::new();
+        hash.insert(self.variant.to_string(),Type::Array(self.array));
+        Ok(Type::Complex(hash))
+    }
+}
+
+pub struct SerializeComplex {
+    hash : HashMap<String,Type>,
+    next_key : Option<String>,
+}
+
+impl ser::SerializeMap for SerializeComplex {
+    type Ok = Type;
+    type Error = ConvertError;
+
+    fn serialize_key<A>(&mut self, value : &A) -> Result<(),ConvertError> where A : Serialize + ?Sized {
+        self.next_key = Some(key(to_type(value)?)?);
+        Ok(())
+    }
+
+    fn serialize_value<A>(&mut self, value : &A) -> Result<(),ConvertError> where A : Serialize + ?Sized {
+        match self.next_key.take() {
+            None => Err(ConvertError::new("`serialize_value` called before `serialize_key`")),
+            Some(key) => {
+                let value = to_type(value).map_err(|error| error.at(&key))?;
+                self.hash.insert(key,value);
+                Ok(())
+            }
+        }
+    }
+
+    fn end(self) -> Result<Type,ConvertError> { Ok(Type::Complex(self.hash)) }
+}
+
+impl ser::SerializeStruct for SerializeComplex {
+    type Ok = Type;
+    type Error = ConvertError;
+
+    fn serialize_field<A>(&mut self, key : &'static str, value : &A) -> Result<(),ConvertError>
+        where A : Serialize + ?Sized,
+    {
+        self.hash.insert(key.to_string(),to_type(value).map_err(|error| error.at(key))?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Type,ConvertError> { Ok(Type::Complex(self.hash)) }
+}
+
+pub struct SerializeStructVariant {
+    variant : &'static str,
+    hash : HashMap<String,Type>,
+}
+
+impl ser::SerializeStructVariant for SerializeStructVariant {
+    type Ok = Type;
+    type Error = ConvertError;
+
+    fn serialize_field<A>(&mut self, key : &'static str, value : &A) -> Result<(),ConvertError>
+        where A : Serialize + ?Sized,
+    {
+        let value = to_type(value).map_err(|error| error.at(key).at(self.variant))?;
+        self.hash.insert(key.to_string(),value);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Type,ConvertError> {
+        let mut hash = HashMap::new();
+        hash.insert(self.variant.to_string(),Type::Complex(self.hash));
+        Ok(Type::Complex(hash))
+    }
+}
@@ -0,0 +1,66 @@
+use std::error;
+use std::fmt;
+use std::path::PathBuf;
+
+/// Errors that a program may want to handle differently than the rest
+///
+/// Functions still return a `failure::Error`, these can be found with
+/// `downcast_ref`.
+///
+/// ```rust
+/// # extern crate settingsfile;
+/// use settingsfile::{Settings,SettingsError};
+/// use settingsfile::EmptyConfig; // dumb config for examples and testing
+///
+/// let mut settings = Settings::new(EmptyConfig{});
+/// settings.set_policy_value("update.channel","stable");
+///
+/// let error = settings.set_value("update.channel","beta").unwrap_err();
+/// match error.downcast_ref::<SettingsError>() {
+///     Some(SettingsError::Locked { key }) => println!("{} is set by your administrator",key),
+///     _ => println!("{}",error),
+/// }
+/// ```
+#[derive(Debug,Clone,PartialEq)]
+pub enum SettingsError {
+    /// The key is set by a policy and can't be changed.
+    Locked { key : String },
+    /// The local file sets keys that aren't in the allowlist, only
+    /// returned in strict mode.
+    LocalNotAllowed { keys : Vec<String> },
+    /// The local file hasn't been trusted, or was changed after it was.
+    LocalNotTrusted { path : PathBuf },
+    /// Another program held the lock on the file for too long.
+    LockTimeout { path : PathBuf },
+    /// The keys were changed in the file and by us since it was loaded,
+    /// from `Settings::save_merged`.
+    Conflict { keys : Vec<String> },
+    /// The file was changed by something else since it was loaded,
+    /// use `save_force` to write over it anyway.
+    ConcurrentModification { path : PathBuf },
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SettingsError::Locked { ref key } => write!(f,"'{}' is locked by policy",key),
+            SettingsError::LocalNotAllowed { ref keys } => {
+                write!(f,"The local file can't set {}",keys.join(", "))
+            },
+            SettingsError::LocalNotTrusted { ref path } => {
+                write!(f,"{} isn't trusted, it needs to be trusted before it is used",path.display())
+            },
+            SettingsError::LockTimeout { ref path } => {
+                write!(f,"Timed out waiting for the lock on {}",path.display())
+            },
+            SettingsError::Conflict { ref keys } => {
+                write!(f,"{} changed in the file since it was loaded",keys.join(", "))
+            },
+            SettingsError::ConcurrentModification { ref path } => {
+                write!(f,"{} was changed since it was loaded",path.display())
+            },
+        }
+    }
+}
+
+impl error::Error for SettingsError { }
@@ -0,0 +1,113 @@
+use Format;
+use Location;
+use SettingsRaw;
+use formats::locate_key;
+
+use failure::Error;
+use std::marker::PhantomData;
+
+/// The serde library behind one of the built in formats, used by
+/// [SerdeFormat](struct.SerdeFormat.html) to read and write the files.
+pub trait Codec {
+    /// The file extension, without the `.`
+    fn extension() -> &'static str;
+
+    fn from_str(buffer : &str) -> Result<SettingsRaw,Error>;
+    fn to_string(object : &SettingsRaw) -> Result<String,Error>;
+
+    fn locate(buffer : &str, key_path : &str) -> Option<(usize,usize)> {
+        //! Finds where `key_path` is written, works for all the formats
+        //! that write keys like `key :` or `"key":`.
+
+        locate_key(buffer,key_path)
+    }
+}
+
+/// A [Format](../trait.Format.html) that reads and writes its files with
+/// a [Codec](trait.Codec.html). All the built in formats are one of these,
+/// so `Json` is a `SerdeFormat<JsonCodec>`.
+#[derive(Clone,Debug)]
+pub struct SerdeFormat<C> {
+    folder : String,
+    filename : String,
+    location : Location,
+    codec : PhantomData<C>,
+}
+
+impl<C> SerdeFormat<C> where C : Codec {
+    pub fn new<S1,S2>(folder : S1, filename : S2) -> SerdeFormat<C>
+        where S1 : ToString, S2 : ToString,
+    {
+        //! Creates the format for `~/{folder}/{filename}.{extension}`
+
+        SerdeFormat {
+            folder : folder.to_string(),
+            filename : filename.to_string(),
+            location : Location::Home,
+            codec : PhantomData,
+        }
+    }
+
+    pub fn with_location(mut self, location : Location) -> SerdeFormat<C> {
+        //! Keeps the files in `location` instead of the home directory.
+
+        self.location = location;
+        self
+    }
+}
+
+impl<C> Format for SerdeFormat<C> where C : Codec + Clone {
+    fn filename(&self) -> String { self.filename.clone() }
+    fn folder(&self) -> String { self.folder.clone() }
+    fn location(&self) -> Location { self.location }
+    fn extension(&self) -> Option<String> { Some(C::extension().to_string()) }
+
+    fn from_str(&self,buffer:&str) -> Result<SettingsRaw,Error> {
+        C::from_str(buffer)
+    }
+
+    fn to_string(&self,object:&SettingsRaw) -> Result<String,Error> {
+        C::to_string(object)
+    }
+
+    fn locate(&self,buffer:&str,key_path:&str) -> Option<(usize,usize)> {
+        C::locate(buffer,key_path)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use Format;
+    use Type;
+    use SettingsRaw;
+    use super::{Codec,SerdeFormat};
+    use std::collections::HashMap;
+
+    pub fn sample() -> SettingsRaw {
+        //! Settings with one of every `Type`, used to test all the formats.
+
+        let mut user = HashMap::new();
+        user.insert("name".to_string(),Type::Text("snsvrno".to_string()));
+        user.insert("age".to_string(),Type::Int(30));
+        user.insert("paths".to_string(),Type::Array(vec![Type::Text("~/bin".to_string()),Type::Text("~/.cargo/bin".to_string())]));
+        user.insert("email".to_string(),Type::Unset);
+
+        let mut raw = HashMap::new();
+        raw.insert("user".to_string(),Type::Complex(user));
+        raw.insert("scale".to_string(),Type::Float(1.5));
+        raw.insert("enabled".to_string(),Type::Switch(true));
+        raw
+    }
+
+    pub fn round_trip<C>(raw : &SettingsRaw) -> String where C : Codec + Clone {
+        //! Writes and reads back `raw` with the codec, checking that
+        //! nothing changed, and returns the text that was written.
+
+        let format : SerdeFormat<C> = SerdeFormat::new(".config/app","settings");
+        assert_eq!(format.get_filename(),format!("settings.{}",C::extension()));
+
+        let text = format.to_string(raw).unwrap();
+        assert_eq!(&format.from_str(&text).unwrap(),raw);
+        text
+    }
+}
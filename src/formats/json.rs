@@ -0,0 +1,40 @@
+use SettingsRaw;
+use formats::{Codec,SerdeFormat};
+
+use serde_json;
+use failure::Error;
+
+/// [JSON](https://www.json.org/) format, using
+/// [serde_json](https://crates.io/crates/serde_json).
+///
+/// Requires the `json` feature.
+pub type Json = SerdeFormat<JsonCodec>;
+
+/// The [Codec](trait.Codec.html) for [Json](type.Json.html).
+#[derive(Clone,Copy,Debug)]
+pub struct JsonCodec;
+
+impl Codec for JsonCodec {
+    fn extension() -> &'static str { "json" }
+
+    fn from_str(buffer : &str) -> Result<SettingsRaw,Error> {
+        Ok(serde_json::from_str(buffer)?)
+    }
+
+    fn to_string(object : &SettingsRaw) -> Result<String,Error> {
+        Ok(serde_json::to_string_pretty(object)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::JsonCodec;
+    use formats::codec::tests::{round_trip,sample};
+
+    #[test]
+    fn round_trip_json() {
+        let text = round_trip::<JsonCodec>(&sample());
+        assert!(text.contains("\"name\": \"snsvrno\""));
+        assert!(text.contains("\"$unset\": true"));
+    }
+}
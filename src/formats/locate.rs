@@ -0,0 +1,143 @@
+//! Helpers to find where a key is written in a file, used by
+//! [Format::locate](../trait.Format.html#method.locate) so values
+//! can be traced back to the line that set them.
+//!
+//! These are simple text searches and not full parsers, they are meant
+//! for pointing a user in the right direction.
+
+fn position(buffer : &str, offset : usize) -> (usize,usize) {
+    //! Converts a byte offset into a line and column, both starting at 1.
+
+    let before = &buffer[..offset];
+    let line = before.matches('\n').count() + 1;
+    let column = match before.rfind('\n') {
+        Some(newline) => before[newline+1..].chars().count() + 1,
+        None => before.chars().count() + 1,
+    };
+    (line,column)
+}
+
+fn is_key_char(c : char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-'
+}
+
+fn find_key_from(buffer : &str, key : &str, start : usize) -> Option<usize> {
+    //! Finds `key` used as a map key (`"key" :`, `key:`, `'key' :`)
+    //! somewhere after `start`, returns the offset of the key.
+
+    if key.is_empty() { return None; }
+
+    let mut search = start;
+    while let Some(found) = buffer[search..].find(key) {
+        let begin = search + found;
+        let end = begin + key.len();
+        search = end;
+
+        // the key might be quoted
+        let (begin,end) = {
+            let before = buffer[..begin].chars().next_back();
+            let after = buffer[end..].chars().next();
+            match (before,after) {
+                (Some(q1),Some(q2)) if q1 == q2 && (q1 == '"' || q1 == '\'') => (begin - 1,end + 1),
+                _ => (begin,end),
+            }
+        };
+
+        // has to be a whole word
+        if let Some(c) = buffer[..begin].chars().next_back() {
+            if is_key_char(c) { continue; }
+        }
+        if let Some(c) = buffer[end..].chars().next() {
+            if is_key_char(c) { continue; }
+        }
+
+        // and has to be followed by the key / value separator
+        if buffer[end..].trim_start_matches([' ','\t']).starts_with(':') {
+            return Some(begin);
+        }
+    }
+
+    None
+}
+
+pub fn locate_key(buffer : &str, key_path : &str) -> Option<(usize,usize)> {
+    //! Finds a nested key in formats that use `key : value` (JSON, YAML, RON).
+    //!
+    //! Each part of the `key_path` is searched for after the previous part,
+    //! returns the line and column of the last part.
+
+    let mut offset = 0;
+    let mut found = None;
+    for part in key_path.split('.') {
+        let begin = find_key_from(buffer,part,offset)?;
+        offset = begin + part.len();
+        found = Some(begin);
+    }
+
+    found.map(|begin| position(buffer,begin))
+}
+
+pub fn locate_toml_key(buffer : &str, key_path : &str) -> Option<(usize,usize)> {
+    //! Finds a key in TOML, which can either be inside of a `[table]`
+    //! or written as a dotted key.
+
+    let parts : Vec<&str> = key_path.split('.').collect();
+
+    // walking through the lines and remembering which table we are in
+    let mut table : Vec<String> = Vec::new();
+    let mut offset = 0;
+
+    for line in buffer.split('\n') {
+        let trimmed = line.trim_start();
+        let indent = line.len() - trimmed.len();
+
+        if trimmed.starts_with('[') {
+            let name = trimmed.trim_start_matches('[');
+            if let Some(end) = name.find(']') {
+                table = name[..end].split('.').map(|part| part.trim().trim_matches('"').to_string()).collect();
+                if table.iter().map(|s| s.as_str()).eq(parts.iter().cloned()) {
+                    return Some(position(buffer,offset + indent));
+                }
+            }
+        } else if let Some(equals) = trimmed.find('=') {
+            let mut key : Vec<String> = table.clone();
+            for part in trimmed[..equals].split('.') {
+                key.push(part.trim().trim_matches('"').to_string());
+            }
+            if key.iter().map(|s| s.as_str()).eq(parts.iter().cloned()) {
+                return Some(position(buffer,offset + indent));
+            }
+        }
+
+        offset += line.len() + 1;
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{locate_key,locate_toml_key};
+
+    #[test]
+    fn json_like() {
+        let json = "{\n  \"username\" : \"other\",\n  \"user\" : {\n    \"name\" : \"snsvrno\"\n  }\n}";
+        assert_eq!(locate_key(json,"user.name"),Some((4,5)));
+        assert_eq!(locate_key(json,"user"),Some((3,3)));
+        assert_eq!(locate_key(json,"user.email"),None);
+
+        let yaml = "user:\n  name: snsvrno\n  email: someone\n";
+        assert_eq!(locate_key(yaml,"user.email"),Some((3,3)));
+    }
+
+    #[test]
+    fn toml() {
+        let toml = "scale = 1.5\nuser.age = 30\n\n[user]\nname = \"snsvrno\"\n\n[user.paths]\n  bin = \"~/bin\"\n";
+        assert_eq!(locate_toml_key(toml,"scale"),Some((1,1)));
+        assert_eq!(locate_toml_key(toml,"user.age"),Some((2,1)));
+        assert_eq!(locate_toml_key(toml,"user"),Some((4,1)));
+        assert_eq!(locate_toml_key(toml,"user.name"),Some((5,1)));
+        assert_eq!(locate_toml_key(toml,"user.paths.bin"),Some((8,3)));
+        assert_eq!(locate_toml_key(toml,"user.email"),None);
+    }
+}
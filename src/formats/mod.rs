@@ -0,0 +1,47 @@
+//! Ready made [Format](../trait.Format.html) implementations for the most
+//! common file formats.
+//!
+//! Each format is behind a cargo feature of the same name so you only pull
+//! in the serde libraries you actually use.
+//!
+//! ```toml
+//! [dependencies]
+//! settingsfile = { version = "0.3", features = ["toml"] }
+//! ```
+//!
+//! This module also has the helpers the formats use to find where a key is
+//! written in a file, which can be used for custom formats too.
+//!
+//! All the formats are created with the folder and the filename (without
+//! the extension) of the configuration, and can be used anywhere a
+//! `Format` is expected.
+//!
+//! Every format writes a [Type::Unset](../enum.Type.html#variant.Unset) as a
+//! map with a single `"$unset" : true` entry, and reads that map back as a
+//! `Type::Unset`.
+//!
+//! ```rust,ignore
+//! use settingsfile::Settings;
+//! use settingsfile::formats::Toml;
+//!
+//! // ~/.config/app/settings.toml
+//! let settings = Settings::new_and_load(Toml::new(".config/app","settings"));
+//! ```
+
+mod locate;
+pub use self::locate::{locate_key,locate_toml_key};
+
+mod codec;
+pub use self::codec::{Codec,SerdeFormat};
+
+#[cfg(feature = "json")] mod json;
+#[cfg(feature = "json")] pub use self::json::{Json,JsonCodec};
+
+#[cfg(feature = "toml")] mod toml;
+#[cfg(feature = "toml")] pub use self::toml::{Toml,TomlCodec};
+
+#[cfg(feature = "yaml")] mod yaml;
+#[cfg(feature = "yaml")] pub use self::yaml::{Yaml,YamlCodec};
+
+#[cfg(any(test, feature = "ron"))] mod ron;
+#[cfg(any(test, feature = "ron"))] pub use self::ron::{Ron,RonCodec};
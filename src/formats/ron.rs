@@ -0,0 +1,43 @@
+use SettingsRaw;
+use formats::{Codec,SerdeFormat};
+
+use ron;
+use failure::Error;
+
+/// [RON](https://github.com/ron-rs/ron) format, using
+/// [ron](https://crates.io/crates/ron).
+///
+/// Requires the `ron` feature.
+pub type Ron = SerdeFormat<RonCodec>;
+
+/// The [Codec](trait.Codec.html) for [Ron](type.Ron.html).
+#[derive(Clone,Copy,Debug)]
+pub struct RonCodec;
+
+impl Codec for RonCodec {
+    fn extension() -> &'static str { "ron" }
+
+    fn from_str(buffer : &str) -> Result<SettingsRaw,Error> {
+        Ok(ron::de::from_str(buffer)?)
+    }
+
+    fn to_string(object : &SettingsRaw) -> Result<String,Error> {
+        Ok(ron::ser::to_string_pretty(object,ron::ser::PrettyConfig::default())?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use Type;
+    use super::RonCodec;
+    use formats::codec::tests::{round_trip,sample};
+
+    #[test]
+    fn round_trip_ron() {
+        let mut raw = sample();
+        raw.insert("nested".to_string(),Type::Array(vec![Type::Array(vec![Type::Int(1)]),Type::Text("two".to_string())]));
+
+        let text = round_trip::<RonCodec>(&raw);
+        assert!(text.contains("\"name\": \"snsvrno\""));
+    }
+}
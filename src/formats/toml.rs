@@ -0,0 +1,213 @@
+use SettingsRaw;
+use Type;
+use UNSET_KEY;
+use formats::{Codec,SerdeFormat,locate_toml_key};
+
+use toml;
+use failure::Error;
+
+/// [TOML](https://github.com/toml-lang/toml) format, using
+/// [toml-rs](https://crates.io/crates/toml).
+///
+/// Requires the `toml` feature.
+///
+/// TOML doesn't have a null value, so any `Type::None` is left out
+/// of the file when saving. TOML also requires all the plain values
+/// of a table to be written before any of the sub tables, and toml-rs
+/// can only write tables in an array when everything in it is a table,
+/// so other arrays with tables are written inline. Both are handled
+/// here so any `Settings` can be saved.
+pub type Toml = SerdeFormat<TomlCodec>;
+
+/// The [Codec](trait.Codec.html) for [Toml](type.Toml.html).
+#[derive(Clone,Copy,Debug)]
+pub struct TomlCodec;
+
+fn without_none(value : Type) -> Option<Type> {
+    //! Removes all the `Type::None` from the tree, returns `None`
+    //! if the value itself is a `Type::None`.
+
+    match value {
+        Type::None => None,
+        Type::Array(array) => Some(Type::Array(array.into_iter().filter_map(without_none).collect())),
+        Type::Complex(hash) => Some(Type::Complex(hash.into_iter()
+            .filter_map(|(key,value)| without_none(value).map(|value| (key,value)))
+            .collect())),
+        other => Some(other),
+    }
+}
+
+fn is_table(value : &Type) -> bool {
+    //! `Type::Unset` is written as a table too.
+
+    matches!(*value,Type::Complex(_) | Type::Unset)
+}
+
+fn has_table(value : &Type) -> bool {
+    match *value {
+        Type::Array(ref array) => array.iter().any(has_table),
+        ref value => is_table(value),
+    }
+}
+
+fn has_text(value : &Type, text : &str) -> bool {
+    match *value {
+        Type::Text(ref inner) => inner.contains(text),
+        Type::Array(ref array) => array.iter().any(|value| has_text(value,text)),
+        Type::Complex(ref hash) => hash.values().any(|value| has_text(value,text)),
+        _ => false,
+    }
+}
+
+fn with_markers(value : Type, marker : &str, inline : &mut Vec<String>) -> Type {
+    //! Replaces every array that toml-rs can't write with a `marker` text,
+    //! and keeps the array written as inline TOML in `inline` so it can
+    //! be put back in place of the marker.
+
+    match value {
+        Type::Complex(hash) => Type::Complex(hash.into_iter()
+            .map(|(key,value)| (key,with_markers(value,marker,inline)))
+            .collect()),
+        Type::Array(array) => {
+            if array.iter().all(is_table) {
+                Type::Array(array.into_iter().map(|value| with_markers(value,marker,inline)).collect())
+            } else if array.iter().any(has_table) {
+                inline.push(inline_value(&Type::Array(array)));
+                Type::Text(format!("{}{}",marker,inline.len()-1))
+            } else {
+                Type::Array(array)
+            }
+        },
+        other => other,
+    }
+}
+
+fn inline_value(value : &Type) -> String {
+    //! Writes the value as inline TOML.
+
+    match *value {
+        Type::Text(ref text) => quote(text),
+        Type::Switch(switch) => switch.to_string(),
+        Type::Int(int) => int.to_string(),
+        Type::Float(float) => {
+            if float.is_nan() { "nan".to_string() }
+            else if float.is_infinite() { if float > 0.0 { "inf".to_string() } else { "-inf".to_string() } }
+            else {
+                let mut text = float.to_string();
+                if !text.contains('.') { text.push_str(".0"); }
+                text
+            }
+        },
+        Type::Array(ref array) => {
+            let values : Vec<String> = array.iter().map(inline_value).collect();
+            format!("[{}]",values.join(", "))
+        },
+        Type::Complex(ref hash) => {
+            let mut keys : Vec<&String> = hash.keys().collect();
+            keys.sort();
+            let values : Vec<String> = keys.iter().map(|key| format!("{} = {}",bare_key(key),inline_value(&hash[*key]))).collect();
+            if values.is_empty() { "{}".to_string() } else { format!("{{ {} }}",values.join(", ")) }
+        },
+        Type::Unset => format!("{{ {} = true }}",bare_key(UNSET_KEY)),
+        // already removed by `without_none`
+        Type::None => quote(""),
+    }
+}
+
+fn bare_key(key : &str) -> String {
+    if !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        key.to_string()
+    } else {
+        quote(key)
+    }
+}
+
+fn quote(text : &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04X}",c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+impl Codec for TomlCodec {
+    fn extension() -> &'static str { "toml" }
+
+    fn from_str(buffer : &str) -> Result<SettingsRaw,Error> {
+        Ok(toml::from_str(buffer)?)
+    }
+
+    fn to_string(object : &SettingsRaw) -> Result<String,Error> {
+        let object = without_none(Type::Complex(object.clone())).unwrap_or(Type::Complex(SettingsRaw::new()));
+
+        let mut marker = "settingsfile-inline-".to_string();
+        while has_text(&object,&marker) { marker.push('-'); }
+        let mut inline = Vec::new();
+        let object = with_markers(object,&marker,&mut inline);
+
+        // going through a `toml::Value` first because it orders the
+        // tables after the values when it is serialized.
+        let value = toml::Value::try_from(object)?;
+        let mut text = toml::to_string_pretty(&value)?;
+        for (index,array) in inline.iter().enumerate() {
+            text = text.replacen(&format!("'{}{}'",marker,index),array,1);
+        }
+        Ok(text)
+    }
+
+    fn locate(buffer : &str, key_path : &str) -> Option<(usize,usize)> {
+        locate_toml_key(buffer,key_path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use Type;
+    use formats::Codec;
+    use super::TomlCodec;
+    use formats::codec::tests::{round_trip,sample};
+    use std::collections::HashMap;
+
+    #[test]
+    fn round_trip_toml() {
+        let mut raw = sample();
+        raw.insert("zzz_after_tables".to_string(),Type::Int(1));
+        let text = round_trip::<TomlCodec>(&raw);
+        assert!(text.contains("zzz_after_tables = 1\n\n[user]"));
+
+        // `Type::None` is left out
+        raw.insert("nothing".to_string(),Type::None);
+        assert_eq!(TomlCodec::to_string(&raw).unwrap(),text);
+    }
+
+    #[test]
+    fn mixed_arrays() {
+        let mut point = HashMap::new();
+        point.insert("x".to_string(),Type::Int(1));
+        point.insert("label".to_string(),Type::Text("a \"quoted\"\nlabel".to_string()));
+
+        let mut raw = sample();
+        raw.insert("mixed".to_string(),Type::Array(vec![Type::Int(1),Type::Text("two".to_string()),Type::Float(3.0),Type::Switch(true)]));
+        raw.insert("points".to_string(),Type::Array(vec![
+            Type::Complex(point.clone()),
+            Type::Int(2),
+            Type::Array(vec![Type::Complex(point.clone()),Type::Unset]),
+        ]));
+        raw.insert("settingsfile-inline-0".to_string(),Type::Text("settingsfile-inline-0".to_string()));
+
+        let mut table = HashMap::new();
+        table.insert("inner".to_string(),Type::Array(vec![Type::Int(1),Type::Complex(point)]));
+        raw.insert("tables".to_string(),Type::Array(vec![Type::Complex(table)]));
+
+        round_trip::<TomlCodec>(&raw);
+    }
+}
@@ -0,0 +1,44 @@
+use SettingsRaw;
+use formats::{Codec,SerdeFormat};
+
+use serde_yaml;
+use failure::Error;
+
+/// [YAML](https://yaml.org/) format, using
+/// [serde_yaml](https://crates.io/crates/serde_yaml).
+///
+/// Requires the `yaml` feature.
+pub type Yaml = SerdeFormat<YamlCodec>;
+
+/// The [Codec](trait.Codec.html) for [Yaml](type.Yaml.html).
+#[derive(Clone,Copy,Debug)]
+pub struct YamlCodec;
+
+impl Codec for YamlCodec {
+    fn extension() -> &'static str { "yaml" }
+
+    fn from_str(buffer : &str) -> Result<SettingsRaw,Error> {
+        Ok(serde_yaml::from_str(buffer)?)
+    }
+
+    fn to_string(object : &SettingsRaw) -> Result<String,Error> {
+        Ok(serde_yaml::to_string(object)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use Type;
+    use super::YamlCodec;
+    use formats::codec::tests::{round_trip,sample};
+
+    #[test]
+    fn round_trip_yaml() {
+        let mut raw = sample();
+        raw.insert("nothing".to_string(),Type::None);
+
+        let text = round_trip::<YamlCodec>(&raw);
+        assert!(text.contains("name: snsvrno"));
+        assert!(text.contains("nothing: ~"));
+    }
+}
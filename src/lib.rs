@@ -16,6 +16,13 @@
 #[macro_use] extern crate log;
 extern crate serde;
 extern crate dirs;
+extern crate sha2;
+#[cfg(feature = "json")] extern crate serde_json;
+#[cfg(feature = "toml")] extern crate toml;
+#[cfg(feature = "yaml")] extern crate serde_yaml;
+#[cfg(any(test, feature = "ron"))] extern crate ron;
+#[cfg(feature = "watch")] extern crate notify;
+#[cfg(test)] extern crate tempfile;
 
 // public traits
 mod traits;
@@ -24,7 +31,33 @@ pub use traits::format::Format;
 pub use traits::format::SettingsRaw;
 // public structs
 mod structs;
-pub use structs::settings::Settings;
+pub use structs::settings::{Settings,UpdateGuard};
+pub use structs::subscribe::Subscription;
 pub use structs::shadowsettings::ShadowSettings;
-pub use structs::types::Type;
-pub use structs::empty::EmptyConfig;
\ No newline at end of file
+pub use structs::layered::{LayeredSettings,Layer};
+pub use structs::types::{Type,UNSET_KEY};
+pub use structs::empty::EmptyConfig;
+pub use structs::registry::FormatRegistry;
+pub use structs::origin::Origin;
+pub use structs::merge::{MergeOptions,ArrayMerge};
+pub use structs::env::EnvSource;
+pub use structs::overrides::Overrides;
+pub use structs::trust::TrustStore;
+pub use structs::discovery::LocalDiscovery;
+pub use structs::state::StateFile;
+pub use structs::lock::FileLock;
+#[cfg(feature = "watch")]
+pub use structs::watch::{SettingsWatcher,WatchEvent};
+// errors
+mod error;
+pub use error::SettingsError;
+// built in formats
+pub mod formats;
+// finding where the files are
+mod paths;
+pub use paths::{Location,XdgDir};
+// writing them safely
+mod atomic;
+// conversions
+mod convert;
+pub use convert::{to_type,from_type,ConvertError};
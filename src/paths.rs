@@ -0,0 +1,259 @@
+//! Finding the directories that configuration files live in.
+
+use Format;
+
+use failure::Error;
+use dirs;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path,PathBuf};
+use std::sync::OnceLock;
+
+/// Where the files of a [Format](trait.Format.html) are kept
+///
+/// Set with `Format::location`.
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Default)]
+pub enum Location {
+    /// In `folder()` inside the home directory, like `~/.app`. State,
+    /// cache and data files are kept in the same folder.
+    #[default]
+    Home,
+    /// Follows the [XDG Base Directory](https://specifications.freedesktop.org/basedir-spec/latest/)
+    /// specification, so the configuration is in `$XDG_CONFIG_HOME/{app_name}`
+    /// (`~/.config/{app_name}`) and state, cache and data files have their
+    /// own folders. A file in the `Home` location is moved over the first
+    /// time it is loaded.
+    Xdg,
+    /// Everything is kept in the folder of the executable, for programs
+    /// that run from a USB stick or a CI cache. Usually turned on by a
+    /// marker file, see `Format::portable_marker`.
+    Portable,
+}
+
+/// The kinds of XDG base directories.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum XdgDir {
+    Config,
+    State,
+    Cache,
+    Data,
+}
+
+impl XdgDir {
+    pub fn var(&self) -> &'static str {
+        //! The environment variable that sets the directory.
+
+        match *self {
+            XdgDir::Config => "XDG_CONFIG_HOME",
+            XdgDir::State => "XDG_STATE_HOME",
+            XdgDir::Cache => "XDG_CACHE_HOME",
+            XdgDir::Data => "XDG_DATA_HOME",
+        }
+    }
+
+    pub fn fallback(&self) -> &'static str {
+        //! The directory inside home used when the variable isn't set.
+
+        match *self {
+            XdgDir::Config => ".config",
+            XdgDir::State => ".local/state",
+            XdgDir::Cache => ".cache",
+            XdgDir::Data => ".local/share",
+        }
+    }
+}
+
+pub fn xdg_dir(kind : XdgDir, value : Option<OsString>, home : &Path) -> PathBuf {
+    //! The XDG base directory of `kind`, from the `value` of its variable
+    //! or inside `home`. Relative paths are ignored, as the specification says.
+
+    match value {
+        Some(ref value) if Path::new(value).is_absolute() => PathBuf::from(value),
+        _ => home.join(kind.fallback()),
+    }
+}
+
+pub fn migrate_legacy<F>(format : &F) -> Result<bool,Error> where F : Format + ?Sized {
+    //! Moves the file in the `Home` location to the XDG location on the
+    //! first load, if there is one to move.
+
+    match format.get_legacy_path_and_file()? {
+        Some(legacy) => migrate(&legacy,&format.get_path_and_file()?),
+        None => Ok(false),
+    }
+}
+
+pub fn migrate(legacy : &Path, path : &Path) -> Result<bool,Error> {
+    //! Moves the file at `legacy` to `path` if only `legacy` exists,
+    //! returns `true` if it was moved.
+
+    if path.exists() || !legacy.is_file() {
+        return Ok(false);
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    info!("Moving {} to {}",legacy.display(),path.display());
+    // rename doesn't work between filesystems
+    if fs::rename(legacy,path).is_err() {
+        fs::copy(legacy,path)?;
+        fs::remove_file(legacy)?;
+    }
+    Ok(true)
+}
+
+pub fn home_dir() -> Result<PathBuf,Error> {
+    //! The home directory, the default `Format::base_dir`.
+
+    match dirs::home_dir() {
+        Some(dir) => Ok(dir),
+        None => Err(format_err!("Can't find the home directory")),
+    }
+}
+
+pub fn current_dir() -> Result<PathBuf,Error> {
+    //! The current directory, the default `Format::local_dir`.
+
+    match env::current_dir() {
+        Ok(dir) => Ok(dir),
+        Err(error) => Err(format_err!("Can't use the current directory: {}",error)),
+    }
+}
+
+pub fn exe_dir() -> Result<PathBuf,Error> {
+    //! The folder of the executable, the default `Format::exe_dir`. Only
+    //! looked up the first time.
+
+    static EXE_DIR : OnceLock<Result<PathBuf,String>> = OnceLock::new();
+
+    let dir = EXE_DIR.get_or_init(|| {
+        let exe = env::current_exe().map_err(|error| error.to_string())?;
+        match exe.parent() {
+            Some(dir) => Ok(dir.to_path_buf()),
+            None => Err(format!("{} isn't in a folder",exe.display())),
+        }
+    });
+    match *dir {
+        Ok(ref dir) => Ok(dir.clone()),
+        Err(ref error) => Err(format_err!("{}",error)),
+    }
+}
+
+pub fn is_portable(exe_dir : &Path, names : &[String]) -> bool {
+    //! Checks if one of the files in `names` is in `exe_dir`.
+    //!
+    //! The folder of the executable is only looked at the first time, so
+    //! the program doesn't stop being portable while it runs. Any other
+    //! folder, like one from a changed `Format::exe_dir`, is looked at
+    //! every time.
+
+    static PORTABLE : OnceLock<bool> = OnceLock::new();
+
+    let found = || names.iter().any(|name| exe_dir.join(name).exists());
+    match self::exe_dir() {
+        Ok(ref dir) if dir == exe_dir => *PORTABLE.get_or_init(found),
+        _ => found(),
+    }
+}
+
+pub fn system_config_dirs(xdg_config_dirs : Option<OsString>) -> Vec<PathBuf> {
+    //! The system wide configuration directories, the most important first.
+    //!
+    //! These are the directories in `$XDG_CONFIG_DIRS` (or `/etc/xdg` if it
+    //! isn't set) followed by `/etc`. Relative paths in `$XDG_CONFIG_DIRS`
+    //! are ignored, as the specification says.
+
+    let mut dirs : Vec<PathBuf> = match xdg_config_dirs {
+        Some(ref value) if !value.is_empty() => env::split_paths(value).filter(|dir| dir.is_absolute()).collect(),
+        _ => vec![PathBuf::from("/etc/xdg")],
+    };
+    dirs.push(PathBuf::from("/etc"));
+    dirs
+}
+
+pub fn app_name(folder : &str) -> String {
+    //! The name of the application from the configuration folder, the
+    //! last part of the folder without any leading `.`, so both
+    //! `.config/app` and `.app` are `app`.
+
+    folder.split(['/','\\'])
+        .rfind(|part| !part.is_empty())
+        .unwrap_or("")
+        .trim_start_matches('.')
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{system_config_dirs,app_name,xdg_dir,migrate,exe_dir,is_portable,XdgDir};
+    use std::ffi::OsString;
+    use std::fs;
+    use std::path::{Path,PathBuf};
+
+    #[test]
+    fn system_dirs() {
+        assert_eq!(system_config_dirs(None),vec![PathBuf::from("/etc/xdg"),PathBuf::from("/etc")]);
+        assert_eq!(system_config_dirs(Some(OsString::from(""))),vec![PathBuf::from("/etc/xdg"),PathBuf::from("/etc")]);
+        assert_eq!(system_config_dirs(Some(OsString::from("/opt/config:relative:/usr/local/etc/xdg"))),vec![
+            PathBuf::from("/opt/config"),
+            PathBuf::from("/usr/local/etc/xdg"),
+            PathBuf::from("/etc"),
+        ]);
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(app_name(".config/app"),"app");
+        assert_eq!(app_name(".myapp"),"myapp");
+        assert_eq!(app_name("apps\\thing\\"),"thing");
+        assert_eq!(app_name(""),"");
+    }
+
+    #[test]
+    fn xdg_dirs() {
+        let home = Path::new("/home/user");
+        assert_eq!(xdg_dir(XdgDir::Config,None,home),PathBuf::from("/home/user/.config"));
+        assert_eq!(xdg_dir(XdgDir::State,None,home),PathBuf::from("/home/user/.local/state"));
+        assert_eq!(xdg_dir(XdgDir::Cache,Some(OsString::from("relative")),home),PathBuf::from("/home/user/.cache"));
+        assert_eq!(xdg_dir(XdgDir::Data,Some(OsString::from("/data")),home),PathBuf::from("/data"));
+    }
+
+    #[test]
+    fn migrating() {
+        let dir = ::tempfile::tempdir().unwrap();
+        let legacy = dir.path().join(".app").join("settings");
+        let path = dir.path().join(".config").join("app").join("settings");
+        assert!(!migrate(&legacy,&path).unwrap());
+
+        fs::create_dir_all(legacy.parent().unwrap()).unwrap();
+        fs::write(&legacy,"legacy").unwrap();
+        assert!(migrate(&legacy,&path).unwrap());
+        assert!(!legacy.exists());
+        assert_eq!(fs::read_to_string(&path).unwrap(),"legacy");
+
+        // doesn't replace a file that is already there
+        fs::write(&legacy,"legacy").unwrap();
+        assert!(!migrate(&legacy,&path).unwrap());
+        assert!(legacy.exists());
+    }
+
+    #[test]
+    fn portable_once() {
+        // the folder of the executable is only looked at once
+        let dir = exe_dir().unwrap();
+        let names = vec!["settingsfile-portable-test".to_string()];
+        let portable = is_portable(&dir,&names);
+        fs::write(dir.join(&names[0]),"").unwrap();
+        let after = is_portable(&dir,&names);
+        fs::remove_file(dir.join(&names[0])).unwrap();
+        assert_eq!(after,portable);
+
+        // any other folder every time
+        let other = ::tempfile::tempdir().unwrap();
+        assert!(!is_portable(other.path(),&names));
+        fs::write(other.path().join(&names[0]),"").unwrap();
+        assert!(is_portable(other.path(),&names));
+    }
+}
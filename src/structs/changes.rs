@@ -0,0 +1,54 @@
+use SettingsRaw;
+
+use std::collections::BTreeSet;
+use std::sync::{Mutex,MutexGuard};
+
+#[derive(Debug,Default)]
+struct Inner {
+    loaded : SettingsRaw,
+    dirty : BTreeSet<String>,
+}
+
+// the data as it was in the file when it was last loaded or saved, and
+// the keys changed or deleted since. Behind a lock, like `LastStamp`, so
+// saving can update it without needing `&mut`.
+#[derive(Debug,Default)]
+pub struct Changes(Mutex<Inner>);
+
+impl Changes {
+    pub fn loaded(&self) -> SettingsRaw {
+        self.lock().loaded.clone()
+    }
+
+    pub fn dirty(&self) -> Vec<String> {
+        //! The changed keys, sorted.
+
+        self.lock().dirty.iter().cloned().collect()
+    }
+
+    pub fn mark(&self, key_path : &str) {
+        self.lock().dirty.insert(key_path.to_string());
+    }
+
+    pub fn reset(&self, loaded : SettingsRaw) {
+        //! The file has `loaded` in it now, and nothing is changed.
+
+        let mut inner = self.lock();
+        inner.loaded = loaded;
+        inner.dirty.clear();
+    }
+
+    fn lock(&self) -> MutexGuard<'_,Inner> {
+        match self.0.lock() {
+            Ok(inner) => inner,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+impl Clone for Changes {
+    fn clone(&self) -> Changes {
+        let inner = self.lock();
+        Changes(Mutex::new(Inner { loaded : inner.loaded.clone(), dirty : inner.dirty.clone() }))
+    }
+}
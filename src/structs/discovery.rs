@@ -0,0 +1,151 @@
+use dirs;
+
+use std::ffi::OsStr;
+use std::path::{Path,PathBuf};
+
+/// How a [ShadowSettings](struct.ShadowSettings.html) finds its local file
+///
+/// By default only the current directory is used. With [walk_up](#method.walk_up)
+/// the parent directories are searched too, the same way `git` finds `.git`,
+/// so the project's file is still used from inside `src/`.
+///
+/// The search stops at the home directory (it isn't searched, the files
+/// there are global), the root of the filesystem, or after a directory that
+/// has one of the [markers](#method.with_markers) in it.
+///
+/// Normally only the nearest file is used, with [with_merge_all](#method.with_merge_all)
+/// every file found is loaded and the nearer files shadow the ones further
+/// up. Only the nearest file is saved to, the others are read only.
+///
+/// # Example
+///
+/// ```rust
+/// # extern crate settingsfile;
+/// use settingsfile::{ShadowSettings,LocalDiscovery};
+/// use settingsfile::EmptyConfig; // dumb config for examples and testing
+///
+/// let mut settings = ShadowSettings::new(EmptyConfig{});
+/// settings.set_local_discovery(LocalDiscovery::walk_up()
+///     .with_markers(vec![".git"])
+///     .with_merge_all(true));
+/// ```
+#[derive(Clone,Debug,Default)]
+pub struct LocalDiscovery {
+    walk_up : bool,
+    markers : Vec<String>,
+    merge_all : bool,
+    // the home directory, uses the real one if `None`
+    home : Option<PathBuf>,
+}
+
+impl LocalDiscovery {
+    pub fn current_dir() -> LocalDiscovery {
+        //! Only looks in the current directory, the default.
+
+        LocalDiscovery::default()
+    }
+
+    pub fn walk_up() -> LocalDiscovery {
+        //! Looks in the current directory and then its parents.
+
+        LocalDiscovery { walk_up : true, .. LocalDiscovery::default() }
+    }
+
+    pub fn with_markers<I,S>(mut self, markers : I) -> LocalDiscovery
+        where I : IntoIterator<Item=S>, S : ToString,
+    {
+        //! Files or folders that mark the top of a project, like `.git`.
+        //! The directory with the marker is searched but its parents aren't.
+
+        self.markers = markers.into_iter().map(|marker| marker.to_string()).collect();
+        self
+    }
+
+    pub fn with_merge_all(mut self, merge_all : bool) -> LocalDiscovery {
+        //! Uses every file found instead of only the nearest.
+
+        self.merge_all = merge_all;
+        self
+    }
+
+    pub fn with_home<P>(mut self, home : P) -> LocalDiscovery where P : AsRef<Path> {
+        //! Uses `home` as the home directory, mostly for testing.
+
+        self.home = Some(home.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn walks_up(&self) -> bool { self.walk_up }
+    pub fn markers(&self) -> &[String] { &self.markers }
+    pub fn merges_all(&self) -> bool { self.merge_all }
+
+    pub fn find<P,S>(&self, start : P, filename : S) -> Vec<PathBuf>
+        where P : AsRef<Path>, S : AsRef<OsStr>,
+    {
+        //! Finds the files called `filename` starting in `start`, the
+        //! nearest first. Has at most one file unless merging all of them.
+
+        let home = match self.home {
+            Some(ref home) => Some(home.clone()),
+            None => dirs::home_dir(),
+        };
+
+        let mut found = Vec::new();
+        for (depth,dir) in start.as_ref().ancestors().enumerate() {
+            if depth > 0 && (!self.walk_up || Some(dir) == home.as_deref()) {
+                break;
+            }
+
+            let file = dir.join(filename.as_ref());
+            if file.is_file() {
+                found.push(file);
+                if !self.merge_all {
+                    break;
+                }
+            }
+
+            if self.markers.iter().any(|marker| dir.join(marker).exists()) {
+                break;
+            }
+        }
+
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LocalDiscovery;
+    use std::fs;
+
+    #[test]
+    fn finding() {
+        let home = ::tempfile::tempdir().unwrap();
+        let project = home.path().join("code").join("project");
+        let deep = project.join("src").join("deep");
+        fs::create_dir_all(&deep).unwrap();
+        fs::write(home.path().join("settings"),"").unwrap();
+        fs::write(home.path().join("code").join("settings"),"").unwrap();
+        fs::write(project.join("settings"),"").unwrap();
+
+        let current = LocalDiscovery::current_dir().with_home(home.path());
+        assert!(current.find(&deep,"settings").is_empty());
+        assert_eq!(current.find(&project,"settings"),vec![project.join("settings")]);
+
+        let walk = LocalDiscovery::walk_up().with_home(home.path());
+        assert_eq!(walk.find(&deep,"settings"),vec![project.join("settings")]);
+
+        // stops at the home directory
+        let all = walk.clone().with_merge_all(true);
+        assert_eq!(all.find(&deep,"settings"),vec![
+            project.join("settings"),
+            home.path().join("code").join("settings"),
+        ]);
+
+        // and at a marker
+        fs::create_dir(project.join(".git")).unwrap();
+        let marked = all.with_markers(vec![".git"]);
+        assert_eq!(marked.find(&deep,"settings"),vec![project.join("settings")]);
+        assert!(marked.find(&deep,"missing").is_empty());
+    }
+}
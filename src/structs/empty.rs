@@ -1,7 +1,6 @@
 use Format;
 use SettingsRaw;
 use failure::Error;
-use SupportedType;
 
 #[derive(Clone)]
 pub struct EmptyConfig { }
@@ -9,15 +8,11 @@ impl Format for EmptyConfig {
     fn filename(&self) -> String { "settingsfile.text".to_string() }
     fn folder(&self) -> String { "settingsfile".to_string() }
 
-    fn from_str<T>(&self,_:&str) -> Result<SettingsRaw,Error> 
-        where T : Format + Clone 
-    {
+    fn from_str(&self,_:&str) -> Result<SettingsRaw,Error> {
         Err(format_err!("Not Implemented"))
     }
 
-    fn to_string<T:Sized>(&self,_:&T) -> Result<String,Error>
-        where T : SupportedType + serde::ser::Serialize, 
-    {
+    fn to_string(&self,_:&SettingsRaw) -> Result<String,Error> {
         Ok("Not Implemented".to_string())
     }
 }
\ No newline at end of file
@@ -0,0 +1,192 @@
+use Type;
+use SettingsRaw;
+use structs::types::{insert_path,get_path};
+
+use std::collections::HashMap;
+use std::env;
+
+/// Settings read from environment variables
+///
+/// Maps every variable that starts with the prefix onto a key path, the rest
+/// of the name is split on the separator (`__` by default) and lowercased, so
+/// with the prefix `MYAPP_` the variable `MYAPP_USER__NAME` is `user.name`.
+///
+/// The values are converted with [Type::infer](enum.Type.html#method.infer),
+/// so `MYAPP_PORT=8080` is a `Type::Int` and `MYAPP_PATHS=[a,b]` is a
+/// `Type::Array`.
+///
+/// The environment is read when the `EnvSource` is created, use
+/// [refresh](#method.refresh) to read it again.
+///
+/// An `EnvSource` is read only, and is used as the highest priority
+/// layer of a [Settings](struct.Settings.html) or
+/// [ShadowSettings](struct.ShadowSettings.html) with `set_env_source`, or
+/// added to a [LayeredSettings](struct.LayeredSettings.html) with
+/// `push_env_layer`.
+///
+/// # Example
+///
+/// ```rust
+/// # extern crate settingsfile;
+/// use settingsfile::{EnvSource,ShadowSettings,Type};
+/// use settingsfile::EmptyConfig; // dumb config for examples and testing
+///
+/// // using a fake environment instead of the real one
+/// let env = EnvSource::new("MYAPP_")
+///     .with_vars(vec![("MYAPP_USER__NAME","snsvrno"),("MYAPP_PORT","8080")]);
+///
+/// let mut settings = ShadowSettings::new(EmptyConfig{});
+/// settings.set_value_global("port",&80).unwrap();
+/// settings.set_env_source(env);
+///
+/// assert_eq!(settings.get_value("user.name"),Some(Type::Text("snsvrno".to_string())));
+/// assert_eq!(settings.get_value("port"),Some(Type::Int(8080)));
+/// ```
+#[derive(Clone,Debug)]
+pub struct EnvSource {
+    prefix : String,
+    separator : String,
+    // the fake environment, uses the real one if `None`
+    vars : Option<HashMap<String,String>>,
+    values : SettingsRaw,
+}
+
+impl EnvSource {
+    pub fn new<S>(prefix : S) -> EnvSource where S : ToString {
+        //! Reads all the variables that start with `prefix` from the
+        //! environment, using `__` as the separator.
+
+        let mut source = EnvSource {
+            prefix : prefix.to_string(),
+            separator : "__".to_string(),
+            vars : None,
+            values : SettingsRaw::new(),
+        };
+        source.refresh();
+        source
+    }
+
+    pub fn with_separator<S>(mut self, separator : S) -> EnvSource where S : ToString {
+        //! Changes the text that separates the parts of the key path.
+
+        self.separator = separator.to_string();
+        self.refresh();
+        self
+    }
+
+    pub fn with_vars<I,K,V>(mut self, vars : I) -> EnvSource
+        where I : IntoIterator<Item=(K,V)>, K : ToString, V : ToString,
+    {
+        //! Uses `vars` instead of the real environment, mostly for testing.
+
+        self.vars = Some(vars.into_iter().map(|(k,v)| (k.to_string(),v.to_string())).collect());
+        self.refresh();
+        self
+    }
+
+    pub fn prefix(&self) -> &str { &self.prefix }
+    pub fn separator(&self) -> &str { &self.separator }
+
+    pub fn refresh(&mut self) {
+        //! Reads the environment again.
+
+        let mut vars : Vec<(String,String)> = match self.vars {
+            Some(ref vars) => vars.iter().map(|(k,v)| (k.clone(),v.clone())).collect(),
+            None => env::vars_os()
+                .filter_map(|(k,v)| Some((k.into_string().ok()?,v.into_string().ok()?)))
+                .collect(),
+        };
+        // sorted so the result doesn't depend on the order of the
+        // environment when variables overlap, `A__B` wins over `A`.
+        vars.sort();
+
+        let mut values = SettingsRaw::new();
+        for (name,value) in vars {
+            if let Some(key_path) = self.key_path(&name) {
+                insert_path(&mut values,&key_path,Type::infer(&value));
+            }
+        }
+        self.values = values;
+    }
+
+    pub fn key_path(&self, name : &str) -> Option<String> {
+        //! The key path for the variable `name`, or `None` if the
+        //! variable isn't used by this source.
+
+        let rest = name.strip_prefix(self.prefix.as_str())?;
+        if rest.is_empty() || self.separator.is_empty() {
+            return None;
+        }
+
+        let parts : Vec<String> = rest.split(self.separator.as_str()).map(|part| part.to_lowercase()).collect();
+        if parts.iter().any(|part| part.is_empty()) {
+            return None;
+        }
+
+        Some(parts.join("."))
+    }
+
+    pub fn get_value(&self, key_path : &str) -> Option<Type> {
+        //! Gets the value from the environment at `key_path`.
+
+        get_path(&self.values,key_path).cloned()
+    }
+
+    pub(crate) fn values(&self) -> &SettingsRaw {
+        &self.values
+    }
+
+    pub fn keys(&self) -> Vec<String> {
+        //! All the keys that are set in the environment, sorted.
+
+        let mut keys : Vec<String> = match Type::Complex(self.values.clone()).flatten(None) {
+            Type::Complex(flat) => flat.into_keys().collect(),
+            _ => Vec::new(),
+        };
+        keys.sort();
+        keys
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use Type;
+    use super::EnvSource;
+
+    fn env() -> EnvSource {
+        EnvSource::new("MYAPP_").with_vars(vec![
+            ("MYAPP_USER__NAME","snsvrno"),
+            ("MYAPP_USER__AGE","30"),
+            ("MYAPP_MAX_SIZE","1.5"),
+            ("MYAPP_PATHS","[\"~/bin\", ~/.cargo/bin]"),
+            ("MYAPP_DEBUG","true"),
+            ("MYAPP_","nothing"),
+            ("MYAPP_BAD____KEY","nothing"),
+            ("OTHER_NAME","other"),
+        ])
+    }
+
+    #[test]
+    fn mapping() {
+        let env = env();
+        assert_eq!(env.key_path("MYAPP_USER__NAME"),Some("user.name".to_string()));
+        assert_eq!(env.key_path("OTHER_NAME"),None);
+
+        assert_eq!(env.keys(),vec!["debug","max_size","paths","user.age","user.name"]);
+        assert_eq!(env.get_value("user.name"),Some(Type::Text("snsvrno".to_string())));
+        assert_eq!(env.get_value("user.age"),Some(Type::Int(30)));
+        assert_eq!(env.get_value("max_size"),Some(Type::Float(1.5)));
+        assert_eq!(env.get_value("debug"),Some(Type::Switch(true)));
+        assert_eq!(env.get_value("paths"),Some(Type::Array(vec![
+            Type::Text("~/bin".to_string()),
+            Type::Text("~/.cargo/bin".to_string()),
+        ])));
+        assert_eq!(env.get_value("user").unwrap().to_complex().unwrap().len(),2);
+    }
+
+    #[test]
+    fn separator() {
+        let env = EnvSource::new("APP.").with_separator(".").with_vars(vec![("APP.user.name","snsvrno")]);
+        assert_eq!(env.get_value("user.name"),Some(Type::Text("snsvrno".to_string())));
+    }
+}
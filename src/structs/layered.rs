@@ -0,0 +1,618 @@
+use Format;
+use Type;
+use Settings;
+use SupportedType;
+use Origin;
+use MergeOptions;
+use EnvSource;
+use Overrides;
+use SettingsRaw;
+
+use std::fs;
+use std::fs::File;
+use std::path::{Path,PathBuf};
+use failure::Error;
+
+/// One layer of a [LayeredSettings](struct.LayeredSettings.html)
+///
+/// A layer is a named `Settings` with an optional file that it is
+/// read from and saved to. Layers that aren't writable can only be
+/// loaded, and will never be changed or saved by the `LayeredSettings`.
+#[derive(Clone)]
+pub struct Layer<T> where T : Format + Clone {
+    name : String,
+    path : Option<PathBuf>,
+    writable : bool,
+    settings : Settings<T>,
+}
+
+impl<T> Layer<T> where T : Format + Clone {
+    pub fn name(&self) -> &str { &self.name }
+    pub fn path(&self) -> Option<&Path> { self.path.as_deref() }
+    pub fn is_writable(&self) -> bool { self.writable }
+    pub fn settings(&self) -> &Settings<T> { &self.settings }
+}
+
+/// Settings that pulls data from any number of layers
+///
+/// A generalised [ShadowSettings](struct.ShadowSettings.html), instead of only
+/// having a global and a local file, `LayeredSettings` has an ordered list of
+/// named layers. Layers added later have priority over the layers added before
+/// them, so a typical stack would be built in this order:
+///
+/// defaults → system → user → project → environment → command line
+///
+/// # Example
+///
+/// ```rust
+/// # extern crate settingsfile;
+/// use settingsfile::{LayeredSettings,Type};
+/// use settingsfile::EmptyConfig; // dumb config for examples and testing
+///
+/// let mut settings = LayeredSettings::new(EmptyConfig{});
+/// settings.push_layer("defaults",None::<&str>,false).unwrap();
+/// settings.push_layer("user",Some("/home/user/.config/app/settings"),true).unwrap();
+/// settings.push_layer("project",Some("settings"),true).unwrap();
+///
+/// settings.set_value_in("user","user.name","snsvrno").unwrap();
+/// settings.set_value_in("project","user.name","project name").unwrap();
+///
+/// assert_eq!(settings.get_value("user.name"),Some(Type::Text("project name".to_string())));
+/// // the defaults layer isn't writable
+/// assert!(settings.set_value_in("defaults","user.name","default").is_err());
+/// ```
+#[derive(Clone)]
+pub struct LayeredSettings<T> where T : Format + Clone {
+    ioconfig : T,
+    // ordered from the lowest priority to the highest
+    layers : Vec<Layer<T>>,
+    merge : MergeOptions,
+}
+
+impl<T> LayeredSettings<T> where T : Format + Clone {
+    pub fn new(config : T) -> LayeredSettings<T> {
+        //! Creates a new `LayeredSettings` without any layers.
+
+        LayeredSettings {
+            ioconfig : config,
+            layers : Vec::new(),
+            merge : MergeOptions::new(),
+        }
+    }
+
+    // layer management //////////////////////////////////////////////////////////////////////
+
+    pub fn push_layer<S,P>(&mut self, name : S, path : Option<P>, writable : bool) -> Result<(),Error>
+        where S : ToString, P : AsRef<Path>,
+    {
+        //! Adds an empty layer on the top of the stack, so it will override
+        //! every existing layer.
+        //!
+        //! Layers without a `path` are in memory only, they can't be loaded
+        //! or saved but can be filled with `set_value_in`.
+
+        let index = self.layers.len();
+        self.insert_layer(index,name,path,writable)
+    }
+
+    pub fn push_env_layer<S>(&mut self, name : S, source : &EnvSource) -> Result<(),Error>
+        where S : ToString,
+    {
+        //! Adds a read only layer on the top of the stack with all the
+        //! values from the [EnvSource](struct.EnvSource.html).
+        //!
+        //! The values are copied when the layer is added, so the layer
+        //! needs to be removed and added again to pick up changes.
+
+        self.push_values_layer(name.to_string(),source.values())
+    }
+
+    pub fn push_overrides_layer<S>(&mut self, name : S, overrides : &Overrides) -> Result<(),Error>
+        where S : ToString,
+    {
+        //! Adds a read only layer on the top of the stack with the
+        //! [Overrides](struct.Overrides.html) from the command line.
+
+        self.push_values_layer(name.to_string(),overrides.values())
+    }
+
+    fn push_values_layer(&mut self, name : String, values : &SettingsRaw) -> Result<(),Error> {
+        //! Adds a read only layer on the top of the stack with a copy
+        //! of `values`.
+
+        self.push_layer(name,None::<&str>,false)?;
+
+        let index = self.layers.len() - 1;
+        if let Type::Complex(flat) = Type::Complex(values.clone()).flatten(None) {
+            for (key,value) in flat {
+                self.layers[index].settings.set_value(&key,&value)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn insert_layer<S,P>(&mut self, index : usize, name : S, path : Option<P>, writable : bool) -> Result<(),Error>
+        where S : ToString, P : AsRef<Path>,
+    {
+        //! Adds an empty layer at `index`, `0` being the lowest priority.
+        //!
+        //! Will error if a layer with the same name already exists.
+
+        let name = name.to_string();
+        if self.position(&name).is_some() {
+            return Err(format_err!("Layer '{}' already exists",name));
+        }
+        if index > self.layers.len() {
+            return Err(format_err!("Can't insert layer '{}' at {}, there are only {} layers",name,index,self.layers.len()));
+        }
+
+        let path = path.map(|path| path.as_ref().to_path_buf());
+        let settings = Settings::new(self.config_for(path.as_deref()));
+        self.layers.insert(index,Layer { name, path, writable, settings });
+
+        Ok(())
+    }
+
+    fn config_for(&self, path : Option<&Path>) -> T {
+        //! The configuration for the layer's file, see
+        //! [Format::select_for](trait.Format.html#method.select_for).
+
+        let mut config = self.ioconfig.clone();
+        if let Some(path) = path {
+            config.select_for(path);
+        }
+        config
+    }
+
+    pub fn remove_layer(&mut self, name : &str) -> Option<Layer<T>> {
+        //! Removes the layer from the stack and returns it.
+
+        match self.position(name) {
+            Some(index) => Some(self.layers.remove(index)),
+            None => None,
+        }
+    }
+
+    pub fn layer(&self, name : &str) -> Option<&Layer<T>> {
+        self.layers.iter().find(|layer| layer.name == name)
+    }
+
+    pub fn layer_names(&self) -> Vec<String> {
+        //! The names of all the layers, from the lowest priority to the highest.
+
+        self.layers.iter().map(|layer| layer.name.clone()).collect()
+    }
+
+    fn position(&self, name : &str) -> Option<usize> {
+        self.layers.iter().position(|layer| layer.name == name)
+    }
+
+    fn layer_mut(&mut self, name : &str) -> Result<&mut Layer<T>,Error> {
+        match self.layers.iter_mut().find(|layer| layer.name == name) {
+            Some(layer) => Ok(layer),
+            None => Err(format_err!("Layer '{}' doesn't exist",name)),
+        }
+    }
+
+    fn writable_layer_mut(&mut self, name : &str) -> Result<&mut Layer<T>,Error> {
+        let layer = self.layer_mut(name)?;
+        if layer.writable {
+            Ok(layer)
+        } else {
+            Err(format_err!("Layer '{}' is read only",name))
+        }
+    }
+
+    // io - filesystem functions //////////////////////////////////////////////////////////////
+
+    pub fn load(&mut self) -> Result<(),Error> {
+        //! Loads every layer that has a path.
+        //!
+        //! Files that don't exist are skipped, every layer is attempted even
+        //! if one fails and all the errors are returned together.
+
+        let mut errors : Vec<String> = Vec::new();
+        for name in self.layer_names() {
+            if let Err(error) = self.load_layer(&name) {
+                errors.push(format!("{}: {}",name,error));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(format_err!("{}",errors.join(", ")))
+        }
+    }
+
+    pub fn load_layer(&mut self, name : &str) -> Result<(),Error> {
+        //! Loads a single layer from its file, replacing the layer's data.
+        //!
+        //! Does nothing if the layer doesn't have a path or the file
+        //! doesn't exist. Takes a shared lock while reading, and remembers
+        //! what the file looked like so `save_layer` won't write over
+        //! changes made to it since.
+
+        let config = self.config_for(self.layer(name).and_then(|layer| layer.path.as_deref()));
+        let layer = self.layer_mut(name)?;
+        let path = match layer.path {
+            Some(ref path) => path.clone(),
+            None => return Ok(()),
+        };
+        if !path.exists() {
+            return Ok(());
+        }
+
+        info!("Using {} for layer {}",path.display(),name);
+        let contents = layer.settings.read_at(&path)?;
+        let mut settings = Settings::create_from_buffer(String::from_utf8(contents.clone())?,config)?;
+        settings.set_lock_timeout(layer.settings.lock_timeout());
+        settings.stamp_contents(&path,&contents)?;
+        layer.settings = settings;
+        Ok(())
+    }
+
+    pub fn load_layer_from(&mut self, name : &str, file : &mut File) -> Result<(),Error> {
+        //! Loads the file buffer over the existing layer, replacing it.
+
+        let config = self.config_for(self.layer(name).and_then(|layer| layer.path.as_deref()));
+        let layer = self.layer_mut(name)?;
+        layer.settings = Settings::create_from(file,config)?;
+        Ok(())
+    }
+
+    pub fn save(&self) -> Result<(),Error> {
+        //! Saves every writable layer that has a path, see
+        //! [save_layer](#method.save_layer).
+
+        for layer in self.layers.iter() {
+            if layer.writable && layer.path.is_some() {
+                self.save_layer(&layer.name)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn save_layer(&self, name : &str) -> Result<(),Error> {
+        //! Saves a single layer to its file, with the same lock as
+        //! [Settings::save](struct.Settings.html#method.save).
+        //!
+        //! Will error if the layer is read only or doesn't have a path, and
+        //! with a [SettingsError::ConcurrentModification](enum.SettingsError.html)
+        //! if the file was changed since the layer was loaded.
+
+        self.save_layer_checked(name,true)
+    }
+
+    pub fn save_layer_force(&self, name : &str) -> Result<(),Error> {
+        //! Saves a single layer like `save_layer`, even if the file was
+        //! changed since it was loaded.
+
+        self.save_layer_checked(name,false)
+    }
+
+    fn save_layer_checked(&self, name : &str, check : bool) -> Result<(),Error> {
+        let layer = match self.layer(name) {
+            Some(layer) => layer,
+            None => return Err(format_err!("Layer '{}' doesn't exist",name)),
+        };
+        if !layer.writable {
+            return Err(format_err!("Layer '{}' is read only",name));
+        }
+        let path = match layer.path {
+            Some(ref path) => path,
+            None => return Err(format_err!("Layer '{}' doesn't have a path",name)),
+        };
+
+        info!("Saving layer {} to {}",name,path.display());
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        layer.settings.save_at(path,check)?;
+        Ok(())
+    }
+
+    // io - object functions //////////////////////////////////////////////////////////////////
+
+    pub fn get_value(&self, key_path : &str) -> Option<Type> {
+        //! Gets the most accurate value from the `key_path`, looking through
+        //! all the layers. Higher layers override lower layers, and complex
+        //! values are merged at every depth from all the layers that define them.
+        //!
+        //! A key that is a `Type::Unset` in a layer is hidden from all the
+        //! layers below it, see [unset_in](#method.unset_in).
+
+        let mut result : Option<Type> = None;
+
+        for layer in self.layers.iter() {
+            let value = match layer.settings.is_unset(key_path) {
+                true => Some(Type::Unset),
+                false => layer.settings.get_value(key_path),
+            };
+            if let Some(value) = value {
+                result = Some(match result {
+                    None => value,
+                    Some(lower) => self.merge.merge(key_path,lower,value),
+                });
+            }
+        }
+
+        result?.without_unset()
+    }
+
+    pub fn set_merge_options(&mut self, options : MergeOptions) {
+        //! Sets how values from different layers are combined, see
+        //! [MergeOptions](struct.MergeOptions.html).
+
+        self.merge = options;
+    }
+
+    pub fn merge_options(&self) -> &MergeOptions {
+        &self.merge
+    }
+
+    pub fn get_value_with_origin(&self, key_path : &str) -> Option<(Type,Origin)> {
+        //! Gets the same value as `get_value`, along with the highest layer
+        //! that defined it.
+
+        let value = self.get_value(key_path)?;
+        let layer = self.layers.iter().rev().find(|layer| layer.settings.get_value(key_path).is_some())?;
+        Some((value,Origin::new(&layer.name,layer.path.clone(),layer.settings.locate(key_path))))
+    }
+
+    pub fn keys_with_origin(&self) -> Vec<(String,Origin)> {
+        //! Lists every key along with the layer its value comes from. Sorted by key.
+
+        let mut keys = self.keys();
+        keys.sort();
+
+        keys.into_iter().filter_map(|key| {
+            let (_,origin) = self.get_value_with_origin(&key)?;
+            Some((key,origin))
+        }).collect()
+    }
+
+    pub fn get_value_or<A>(&self, key_path : &str, default_value : &A) -> Type
+        where A : SupportedType + ?Sized,
+    {
+        match self.get_value(key_path) {
+            Some(value) => value,
+            None => default_value.wrap(),
+        }
+    }
+
+    pub fn get_value_in(&self, layer : &str, key_path : &str) -> Option<Type> {
+        //! Gets the value from only one layer.
+
+        match self.layer(layer) {
+            Some(layer) => layer.settings.get_value(key_path),
+            None => None,
+        }
+    }
+
+    pub fn set_value_in<A>(&mut self, layer : &str, key_path : &str, value : &A) -> Result<(),Error>
+        where A : SupportedType + ?Sized,
+    {
+        //! Sets the value in the given layer, will error if the layer
+        //! doesn't exist or is read only.
+
+        self.writable_layer_mut(layer)?.settings.set_value(key_path,value)
+    }
+
+    pub fn unset_in(&mut self, layer : &str, key_path : &str) -> Result<(),Error> {
+        //! Unsets the key in the given layer, so `get_value` will return
+        //! `None` unless a higher layer sets it again.
+
+        self.set_value_in(layer,key_path,&Type::Unset)
+    }
+
+    pub fn delete_key_in(&mut self, layer : &str, key_path : &str) -> Result<Option<Type>,Error> {
+        //! Deletes the key from the given layer, will error if the layer
+        //! doesn't exist or is read only.
+
+        Ok(self.writable_layer_mut(layer)?.settings.delete_key(key_path))
+    }
+
+    pub fn keys(&self) -> Vec<String> {
+        //! All the keys that have a value in any layer, keys that
+        //! are unset are left out.
+
+        let mut keys : Vec<String> = Vec::new();
+        for layer in self.layers.iter() {
+            for key in layer.settings.keys() {
+                if !keys.contains(&key) {
+                    keys.push(key);
+                }
+            }
+        }
+        keys.retain(|key| self.get_value(key).is_some());
+        keys
+    }
+
+    pub fn keys_in(&self, layer : &str) -> Vec<String> {
+        match self.layer(layer) {
+            Some(layer) => layer.settings.keys(),
+            None => Vec::new(),
+        }
+    }
+}
+
+// tests ////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use Format;
+    use SettingsRaw;
+    use Type;
+    use LayeredSettings;
+    use formats;
+
+    use failure::Error;
+    use ron;
+    use tempfile;
+    use std::env;
+    use std::path::PathBuf;
+
+    // configuration that can actually read and write, keeping its lock
+    // files in `base`.
+    #[derive(Clone)]
+    struct Configuration { base : PathBuf }
+    impl Format for Configuration {
+        fn filename(&self) -> String { "".to_string() }
+        fn folder(&self) -> String { "".to_string() }
+        fn base_dir(&self) -> Result<PathBuf,Error> { Ok(self.base.clone()) }
+
+        fn from_str(&self,buffer:&str) -> Result<SettingsRaw,Error> {
+            Ok(ron::de::from_str(buffer)?)
+        }
+        fn to_string(&self,object:&SettingsRaw) -> Result<String,Error> {
+            Ok(ron::ser::to_string(object)?)
+        }
+        fn locate(&self,buffer:&str,key_path:&str) -> Option<(usize,usize)> {
+            formats::locate_key(buffer,key_path)
+        }
+    }
+
+    fn layered() -> LayeredSettings<Configuration> {
+        let mut settings = LayeredSettings::new(Configuration { base : env::temp_dir() });
+        assert!(settings.push_layer("defaults",None::<&str>,false).is_ok());
+        assert!(settings.push_layer("user",None::<&str>,true).is_ok());
+        assert!(settings.push_layer("project",None::<&str>,true).is_ok());
+        settings
+    }
+
+    #[test]
+    fn resolving() {
+        let mut settings = layered();
+        assert!(settings.set_value_in("user","a.b.c","user").is_ok());
+        assert!(settings.set_value_in("user","a.d",&1).is_ok());
+        assert!(settings.set_value_in("project","a.b.c","project").is_ok());
+
+        assert_eq!(settings.get_value("a.b.c"),Some(Type::Text("project".to_string())));
+        assert_eq!(settings.get_value("a.d"),Some(Type::Int(1)));
+        assert_eq!(settings.get_value_in("user","a.b.c"),Some(Type::Text("user".to_string())));
+
+        let a = settings.get_value("a").unwrap().to_complex().unwrap();
+        assert_eq!(a.get("d"),Some(&Type::Int(1)));
+
+        // merged at every depth
+        assert!(settings.set_value_in("user","a.b.e",&2).is_ok());
+        let b = settings.get_value("a.b").unwrap().to_complex().unwrap();
+        assert_eq!(b.get("c"),Some(&Type::Text("project".to_string())));
+        assert_eq!(b.get("e"),Some(&Type::Int(2)));
+
+        assert_eq!(settings.delete_key_in("project","a.b.c").unwrap(),Some(Type::Text("project".to_string())));
+        assert_eq!(settings.get_value("a.b.c"),Some(Type::Text("user".to_string())));
+    }
+
+    #[test]
+    fn unsetting() {
+        let mut settings = layered();
+        assert!(settings.set_value_in("user","a.b",&1).is_ok());
+        assert!(settings.set_value_in("user","a.c",&2).is_ok());
+        assert!(settings.unset_in("project","a.b").is_ok());
+        assert_eq!(settings.get_value("a.b"),None);
+        assert_eq!(settings.get_value("a.c"),Some(Type::Int(2)));
+        assert_eq!(settings.keys(),vec!["a.c".to_string()]);
+
+        // unsetting a parent hides everything under it
+        assert!(settings.unset_in("project","a").is_ok());
+        assert_eq!(settings.get_value("a.c"),None);
+        assert_eq!(settings.get_value("a"),None);
+        assert!(settings.unset_in("defaults","a").is_err());
+    }
+
+    #[test]
+    fn environment() {
+        use {EnvSource,Overrides};
+
+        let mut settings = layered();
+        assert!(settings.set_value_in("project","user.name","project").is_ok());
+        assert!(settings.set_value_in("project","user.email","email").is_ok());
+
+        let env = EnvSource::new("APP_").with_vars(vec![("APP_USER__NAME","env"),("APP_DEBUG","true")]);
+        assert!(settings.push_env_layer("env",&env).is_ok());
+        assert_eq!(settings.get_value("user.name"),Some(Type::Text("env".to_string())));
+        assert_eq!(settings.get_value("user.email"),Some(Type::Text("email".to_string())));
+        assert_eq!(settings.get_value("debug"),Some(Type::Switch(true)));
+        assert!(settings.set_value_in("env","debug",&false).is_err());
+
+        let (overrides,_) = Overrides::from_args(vec!["--set","debug=false"]).unwrap();
+        assert!(settings.push_overrides_layer("command line",&overrides).is_ok());
+        assert_eq!(settings.get_value("debug"),Some(Type::Switch(false)));
+    }
+
+    #[test]
+    fn managing_layers() {
+        let mut settings = layered();
+        assert!(settings.set_value_in("defaults","key",&1).is_err());
+        assert!(settings.set_value_in("nothing","key",&1).is_err());
+        assert!(settings.push_layer("user",None::<&str>,true).is_err());
+
+        assert!(settings.insert_layer(1,"system",None::<&str>,true).is_ok());
+        assert_eq!(settings.layer_names(),vec!["defaults","system","user","project"]);
+
+        assert!(settings.set_value_in("system","key",&1).is_ok());
+        assert!(settings.set_value_in("user","key",&2).is_ok());
+        assert_eq!(settings.get_value("key"),Some(Type::Int(2)));
+
+        assert!(settings.remove_layer("user").is_some());
+        assert_eq!(settings.get_value("key"),Some(Type::Int(1)));
+        assert!(settings.remove_layer("user").is_none());
+    }
+
+    #[test]
+    fn saving_and_loading() {
+        let folder = tempfile::tempdir().unwrap();
+        let user_path = folder.path().join("user").join("settings.ron");
+        let system_path = folder.path().join("system.ron");
+
+        let mut settings = LayeredSettings::new(Configuration { base : folder.path().to_path_buf() });
+        assert!(settings.push_layer("system",Some(&system_path),false).is_ok());
+        assert!(settings.push_layer("user",Some(&user_path),true).is_ok());
+        assert!(settings.set_value_in("user","user.name","snsvrno").is_ok());
+        assert!(settings.save().is_ok());
+        assert!(settings.save_layer("system").is_err());
+        assert!(!system_path.exists());
+
+        let mut other = LayeredSettings::new(Configuration { base : folder.path().to_path_buf() });
+        assert!(other.push_layer("system",Some(&system_path),false).is_ok());
+        assert!(other.push_layer("user",Some(&user_path),true).is_ok());
+        assert!(other.load().is_ok());
+        assert_eq!(other.get_value("user.name"),Some(Type::Text("snsvrno".to_string())));
+
+        let (_,origin) = other.get_value_with_origin("user.name").unwrap();
+        assert_eq!(origin.layer(),"user");
+        assert_eq!(origin.path(),Some(user_path.as_path()));
+        assert_eq!(origin.line(),Some(1));
+        assert_eq!(other.keys_with_origin().len(),1);
+    }
+
+    #[test]
+    fn saving_changed_layer() {
+        use SettingsError;
+        use std::fs;
+
+        let folder = tempfile::tempdir().unwrap();
+        let path = folder.path().join("settings.ron");
+        fs::write(&path,"{\"name\":\"first\"}").unwrap();
+
+        let mut settings = LayeredSettings::new(Configuration { base : folder.path().to_path_buf() });
+        assert!(settings.push_layer("user",Some(&path),true).is_ok());
+        assert!(settings.load().is_ok());
+        assert!(settings.set_value_in("user","size",&12).is_ok());
+
+        // someone else changed it since
+        fs::write(&path,"{\"name\":\"second\"}").unwrap();
+        let error = settings.save_layer("user").unwrap_err();
+        assert_eq!(error.downcast_ref::<SettingsError>(),Some(&SettingsError::ConcurrentModification { path : path.clone() }));
+        assert!(settings.save().is_err());
+        assert!(fs::read_to_string(&path).unwrap().contains("second"));
+
+        assert!(settings.save_layer_force("user").is_ok());
+        assert!(fs::read_to_string(&path).unwrap().contains("first"));
+        // and it knows the file it wrote
+        assert!(settings.save_layer("user").is_ok());
+    }
+}
@@ -0,0 +1,216 @@
+use Type;
+
+impl Type {
+    pub fn infer(literal : &str) -> Type {
+        //! Creates a `Type` from text written by a user, like an environment
+        //! variable or a command line argument.
+        //!
+        //! The text is only converted when the value would be written back
+        //! exactly the same, anything else is kept as a `Type::Text`.
+        //!
+        //! - `true` and `false` are a `Type::Switch`
+        //! - whole numbers like `42` or `-3` are a `Type::Int`, numbers with
+        //!   leading zeros like `007` are kept as text
+        //! - decimal numbers like `1.5` are a `Type::Float`, but not ones that
+        //!   would be written differently, like the version `1.10` or `2.0`
+        //! - `[a, 2, "c"]` is a `Type::Array` with each item inferred
+        //! - text in quotes, `"42"` or `'42'`, is a `Type::Text` without the quotes
+        //!
+        //! ```rust
+        //! # extern crate settingsfile;
+        //! use settingsfile::Type;
+        //!
+        //! assert_eq!(Type::infer("42"),Type::Int(42));
+        //! assert_eq!(Type::infer("\"42\""),Type::Text("42".to_string()));
+        //! assert_eq!(Type::infer("[a,b]"),Type::Array(vec![
+        //!     Type::Text("a".to_string()),
+        //!     Type::Text("b".to_string()),
+        //! ]));
+        //! ```
+
+        let trimmed = literal.trim();
+
+        match trimmed {
+            "true" => return Type::Switch(true),
+            "false" => return Type::Switch(false),
+            _ => (),
+        }
+
+        if is_int(trimmed) {
+            if let Ok(int) = trimmed.parse::<i32>() {
+                return Type::Int(int);
+            }
+        }
+
+        if is_float(trimmed) {
+            if let Ok(float) = trimmed.parse::<f32>() {
+                if float.to_string() == trimmed {
+                    return Type::Float(float);
+                }
+            }
+        }
+
+        if let Some(text) = unquote(trimmed) {
+            return Type::Text(text);
+        }
+
+        if trimmed.starts_with('[') && trimmed.ends_with(']') {
+            if let Some(items) = split_items(&trimmed[1..trimmed.len()-1]) {
+                return Type::Array(items.iter().map(|item| Type::infer(item.trim())).collect());
+            }
+        }
+
+        Type::Text(literal.to_string())
+    }
+}
+
+fn digits(text : &str) -> bool {
+    !text.is_empty() && text.chars().all(|c| c.is_ascii_digit())
+}
+
+fn is_int(text : &str) -> bool {
+    let unsigned = text.strip_prefix('-').unwrap_or(text);
+    digits(unsigned) && (unsigned == "0" || !unsigned.starts_with('0'))
+}
+
+fn is_float(text : &str) -> bool {
+    let unsigned = text.strip_prefix('-').unwrap_or(text);
+    match unsigned.find('.') {
+        Some(dot) => {
+            let whole = &unsigned[..dot];
+            digits(whole) && digits(&unsigned[dot+1..]) && (whole == "0" || !whole.starts_with('0'))
+        },
+        None => false,
+    }
+}
+
+fn unquote(text : &str) -> Option<String> {
+    //! Removes the quotes around the text, and unescapes any `\"` inside.
+
+    let quote = text.chars().next()?;
+    if text.len() < 2 || (quote != '"' && quote != '\'') || !text.ends_with(quote) {
+        return None;
+    }
+
+    let mut unquoted = String::new();
+    let mut escaped = false;
+    for c in text[1..text.len()-1].chars() {
+        if escaped {
+            unquoted.push(c);
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == quote {
+            // a quote in the middle, so this is something like `"a" "b"`
+            return None;
+        } else {
+            unquoted.push(c);
+        }
+    }
+
+    Some(unquoted)
+}
+
+fn split_items(text : &str) -> Option<Vec<String>> {
+    //! Splits the inside of an array on the commas that aren't inside
+    //! of quotes or nested arrays. Returns `None` if the brackets don't
+    //! match up.
+
+    let mut items = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0;
+    let mut quote : Option<char> = None;
+    let mut escaped = false;
+
+    if text.trim().is_empty() {
+        return Some(items);
+    }
+
+    for c in text.chars() {
+        match quote {
+            Some(q) => {
+                if escaped {
+                    escaped = false;
+                } else if c == '\\' {
+                    escaped = true;
+                } else if c == q {
+                    quote = None;
+                }
+                current.push(c);
+            },
+            None => match c {
+                '"' | '\'' => { quote = Some(c); current.push(c); },
+                '[' => { depth += 1; current.push(c); },
+                ']' => {
+                    if depth == 0 { return None; }
+                    depth -= 1;
+                    current.push(c);
+                },
+                ',' if depth == 0 => items.push(current.split_off(0)),
+                _ => current.push(c),
+            },
+        }
+    }
+
+    if depth != 0 || quote.is_some() {
+        return None;
+    }
+
+    items.push(current);
+    Some(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use Type;
+
+    fn text(value : &str) -> Type { Type::Text(value.to_string()) }
+
+    #[test]
+    fn scalars() {
+        assert_eq!(Type::infer("true"),Type::Switch(true));
+        assert_eq!(Type::infer("false"),Type::Switch(false));
+        assert_eq!(Type::infer("True"),text("True"));
+        assert_eq!(Type::infer("42"),Type::Int(42));
+        assert_eq!(Type::infer("-3"),Type::Int(-3));
+        assert_eq!(Type::infer("0"),Type::Int(0));
+        assert_eq!(Type::infer("007"),text("007"));
+        assert_eq!(Type::infer("+5"),text("+5"));
+        assert_eq!(Type::infer("99999999999"),text("99999999999"));
+        assert_eq!(Type::infer("1.5"),Type::Float(1.5));
+        assert_eq!(Type::infer("-0.25"),Type::Float(-0.25));
+        assert_eq!(Type::infer("1."),text("1."));
+        assert_eq!(Type::infer("1.10"),text("1.10"));
+        assert_eq!(Type::infer("2.0"),text("2.0"));
+        assert_eq!(Type::infer("0.1"),Type::Float(0.1));
+        assert_eq!(Type::infer("3.14159274"),text("3.14159274"));
+        assert_eq!(Type::infer("1.2.3"),text("1.2.3"));
+        assert_eq!(Type::infer("nan"),text("nan"));
+        assert_eq!(Type::infer("snsvrno"),text("snsvrno"));
+        assert_eq!(Type::infer(""),text(""));
+    }
+
+    #[test]
+    fn quoted() {
+        assert_eq!(Type::infer("\"42\""),text("42"));
+        assert_eq!(Type::infer("'true'"),text("true"));
+        assert_eq!(Type::infer("\"say \\\"hi\\\"\""),text("say \"hi\""));
+        assert_eq!(Type::infer("\"a\" \"b\""),text("\"a\" \"b\""));
+        assert_eq!(Type::infer("\""),text("\""));
+    }
+
+    #[test]
+    fn arrays() {
+        assert_eq!(Type::infer("[]"),Type::Array(vec![]));
+        assert_eq!(Type::infer("[a, b]"),Type::Array(vec![text("a"),text("b")]));
+        assert_eq!(Type::infer("[1, 2.5, true, \"x,y\"]"),Type::Array(vec![
+            Type::Int(1),Type::Float(2.5),Type::Switch(true),text("x,y"),
+        ]));
+        assert_eq!(Type::infer("[[1,2],[3]]"),Type::Array(vec![
+            Type::Array(vec![Type::Int(1),Type::Int(2)]),
+            Type::Array(vec![Type::Int(3)]),
+        ]));
+        assert_eq!(Type::infer("[a,b"),text("[a,b"));
+        assert_eq!(Type::infer("[a]]"),text("[a]]"));
+    }
+}
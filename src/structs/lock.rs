@@ -0,0 +1,243 @@
+use Format;
+use SettingsError;
+use XdgDir;
+use structs::stamp;
+
+use failure::Error;
+use std::fs;
+use std::fs::{File,OpenOptions,TryLockError};
+use std::io;
+use std::path::{Path,PathBuf};
+use std::thread;
+use std::time::{Duration,Instant};
+
+/// An advisory lock on a file, released when it is dropped
+///
+/// Used by [Settings](struct.Settings.html) so programs running at the same
+/// time don't lose each other's changes. Loading takes a shared lock and
+/// saving takes an exclusive one, on a `{file_name}.{extension}.lock` file
+/// next to the configuration. Local files and layers are locked with a
+/// file in the `locks` folder of `XdgDir::State` instead, so nothing is
+/// left in the project. The lock files are left behind, removing them
+/// would race with other programs locking them.
+///
+/// Loading without the lock is fine when the lock file can't be made,
+/// like in a read only folder, since nobody can write there either.
+///
+/// The locks are only advisory, programs that don't use them (like a text
+/// editor) can still change the file.
+#[derive(Debug)]
+pub struct FileLock {
+    file : File,
+    path : PathBuf,
+    exclusive : bool,
+}
+
+impl FileLock {
+    pub fn shared<P>(path : P, timeout : Option<Duration>) -> Result<FileLock,Error> where P : AsRef<Path> {
+        //! Locks `path` for reading, any number of shared locks can be
+        //! held at once. Waits for an exclusive lock to be released, up to
+        //! `timeout` or forever if it is `None`.
+
+        FileLock::acquire(path.as_ref(),false,timeout)
+    }
+
+    pub fn exclusive<P>(path : P, timeout : Option<Duration>) -> Result<FileLock,Error> where P : AsRef<Path> {
+        //! Locks `path` for writing, waiting for all other locks to be
+        //! released, up to `timeout` or forever if it is `None`.
+        //!
+        //! Returns a [SettingsError::LockTimeout](enum.SettingsError.html)
+        //! when it can't get the lock in time.
+
+        FileLock::acquire(path.as_ref(),true,timeout)
+    }
+
+    fn acquire(path : &Path, exclusive : bool, timeout : Option<Duration>) -> Result<FileLock,Error> {
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)?;
+
+        match timeout {
+            None if exclusive => file.lock()?,
+            None => file.lock_shared()?,
+            Some(timeout) => {
+                let start = Instant::now();
+                loop {
+                    let result = if exclusive { file.try_lock() } else { file.try_lock_shared() };
+                    match result {
+                        Ok(()) => break,
+                        Err(TryLockError::WouldBlock) => (),
+                        Err(TryLockError::Error(error)) => return Err(error.into()),
+                    }
+                    if start.elapsed() >= timeout {
+                        return Err(SettingsError::LockTimeout { path : path.to_path_buf() }.into());
+                    }
+                    thread::sleep(Duration::from_millis(10));
+                }
+            },
+        }
+
+        Ok(FileLock { file, path : path.to_path_buf(), exclusive })
+    }
+
+    pub fn path(&self) -> &Path { &self.path }
+    pub fn is_exclusive(&self) -> bool { self.exclusive }
+}
+
+pub(crate) fn lock_file_for<T>(format : &T, path : &Path) -> Result<PathBuf,Error> where T : Format {
+    //! The lock file used for a file that isn't the global one, like a
+    //! local file or a layer. Kept in the state folder of `format` and
+    //! named after the file and a hash of where it is, so every file gets
+    //! its own.
+
+    // the file might not be there yet, but its folder is
+    let path = match (path.parent(),path.file_name()) {
+        (Some(dir),Some(name)) => fs::canonicalize(dir).map(|dir| dir.join(name)).unwrap_or_else(|_| path.to_path_buf()),
+        _ => path.to_path_buf(),
+    };
+    let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    let hash = stamp::hash(path.to_string_lossy().as_bytes());
+
+    let dir = format.get_dir(XdgDir::State)?.join("locks");
+    fs::create_dir_all(&dir)?;
+    Ok(dir.join(format!("{}.{}.lock",name,&hash[..16])))
+}
+
+pub(crate) fn unless_read_only(lock : Result<FileLock,Error>) -> Result<Option<FileLock>,Error> {
+    //! Goes on without the lock if it couldn't be made because the folder
+    //! can't be written to, used when loading.
+
+    match lock {
+        Ok(lock) => Ok(Some(lock)),
+        Err(error) => match error.downcast_ref::<io::Error>().map(|error| error.kind()) {
+            Some(io::ErrorKind::PermissionDenied) | Some(io::ErrorKind::ReadOnlyFilesystem) => {
+                warn!("Loading without a lock: {}",error);
+                Ok(None)
+            },
+            _ => Err(error),
+        },
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        // closing the file would unlock it too
+        let _ = self.file.unlock();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FileLock,lock_file_for,unless_read_only};
+    use Format;
+    use Settings;
+    use SettingsError;
+    use SettingsRaw;
+    use Type;
+
+    use failure::Error;
+    use std::fs;
+    use std::io;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    // keeps a single `count` in a folder
+    #[derive(Clone)]
+    struct Counter { base : PathBuf }
+    impl Format for Counter {
+        fn filename(&self) -> String { "settings".to_string() }
+        fn folder(&self) -> String { "app".to_string() }
+        fn base_dir(&self) -> Result<PathBuf,Error> { Ok(self.base.clone()) }
+        fn from_str(&self,buffer:&str) -> Result<SettingsRaw,Error> {
+            let mut raw = SettingsRaw::new();
+            raw.insert("count".to_string(),Type::infer(buffer));
+            Ok(raw)
+        }
+        fn to_string(&self,object:&SettingsRaw) -> Result<String,Error> {
+            Ok(object.get("count").map(|count| count.to_string()).unwrap_or_default())
+        }
+    }
+
+    #[test]
+    fn locking() {
+        let dir = ::tempfile::tempdir().unwrap();
+        let path = dir.path().join("settings.lock");
+        let timeout = Some(Duration::from_millis(50));
+
+        let first = FileLock::shared(&path,timeout).unwrap();
+        let second = FileLock::shared(&path,timeout).unwrap();
+        assert!(!first.is_exclusive());
+
+        let error = FileLock::exclusive(&path,timeout).unwrap_err();
+        assert_eq!(error.downcast_ref::<SettingsError>(),Some(&SettingsError::LockTimeout { path : path.clone() }));
+
+        drop(first);
+        drop(second);
+        let exclusive = FileLock::exclusive(&path,timeout).unwrap();
+        assert!(FileLock::shared(&path,timeout).is_err());
+        drop(exclusive);
+        assert!(FileLock::shared(&path,None).is_ok());
+    }
+
+    #[test]
+    fn read_only() {
+        let denied = io::Error::new(io::ErrorKind::PermissionDenied,"denied");
+        assert!(unless_read_only(Err(denied.into())).unwrap().is_none());
+        let read_only = io::Error::new(io::ErrorKind::ReadOnlyFilesystem,"read only");
+        assert!(unless_read_only(Err(read_only.into())).unwrap().is_none());
+
+        // anything else is still an error
+        let missing = io::Error::new(io::ErrorKind::NotFound,"missing");
+        assert!(unless_read_only(Err(missing.into())).is_err());
+        let timeout = SettingsError::LockTimeout { path : PathBuf::from("settings.lock") };
+        assert!(unless_read_only(Err(timeout.into())).is_err());
+    }
+
+    #[test]
+    fn lock_files() {
+        let dir = ::tempfile::tempdir().unwrap();
+        let base = dir.path().join("home");
+        let project = dir.path().join("project");
+        fs::create_dir_all(&project).unwrap();
+        let format = Counter { base : base.clone() };
+
+        // in the state folder, one for each file
+        let lock = lock_file_for(&format,&project.join("settings")).unwrap();
+        assert!(lock.starts_with(base.join("app").join("locks")));
+        assert!(lock.file_name().unwrap().to_string_lossy().starts_with("settings."));
+        assert_eq!(lock_file_for(&format,&project.join(".").join("settings")).unwrap(),lock);
+        assert!(lock_file_for(&format,&dir.path().join("settings")).unwrap() != lock);
+        assert_eq!(fs::read_dir(&project).unwrap().count(),0);
+    }
+
+    #[test]
+    fn updating() {
+        let dir = ::tempfile::tempdir().unwrap();
+        let format = Counter { base : dir.path().to_path_buf() };
+        let mut first = Settings::new(format.clone());
+        let mut second = Settings::new(format.clone());
+        second.set_lock_timeout(Some(Duration::from_millis(50)));
+        assert!(second.set_value("count",&1).is_ok());
+        assert!(second.save().is_ok());
+        assert!(format.get_lock_path_and_file().unwrap().exists());
+
+        {
+            let mut update = first.lock_for_update().unwrap();
+            assert!(update.lock().is_exclusive());
+            assert_eq!(update.get_value("count"),Some(Type::Int(1)));
+
+            // nobody else can use the file
+            assert!(second.save().is_err());
+            let error = second.load().unwrap_err();
+            assert_eq!(error.downcast_ref::<SettingsError>(),Some(&SettingsError::LockTimeout {
+                path : format.get_lock_path_and_file().unwrap(),
+            }));
+
+            assert!(update.set_value("count",&2).is_ok());
+            assert!(update.save().is_ok());
+            assert!(update.reload().is_ok());
+            assert_eq!(update.get_value("count"),Some(Type::Int(2)));
+        }
+
+        assert!(second.load().is_ok());
+        assert_eq!(second.get_value("count"),Some(Type::Int(2)));
+    }
+}
@@ -0,0 +1,209 @@
+use Type;
+
+use std::collections::HashMap;
+
+/// How arrays are combined when the same key is an array in more
+/// than one layer.
+#[derive(Serialize,Deserialize,Debug,Clone,PartialEq,Default)]
+pub enum ArrayMerge {
+    /// The higher layer's array replaces the lower one, the default.
+    #[default]
+    Replace,
+    /// The higher layer's items are added after the lower layer's items.
+    Append,
+    /// Items that are `Type::Complex` and have the same value for the
+    /// given field are merged together, everything else is appended.
+    MergeBy(String),
+}
+
+/// Controls how values from different layers are combined.
+///
+/// Complex values are always merged recursively, so a key set in a higher
+/// layer only hides that key and not its siblings. Arrays use the
+/// [ArrayMerge](enum.ArrayMerge.html) strategy, which can be set for all
+/// arrays or for a specific key path.
+///
+/// ```rust
+/// # extern crate settingsfile;
+/// use settingsfile::{ShadowSettings,MergeOptions,ArrayMerge};
+/// use settingsfile::EmptyConfig; // dumb config for examples and testing
+///
+/// let mut settings = ShadowSettings::new(EmptyConfig{});
+/// settings.set_merge_options(MergeOptions::new()
+///     .with_arrays(ArrayMerge::Append)
+///     .with_arrays_at("plugins",ArrayMerge::MergeBy("name".to_string())));
+/// ```
+#[derive(Serialize,Deserialize,Debug,Clone,PartialEq,Default)]
+pub struct MergeOptions {
+    arrays : ArrayMerge,
+    arrays_at : HashMap<String,ArrayMerge>,
+}
+
+impl MergeOptions {
+    pub fn new() -> MergeOptions {
+        //! Merges complex values recursively and replaces arrays.
+
+        MergeOptions::default()
+    }
+
+    pub fn with_arrays(mut self, strategy : ArrayMerge) -> MergeOptions {
+        //! Sets the strategy used for all arrays that don't have their own.
+
+        self.arrays = strategy;
+        self
+    }
+
+    pub fn with_arrays_at(mut self, key_path : &str, strategy : ArrayMerge) -> MergeOptions {
+        //! Sets the strategy used for the array at `key_path`.
+        //!
+        //! Arrays inside of array items use the path without the index,
+        //! so `plugins.hooks` is the `hooks` array of every item in `plugins`.
+
+        self.arrays_at.insert(key_path.to_string(),strategy);
+        self
+    }
+
+    pub fn arrays_for(&self, key_path : &str) -> &ArrayMerge {
+        //! The strategy that will be used for the array at `key_path`.
+
+        self.arrays_at.get(key_path).unwrap_or(&self.arrays)
+    }
+
+    pub fn merge(&self, key_path : &str, lower : Type, upper : Type) -> Type {
+        //! Combines two values found at `key_path`, `upper` being the value
+        //! from the layer with the higher priority.
+        //!
+        //! Anything that isn't two complexes or two arrays is taken
+        //! from `upper`.
+
+        match (lower,upper) {
+            (Type::Complex(mut lower), Type::Complex(upper)) => {
+                for (key,value) in upper {
+                    let path = join(key_path,&key);
+                    let merged = match lower.remove(&key) {
+                        Some(existing) => self.merge(&path,existing,value),
+                        None => value,
+                    };
+                    lower.insert(key,merged);
+                }
+                Type::Complex(lower)
+            },
+            (Type::Array(lower), Type::Array(upper)) => {
+                Type::Array(self.merge_arrays(key_path,lower,upper))
+            },
+            (_, upper) => upper,
+        }
+    }
+
+    fn merge_arrays(&self, key_path : &str, mut lower : Vec<Type>, upper : Vec<Type>) -> Vec<Type> {
+        match *self.arrays_for(key_path) {
+            ArrayMerge::Replace => upper,
+            ArrayMerge::Append => {
+                lower.extend(upper);
+                lower
+            },
+            ArrayMerge::MergeBy(ref field) => {
+                for item in upper {
+                    let matching = identity(&item,field).and_then(|id| {
+                        lower.iter().position(|existing| identity(existing,field) == Some(id))
+                    });
+                    match matching {
+                        Some(index) => {
+                            let existing = lower.remove(index);
+                            lower.insert(index,self.merge(key_path,existing,item));
+                        },
+                        None => lower.push(item),
+                    }
+                }
+                lower
+            },
+        }
+    }
+}
+
+fn identity<'a>(item : &'a Type, field : &str) -> Option<&'a Type> {
+    match *item {
+        Type::Complex(ref hash) => hash.get(field),
+        _ => None,
+    }
+}
+
+fn join(parent : &str, key : &str) -> String {
+    if parent.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}",parent,key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use Type;
+    use super::{MergeOptions,ArrayMerge};
+    use std::collections::HashMap;
+
+    fn complex(items : Vec<(&str,Type)>) -> Type {
+        let mut hash = HashMap::new();
+        for (key,value) in items {
+            hash.insert(key.to_string(),value);
+        }
+        Type::Complex(hash)
+    }
+
+    fn text(value : &str) -> Type { Type::Text(value.to_string()) }
+
+    #[test]
+    fn deep() {
+        let lower = complex(vec![("b",complex(vec![
+            ("c",complex(vec![("d",text("mortan")),("e",text("bobby lee"))])),
+            ("f",Type::Int(4453)),
+        ]))]);
+        let upper = complex(vec![("b",complex(vec![
+            ("c",complex(vec![("e",text("lee bo"))])),
+        ]))]);
+
+        let merged = MergeOptions::new().merge("a",lower,upper);
+        assert_eq!(merged,complex(vec![("b",complex(vec![
+            ("c",complex(vec![("d",text("mortan")),("e",text("lee bo"))])),
+            ("f",Type::Int(4453)),
+        ]))]));
+
+        // a value that isn't complex replaces the whole section
+        let merged = MergeOptions::new().merge("a",merged,complex(vec![("b",Type::Switch(false))]));
+        assert_eq!(merged,complex(vec![("b",Type::Switch(false))]));
+    }
+
+    #[test]
+    fn arrays() {
+        let lower = Type::Array(vec![Type::Int(1),Type::Int(2)]);
+        let upper = Type::Array(vec![Type::Int(3)]);
+
+        assert_eq!(MergeOptions::new().merge("list",lower.clone(),upper.clone()),upper);
+        assert_eq!(MergeOptions::new().with_arrays(ArrayMerge::Append).merge("list",lower.clone(),upper.clone()),
+            Type::Array(vec![Type::Int(1),Type::Int(2),Type::Int(3)]));
+
+        // only the named key is appended
+        let options = MergeOptions::new().with_arrays_at("other",ArrayMerge::Append);
+        assert_eq!(options.merge("list",lower.clone(),upper.clone()),upper);
+        assert_eq!(options.merge("other",lower,upper).to_array().unwrap().len(),3);
+    }
+
+    #[test]
+    fn arrays_by_identity() {
+        let lower = Type::Array(vec![
+            complex(vec![("name",text("lint")),("enabled",Type::Switch(true)),("level",Type::Int(2))]),
+            complex(vec![("name",text("format")),("enabled",Type::Switch(true))]),
+        ]);
+        let upper = Type::Array(vec![
+            complex(vec![("name",text("format")),("enabled",Type::Switch(false))]),
+            complex(vec![("name",text("test"))]),
+        ]);
+
+        let options = MergeOptions::new().with_arrays(ArrayMerge::MergeBy("name".to_string()));
+        assert_eq!(options.merge("plugins",lower,upper),Type::Array(vec![
+            complex(vec![("name",text("lint")),("enabled",Type::Switch(true)),("level",Type::Int(2))]),
+            complex(vec![("name",text("format")),("enabled",Type::Switch(false))]),
+            complex(vec![("name",text("test"))]),
+        ]));
+    }
+}
@@ -1,4 +1,19 @@
 pub mod settings;
 pub mod shadowsettings;
+pub mod layered;
 pub mod types;
-pub mod empty;
\ No newline at end of file
+pub mod empty;
+pub mod registry;
+pub mod origin;
+pub mod merge;
+pub mod literal;
+pub mod env;
+pub mod overrides;
+pub mod trust;
+pub mod discovery;
+pub mod state;
+pub mod lock;
+pub mod stamp;
+pub mod changes;
+pub mod subscribe;
+#[cfg(feature = "watch")] pub mod watch;
\ No newline at end of file
@@ -0,0 +1,46 @@
+use std::fmt;
+use std::path::{Path,PathBuf};
+
+/// Where a value came from
+///
+/// Returned by functions like `ShadowSettings::get_value_with_origin` to
+/// answer "why is this key set to that?". Contains the name of the layer
+/// that defined the value (`"global"` or `"local"` for a `ShadowSettings`),
+/// the file of that layer and, if the [Format](trait.Format.html) can find
+/// it, the line and column in that file.
+///
+/// The `Display` form is similar to `git config --show-origin`
+///
+/// ```text
+/// local:/home/user/project/settings.toml:4:1
+/// ```
+#[derive(Debug,Clone,PartialEq)]
+pub struct Origin {
+    layer : String,
+    path : Option<PathBuf>,
+    position : Option<(usize,usize)>,
+}
+
+impl Origin {
+    pub fn new<S:ToString>(layer : S, path : Option<PathBuf>, position : Option<(usize,usize)>) -> Origin {
+        Origin { layer : layer.to_string(), path, position }
+    }
+
+    pub fn layer(&self) -> &str { &self.layer }
+    pub fn path(&self) -> Option<&Path> { self.path.as_deref() }
+    pub fn line(&self) -> Option<usize> { self.position.map(|(line,_)| line) }
+    pub fn column(&self) -> Option<usize> { self.position.map(|(_,column)| column) }
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,"{}",self.layer)?;
+        if let Some(ref path) = self.path {
+            write!(f,":{}",path.display())?;
+            if let Some((line,column)) = self.position {
+                write!(f,":{}:{}",line,column)?;
+            }
+        }
+        Ok(())
+    }
+}
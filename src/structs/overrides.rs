@@ -0,0 +1,168 @@
+use Type;
+use SettingsRaw;
+use structs::types::{insert_path,get_path};
+
+use failure::Error;
+
+/// Settings given on the command line
+///
+/// Works like `git -c`, each override is a `key=value` pair with a dotted
+/// key, split on the first `=`. The value is converted with
+/// [Type::infer](enum.Type.html#method.infer), so `-c port=8080` is a
+/// `Type::Int` and `-c name='8080'` is a `Type::Text`.
+///
+/// Overrides are used over every other value when set on a
+/// [Settings](struct.Settings.html) or [ShadowSettings](struct.ShadowSettings.html)
+/// with `set_overrides`, or added to a [LayeredSettings](struct.LayeredSettings.html)
+/// with `push_overrides_layer`. They are never saved.
+///
+/// # Example
+///
+/// ```rust
+/// # extern crate settingsfile;
+/// use settingsfile::{Overrides,Type};
+///
+/// // usually `std::env::args()`
+/// let args = vec!["app","-c","user.name=snsvrno","build","--set","jobs=4"];
+/// let (overrides,args) = Overrides::from_args(args).unwrap();
+///
+/// assert_eq!(args,vec!["app","build"]);
+/// assert_eq!(overrides.get_value("jobs"),Some(Type::Int(4)));
+/// ```
+#[derive(Clone,Debug,Default)]
+pub struct Overrides {
+    values : SettingsRaw,
+    // in the order they were given
+    keys : Vec<String>,
+}
+
+impl Overrides {
+    pub fn new() -> Overrides {
+        //! Creates an empty set of overrides.
+
+        Overrides::default()
+    }
+
+    pub fn from_args<I,S>(args : I) -> Result<(Overrides,Vec<String>),Error>
+        where I : IntoIterator<Item=S>, S : ToString,
+    {
+        //! Takes all the `-c key=value`, `--set key=value` and `--set=key=value`
+        //! arguments and returns the overrides along with the rest of the
+        //! arguments, in the same order.
+        //!
+        //! Everything after a `--` is left alone.
+
+        let mut overrides = Overrides::new();
+        let mut remaining : Vec<String> = Vec::new();
+        let mut args = args.into_iter().map(|arg| arg.to_string());
+
+        while let Some(arg) = args.next() {
+            if arg == "--" {
+                remaining.push(arg);
+                remaining.extend(args);
+                break;
+            }
+
+            if arg == "-c" || arg == "--set" {
+                match args.next() {
+                    Some(pair) => overrides.add(&pair)?,
+                    None => return Err(format_err!("'{}' needs a key=value after it",arg)),
+                }
+            } else if let Some(pair) = arg.strip_prefix("--set=") {
+                overrides.add(pair)?;
+            } else {
+                remaining.push(arg);
+            }
+        }
+
+        Ok((overrides,remaining))
+    }
+
+    pub fn add(&mut self, pair : &str) -> Result<(),Error> {
+        //! Parses a `key=value` and adds it, replacing the value if the
+        //! key was already given.
+
+        let (key,value) = match pair.find('=') {
+            Some(equals) => (pair[..equals].trim(),&pair[equals+1..]),
+            None => return Err(format_err!("'{}' isn't a key=value",pair)),
+        };
+
+        if key.is_empty() || key.split('.').any(|part| part.is_empty()) {
+            return Err(format_err!("'{}' isn't a valid key",key));
+        }
+
+        self.set_value(key,Type::infer(value));
+        Ok(())
+    }
+
+    pub fn set_value(&mut self, key_path : &str, value : Type) {
+        //! Adds an override that is already a `Type`.
+
+        insert_path(&mut self.values,key_path,value);
+        self.keys.retain(|key| key != key_path);
+        self.keys.push(key_path.to_string());
+    }
+
+    pub fn get_value(&self, key_path : &str) -> Option<Type> {
+        get_path(&self.values,key_path).cloned()
+    }
+
+    pub(crate) fn values(&self) -> &SettingsRaw {
+        &self.values
+    }
+
+    pub fn keys(&self) -> Vec<String> {
+        //! All the keys that were given, in the order they were given.
+
+        self.keys.iter().filter(|key| self.get_value(key).is_some()).cloned().collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use Type;
+    use super::Overrides;
+
+    fn text(value : &str) -> Type { Type::Text(value.to_string()) }
+
+    #[test]
+    fn parsing() {
+        let mut overrides = Overrides::new();
+        assert!(overrides.add("user.name=snsvrno").is_ok());
+        assert!(overrides.add("url=https://example.com/?a=b").is_ok());
+        assert!(overrides.add("enabled=true").is_ok());
+        assert!(overrides.add("scale=1.5").is_ok());
+        assert!(overrides.add("list=[a,b]").is_ok());
+        assert!(overrides.add("quoted=\"42\"").is_ok());
+        assert!(overrides.add("empty=").is_ok());
+
+        assert!(overrides.add("no equals").is_err());
+        assert!(overrides.add("=value").is_err());
+        assert!(overrides.add("user..name=value").is_err());
+
+        assert_eq!(overrides.get_value("user.name"),Some(text("snsvrno")));
+        assert_eq!(overrides.get_value("url"),Some(text("https://example.com/?a=b")));
+        assert_eq!(overrides.get_value("enabled"),Some(Type::Switch(true)));
+        assert_eq!(overrides.get_value("scale"),Some(Type::Float(1.5)));
+        assert_eq!(overrides.get_value("list"),Some(Type::Array(vec![text("a"),text("b")])));
+        assert_eq!(overrides.get_value("quoted"),Some(text("42")));
+        assert_eq!(overrides.get_value("empty"),Some(text("")));
+        assert_eq!(overrides.keys()[0],"user.name");
+    }
+
+    #[test]
+    fn args() {
+        let args = vec!["app","-c","a=1","--set=b=2","run","--set","c=3","--","-c","d=4"];
+        let (overrides,rest) = Overrides::from_args(args).unwrap();
+        assert_eq!(rest,vec!["app","run","--","-c","d=4"]);
+        assert_eq!(overrides.keys(),vec!["a","b","c"]);
+        assert_eq!(overrides.get_value("d"),None);
+
+        assert!(Overrides::from_args(vec!["app","-c"]).is_err());
+        assert!(Overrides::from_args(vec!["app","-c","novalue"]).is_err());
+    }
+}
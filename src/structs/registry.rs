@@ -0,0 +1,462 @@
+use Format;
+use Location;
+use SettingsRaw;
+
+use failure::Error;
+use std::path::{Path,PathBuf};
+use std::sync::{Arc,Mutex};
+
+/// A `Format` that picks the real format at runtime.
+///
+/// Holds a list of formats keyed by their file extension, and uses
+/// whichever one matches the configuration that is found on disk, so
+/// one program can read either a `config.toml` or a `config.json`,
+/// whichever the user has.
+///
+/// The format used is decided in this order:
+///
+/// 1. the format set with [select](#method.select), so it can come from a user setting.
+/// 2. the first registered format that has a global file on disk.
+/// 3. the first registered format that has a local file on disk.
+/// 4. the first registered format.
+///
+/// What is found on disk is only looked for once, the first time it is
+/// needed, and kept until the registry is changed.
+///
+/// A local file can use a different format than the global one, it uses
+/// the first registered format that has a local file on disk, or the same
+/// format as the global file if there isn't one.
+///
+/// # Example
+///
+/// ```rust
+/// # extern crate settingsfile;
+/// use settingsfile::{Format,FormatRegistry,Settings,EmptyConfig};
+///
+/// let mut registry = FormatRegistry::new(".config/app","config");
+/// registry.register("txt",EmptyConfig{});
+/// assert_eq!(registry.get_filename(),"config.txt");
+///
+/// let settings = Settings::new(registry);
+/// ```
+#[derive(Clone)]
+pub struct FormatRegistry {
+    folder : String,
+    filename : String,
+    formats : Vec<(String,Arc<dyn Format + Send + Sync>)>,
+    selected : Option<String>,
+    found : Found,
+    location : Location,
+    base_dir : Option<PathBuf>,
+    local_dir : Option<PathBuf>,
+}
+
+// the extensions of the files found on disk, so the disk is only
+// looked at once.
+#[derive(Default)]
+struct Found {
+    global : Mutex<Option<String>>,
+    local : Mutex<Option<String>>,
+}
+
+impl Found {
+    fn get<F>(cell : &Mutex<Option<String>>, find : F) -> Option<String>
+        where F : FnOnce() -> Option<String>,
+    {
+        let mut found = match cell.lock() {
+            Ok(found) => found,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        if found.is_none() {
+            *found = find();
+        }
+        found.clone()
+    }
+
+    fn peek(cell : &Mutex<Option<String>>) -> Option<String> {
+        match cell.lock() {
+            Ok(found) => found.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+}
+
+impl Clone for Found {
+    fn clone(&self) -> Found {
+        Found {
+            global : Mutex::new(Found::peek(&self.global)),
+            local : Mutex::new(Found::peek(&self.local)),
+        }
+    }
+}
+
+impl FormatRegistry {
+    pub fn new<S1,S2>(folder : S1, filename : S2) -> FormatRegistry
+        where S1 : ToString, S2 : ToString,
+    {
+        //! Creates an empty registry for `~/{folder}/{filename}.{extension}`,
+        //! formats need to be added with `register` before it can be used.
+
+        FormatRegistry {
+            folder : folder.to_string(),
+            filename : filename.to_string(),
+            formats : Vec::new(),
+            selected : None,
+            found : Found::default(),
+            location : Location::Home,
+            base_dir : None,
+            local_dir : None,
+        }
+    }
+
+    pub fn with_location(mut self, location : Location) -> FormatRegistry {
+        //! Keeps the files in `location` instead of the home directory.
+
+        self.location = location;
+        self.found = Found::default();
+        self
+    }
+
+    pub fn with_base_dir<P>(mut self, dir : P) -> FormatRegistry
+        where P : Into<PathBuf>,
+    {
+        //! Uses `dir` instead of the home directory, see
+        //! [Format::base_dir](trait.Format.html#method.base_dir).
+
+        self.base_dir = Some(dir.into());
+        self.found = Found::default();
+        self
+    }
+
+    pub fn with_local_dir<P>(mut self, dir : P) -> FormatRegistry
+        where P : Into<PathBuf>,
+    {
+        //! Looks for the local file in `dir` instead of the current
+        //! directory, see [Format::local_dir](trait.Format.html#method.local_dir).
+
+        self.local_dir = Some(dir.into());
+        self.found = Found::default();
+        self
+    }
+
+    pub fn with_builtin<S1,S2>(folder : S1, filename : S2) -> FormatRegistry
+        where S1 : ToString, S2 : ToString,
+    {
+        //! Creates a registry with all of the [built in formats](formats/index.html)
+        //! that are enabled by features.
+
+        #[allow(unused_mut)]
+        let mut registry = FormatRegistry::new(folder,filename);
+
+        #[cfg(feature = "toml")]
+        registry.register("toml",::formats::Toml::new(&registry.folder,&registry.filename));
+        #[cfg(feature = "json")]
+        registry.register("json",::formats::Json::new(&registry.folder,&registry.filename));
+        #[cfg(feature = "yaml")] {
+            registry.register("yaml",::formats::Yaml::new(&registry.folder,&registry.filename));
+            registry.register("yml",::formats::Yaml::new(&registry.folder,&registry.filename));
+        }
+        #[cfg(feature = "ron")]
+        registry.register("ron",::formats::Ron::new(&registry.folder,&registry.filename));
+
+        registry
+    }
+
+    pub fn register<S,F>(&mut self, extension : S, format : F)
+        where S : ToString, F : Format + Send + Sync + 'static,
+    {
+        //! Adds a format that will be used for files with `extension`. Only
+        //! the format's `from_str` and `to_string` are used, the location
+        //! comes from the registry.
+        //!
+        //! Registering an extension that already exists will replace the
+        //! existing format.
+
+        let extension = extension.to_string().to_lowercase();
+        let format : Arc<dyn Format + Send + Sync> = Arc::new(format);
+
+        match self.formats.iter().position(|(ext,_)| *ext == extension) {
+            Some(index) => self.formats[index].1 = format,
+            None => self.formats.push((extension,format)),
+        }
+        self.found = Found::default();
+    }
+
+    pub fn extensions(&self) -> Vec<String> {
+        //! All the registered extensions, in the order they were registered.
+
+        self.formats.iter().map(|(ext,_)| ext.clone()).collect()
+    }
+
+    pub fn select(&mut self, extension : &str) -> Result<(),Error> {
+        //! Forces the registry to use the format registered for `extension`,
+        //! use this when the format comes from a user setting.
+
+        let extension = extension.to_lowercase();
+        if self.formats.iter().any(|(ext,_)| *ext == extension) {
+            self.selected = Some(extension);
+            self.found = Found::default();
+            Ok(())
+        } else {
+            Err(format_err!("No format registered for extension '{}'",extension))
+        }
+    }
+
+    pub fn clear_selection(&mut self) {
+        //! Goes back to picking the format from the files on disk, which
+        //! are looked for again.
+
+        self.selected = None;
+        self.found = Found::default();
+    }
+
+    pub fn format_for(&self, path : &Path) -> Option<&dyn Format> {
+        //! Gets the format that would be used for `path`, based on
+        //! its extension.
+
+        let extension = path.extension()?.to_string_lossy().to_lowercase();
+        self.get(&extension)
+    }
+
+    fn get(&self, extension : &str) -> Option<&dyn Format> {
+        for (ext,format) in self.formats.iter() {
+            if ext == extension {
+                let format : &dyn Format = format.as_ref();
+                return Some(format);
+            }
+        }
+        None
+    }
+
+    pub fn selected(&self) -> Option<String> {
+        //! The extension of the format that will be used for the global file.
+
+        if let Some(ref selected) = self.selected {
+            return Some(selected.clone());
+        }
+        Found::get(&self.found.global,|| self.find_global())
+    }
+
+    pub fn selected_local(&self) -> Option<String> {
+        //! The extension of the format that will be used for the local file.
+
+        Found::get(&self.found.local,|| self.find_local().or_else(|| self.selected()))
+    }
+
+    fn find_global(&self) -> Option<String> {
+        // the file that will be moved to the XDG folder counts as global
+        let legacy_folder = match self.location {
+            Location::Xdg => self.base_dir().ok().map(|dir| dir.join(&self.folder)),
+            Location::Home | Location::Portable => None,
+        };
+        let global_folders = self.get_path().ok().into_iter().chain(legacy_folder);
+
+        for global_folder in global_folders {
+            for (ext,_) in self.formats.iter() {
+                if global_folder.join(self.with_extension(ext)).exists() {
+                    return Some(ext.clone());
+                }
+            }
+        }
+
+        self.find_local().or_else(|| self.formats.first().map(|(ext,_)| ext.clone()))
+    }
+
+    fn find_local(&self) -> Option<String> {
+        let local_folder = self.local_dir().ok()?;
+        for (ext,_) in self.formats.iter() {
+            if local_folder.join(self.with_extension(ext)).exists() {
+                return Some(ext.clone());
+            }
+        }
+        None
+    }
+
+    fn with_extension(&self, extension : &str) -> String {
+        format!("{}.{}",self.filename,extension)
+    }
+
+    fn selected_format(&self) -> Result<&dyn Format,Error> {
+        match self.selected() {
+            None => Err(format_err!("No formats are registered")),
+            Some(extension) => match self.get(&extension) {
+                Some(format) => Ok(format),
+                None => Err(format_err!("No format registered for extension '{}'",extension)),
+            }
+        }
+    }
+}
+
+impl Format for FormatRegistry {
+    fn filename(&self) -> String { self.filename.clone() }
+    fn folder(&self) -> String { self.folder.clone() }
+    fn extension(&self) -> Option<String> { self.selected() }
+    fn local_filename(&self) -> Option<String> { Some(self.filename.clone()) }
+    fn local_extension(&self) -> Option<String> { self.selected_local() }
+    fn location(&self) -> Location { self.location }
+
+    fn active_location(&self) -> Location {
+        // any of the formats counts, which one is used depends on the location
+        if let Ok(dir) = self.exe_dir() {
+            let mut names : Vec<String> = self.formats.iter().map(|(ext,_)| self.with_extension(ext)).collect();
+            names.extend(self.portable_marker());
+            if ::paths::is_portable(&dir,&names) {
+                return Location::Portable;
+            }
+        }
+
+        self.location
+    }
+
+    fn base_dir(&self) -> Result<PathBuf,Error> {
+        match self.base_dir {
+            Some(ref dir) => Ok(dir.clone()),
+            None => ::paths::home_dir(),
+        }
+    }
+
+    fn local_dir(&self) -> Result<PathBuf,Error> {
+        match self.local_dir {
+            Some(ref dir) => Ok(dir.clone()),
+            None => ::paths::current_dir(),
+        }
+    }
+
+    fn select_for(&mut self, path : &Path) {
+        // keeps the current choice for extensions that aren't registered
+        if let Some(extension) = path.extension() {
+            let _ = self.select(&extension.to_string_lossy());
+        }
+    }
+
+    fn from_str(&self,buffer:&str) -> Result<SettingsRaw,Error> {
+        self.selected_format()?.from_str(buffer)
+    }
+
+    fn to_string(&self,object:&SettingsRaw) -> Result<String,Error> {
+        self.selected_format()?.to_string(object)
+    }
+
+    fn locate(&self,buffer:&str,key_path:&str) -> Option<(usize,usize)> {
+        self.selected_format().ok()?.locate(buffer,key_path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use Format;
+    use SettingsRaw;
+    use ShadowSettings;
+    use Type;
+    use FormatRegistry;
+
+    use failure::Error;
+    use std::fs;
+    use std::path::Path;
+
+    // formats that only say who they are.
+    #[derive(Clone)]
+    struct Named { name : &'static str }
+    impl Format for Named {
+        fn filename(&self) -> String { "".to_string() }
+        fn folder(&self) -> String { "".to_string() }
+
+        fn from_str(&self,_:&str) -> Result<SettingsRaw,Error> {
+            let mut hash = SettingsRaw::new();
+            hash.insert("format".to_string(),Type::Text(self.name.to_string()));
+            Ok(hash)
+        }
+        fn to_string(&self,_:&SettingsRaw) -> Result<String,Error> {
+            Ok(self.name.to_string())
+        }
+    }
+
+    fn registry() -> FormatRegistry {
+        let mut registry = FormatRegistry::new("settingsfile_registry_test_folder","config");
+        registry.register("one",Named { name : "one" });
+        registry.register("TWO",Named { name : "two" });
+        registry
+    }
+
+    #[test]
+    fn defaults_to_first() {
+        let registry = registry();
+        assert_eq!(registry.extensions(),vec!["one".to_string(),"two".to_string()]);
+        assert_eq!(registry.get_filename(),"config.one");
+        assert_eq!(registry.to_string(&SettingsRaw::new()).unwrap(),"one");
+    }
+
+    #[test]
+    fn selecting() {
+        let mut registry = registry();
+        assert!(registry.select("Two").is_ok());
+        assert_eq!(registry.get_filename(),"config.two");
+        assert_eq!(registry.from_str("").unwrap().get("format"),Some(&Type::Text("two".to_string())));
+
+        assert!(registry.select("three").is_err());
+        assert_eq!(registry.selected(),Some("two".to_string()));
+
+        registry.clear_selection();
+        assert_eq!(registry.selected(),Some("one".to_string()));
+    }
+
+    #[test]
+    fn by_path() {
+        let registry = registry();
+        let format = registry.format_for(Path::new("/some/where/config.two")).unwrap();
+        assert_eq!(format.to_string(&SettingsRaw::new()).unwrap(),"two");
+        assert!(registry.format_for(Path::new("config")).is_none());
+        assert!(registry.format_for(Path::new("config.three")).is_none());
+    }
+
+    #[test]
+    fn empty() {
+        let registry = FormatRegistry::new("folder","config");
+        assert!(registry.to_string(&SettingsRaw::new()).is_err());
+        assert_eq!(registry.get_filename(),"config");
+    }
+
+    #[test]
+    fn mixed_extensions() {
+        let base = ::tempfile::tempdir().unwrap();
+        let local = ::tempfile::tempdir().unwrap();
+        let registry = registry().with_base_dir(base.path()).with_local_dir(local.path());
+        let global_path = base.path().join("settingsfile_registry_test_folder").join("config.one");
+        fs::create_dir_all(global_path.parent().unwrap()).unwrap();
+        fs::write(&global_path,"global").unwrap();
+        fs::write(local.path().join("config.two"),"local").unwrap();
+
+        assert_eq!(registry.selected(),Some("one".to_string()));
+        assert_eq!(registry.selected_local(),Some("two".to_string()));
+        assert_eq!(registry.get_local_path_and_filename().unwrap(),local.path().join("config.two"));
+
+        let mut settings = ShadowSettings::new_and_load(registry.clone());
+        assert_eq!(settings.get_value_global("format"),Some(Type::Text("one".to_string())));
+        assert_eq!(settings.get_value_local("format"),Some(Type::Text("two".to_string())));
+
+        settings.set_value_local("size",&12).unwrap();
+        settings.save().unwrap();
+        assert_eq!(fs::read_to_string(&global_path).unwrap(),"one");
+        assert_eq!(fs::read_to_string(local.path().join("config.two")).unwrap(),"two");
+
+        // the files aren't looked for again
+        fs::remove_file(&global_path).unwrap();
+        assert_eq!(registry.selected(),Some("one".to_string()));
+    }
+
+    #[test]
+    fn layers_by_extension() {
+        use LayeredSettings;
+
+        let dir = ::tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("system.two"),"system").unwrap();
+        fs::write(dir.path().join("user.one"),"user").unwrap();
+
+        let mut settings = LayeredSettings::new(registry().with_base_dir(dir.path()));
+        settings.push_layer("system",Some(dir.path().join("system.two")),false).unwrap();
+        settings.push_layer("user",Some(dir.path().join("user.one")),true).unwrap();
+        settings.load().unwrap();
+        assert_eq!(settings.get_value_in("system","format"),Some(Type::Text("two".to_string())));
+        assert_eq!(settings.get_value_in("user","format"),Some(Type::Text("one".to_string())));
+    }
+}
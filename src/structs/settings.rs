@@ -2,12 +2,33 @@
 use Format;
 use Type;
 use SupportedType;
-
+use EnvSource;
+use Overrides;
+use SettingsRaw;
+use SettingsError;
+use structs::types::{insert_path,get_path,remove_path};
+use MergeOptions;
+use convert::{to_type,from_value};
+use paths;
+use atomic;
+use FileLock;
+use structs::lock::{lock_file_for,unless_read_only};
+use structs::stamp::{FileStamp,LastStamp};
+use structs::changes::Changes;
+use structs::subscribe::{Notifications,Subscribers,Subscription};
+use Location;
+
+use serde::ser::Serialize;
+use serde::de::DeserializeOwned;
 use std::ops::{Add,AddAssign};
 use std::io::prelude::*;
 use std::collections::HashMap;
 use std::fs::File;
 use std::fs;
+use std::mem;
+use std::path::{Path,PathBuf};
+use std::time::Duration;
+use std::ops::{Deref,DerefMut};
 use failure::Error;
 
 /// Basic one file settings
@@ -101,6 +122,48 @@ pub struct Settings<T> where T : Format + Clone {
     // the information of IO, where this file is located
     // and general details about the format.
     ioconfig: T,
+    // the text this was loaded from, used to find where
+    // keys are defined in the file.
+    #[serde(skip)]
+    source : Option<String>,
+    // read only values from the environment that are
+    // used over the values in the file, never saved.
+    #[serde(skip)]
+    env : Option<EnvSource>,
+    // values from the command line, used over everything
+    // else and never saved.
+    #[serde(skip)]
+    overrides : Option<Overrides>,
+    // values used when the file doesn't have the key, defined
+    // by the program.
+    #[serde(skip)]
+    defaults : SettingsRaw,
+    // when true, keys that are the same as the default aren't saved.
+    #[serde(skip)]
+    skip_defaults : bool,
+    // values set by an administrator that can't be changed,
+    // used over everything else.
+    #[serde(skip)]
+    policy : SettingsRaw,
+    // how long to wait for other programs to release the lock
+    // file, forever if `None`.
+    #[serde(skip,default = "default_lock_timeout")]
+    lock_timeout : Option<Duration>,
+    // the data as it was in the file when it was loaded or saved,
+    // and the keys changed or deleted since, used by `save_merged`.
+    #[serde(skip)]
+    changes : Changes,
+    // what the file looked like when it was last loaded or
+    // saved, so `save` doesn't write over someone else's changes.
+    #[serde(skip)]
+    stamp : LastStamp,
+    // callbacks for when values change, see `subscribe`.
+    #[serde(skip)]
+    subscribers : Subscribers,
+}
+
+fn default_lock_timeout() -> Option<Duration> {
+    Some(Duration::from_secs(10))
 }
 
 impl<T> Settings<T> where T : Format + Clone {
@@ -116,7 +179,17 @@ impl<T> Settings<T> where T : Format + Clone {
 
         Settings { 
             global : HashMap::new(),
-            ioconfig : config
+            ioconfig : config,
+            source : None,
+            env : None,
+            overrides : None,
+            defaults : HashMap::new(),
+            skip_defaults : false,
+            policy : HashMap::new(),
+            lock_timeout : default_lock_timeout(),
+            changes : Changes::default(),
+            stamp : LastStamp::default(),
+            subscribers : Subscribers::default(),
         } 
     }
 
@@ -147,7 +220,7 @@ impl<T> Settings<T> where T : Format + Clone {
         let mut new_hash = Settings::new(flat_hash.ioconfig.clone());
 
         for (key,value) in flat_hash.global.iter() {
-            if let Err(error) = new_hash.set_value(&key,&value) {
+            if let Err(error) = new_hash.set_value(key,&value) {
                 warn!("Error setting {}:{}, {}",key,value,error);
             }
         } 
@@ -169,16 +242,19 @@ impl<T> Settings<T> where T : Format + Clone {
         let mut buf : String = String::new();
         file.read_to_string(&mut buf)?;
 
-        // parses the string
-        if buf.len() > 0 {
-            let hash = Format::from_str::<T>(&config,&buf)?;
-            Ok(Settings{ 
-                global : hash,
-                ioconfig : config
-            })
-        } else { 
-            Ok(Settings{ global: HashMap::new(), ioconfig : config })
+        Settings::create_from_buffer(buf,config)
+    }
+
+    pub(crate) fn create_from_buffer(buf : String, config : T) -> Result<Settings<T>,Error> {
+        //! Same as `create_from`, with the contents already read.
+
+        let mut settings = Settings::new(config);
+        if !buf.is_empty() {
+            settings.global = settings.ioconfig.from_str(&buf)?;
+            settings.changes.reset(settings.global.clone());
+            settings.source = Some(buf);
         }
+        Ok(settings)
     }
 
     pub fn create_from_or_empty(file : &File, config : T) -> Settings<T> {
@@ -200,14 +276,46 @@ impl<T> Settings<T> where T : Format + Clone {
         //! `create_from` for that.
         //! 
         //! _Will override the existing data of a `Setting`_
+        //! 
+        //! With `Location::Xdg` a file in the old location is moved to the
+        //! new one first.
+        //! 
+        //! Takes a shared lock while reading, see [FileLock](struct.FileLock.html).
+        //! In a folder the lock file can't be made in, like a read only
+        //! one, the file is read without it.
         
-        let path = self.ioconfig.get_path_and_file();
-        info!("Loading from {}",path);
+        paths::migrate_legacy(&self.ioconfig)?;
+        let path = self.ioconfig.get_path_and_file()?;
+        // nothing to lock if the folder isn't there, there won't be a file either
+        let _lock = match path.parent() {
+            Some(dir) if dir.is_dir() => unless_read_only(self.lock(false))?,
+            _ => None,
+        };
+        self.load_unlocked(&path)
+    }
 
-        let mut file = File::open(&path)?;
-        info!("{} loaded.",path);
+    fn load_unlocked(&mut self, path : &Path) -> Result<(),Error> {
+        info!("Loading from {}",path.display());
+
+        let mut file = File::open(path)?;
+        info!("{} loaded.",path.display());
         
-        self.load_from(&mut file)
+        self.load_from(&mut file)?;
+        self.stamp_file(path)
+    }
+
+    pub(crate) fn stamp_file(&self, path : &Path) -> Result<(),Error> {
+        //! Remembers what the file at `path` looks like now.
+
+        self.stamp.set(FileStamp::read(path)?);
+        Ok(())
+    }
+
+    pub(crate) fn stamp_contents(&self, path : &Path, contents : &[u8]) -> Result<(),Error> {
+        //! Remembers the file at `path` had `contents` when it was read.
+
+        self.stamp.set(Some(FileStamp::with_contents(path,contents)?));
+        Ok(())
     }
 
     pub fn load_from(&mut self, file : &mut File) -> Result<(),Error> {
@@ -220,9 +328,14 @@ impl<T> Settings<T> where T : Format + Clone {
         file.read_to_string(&mut buf)?;
 
         // parses the string
-        if buf.len() > 0 {
-            let hash = Format::from_str::<T>(&self.ioconfig,&buf)?;
+        if !buf.is_empty() {
+            let hash = self.ioconfig.from_str(&buf)?;
+            let old = self.watched_snapshot();
+            self.changes.reset(hash.clone());
             self.global = hash;
+            self.notify_changes(old);
+            self.source = Some(buf);
+            self.stamp.set(None);
             Ok(())
         } else {
             Err(format_err!("Error loading from buffer"))
@@ -231,24 +344,247 @@ impl<T> Settings<T> where T : Format + Clone {
 
     pub fn save(&self) -> Result<(),Error> {
         //! Saves the setting to a file defined in the configuraton.
+        //! 
+        //! Takes an exclusive lock while writing, see [FileLock](struct.FileLock.html).
+        //! 
+        //! If the file was loaded and has been changed by something else
+        //! since, nothing is written and a
+        //! [SettingsError::ConcurrentModification](enum.SettingsError.html)
+        //! is returned. Use `load` to get the new file, `save_merged` to
+        //! keep both changes or `save_force` to write over it.
+
+        self.save_checked(true)
+    }
+
+    pub fn save_force(&self) -> Result<(),Error> {
+        //! Saves the setting like `save`, even if the file was changed
+        //! since it was loaded.
+
+        self.save_checked(false)
+    }
 
-        let path = self.ioconfig.get_path_and_file();
-        info!("Saving to {}",path);
+    fn save_checked(&self, check : bool) -> Result<(),Error> {
+        let path = self.ioconfig.get_path_and_file()?;
+        info!("Saving to {}",path.display());
         // first makes sure all the directories exist before attempting to create
         // the file, so it has a place to make it
-        fs::create_dir_all(self.ioconfig.get_path())?;
-        // creates the file, now that we know the directory exists
-        let mut file = File::create(path)?;
-        self.save_to(&mut file)
+        fs::create_dir_all(self.ioconfig.get_path()?)?;
+        let _lock = self.lock(true)?;
+        self.write_unlocked(&path,check)?;
+        Ok(())
+    }
+
+    fn write_unlocked(&self, path : &Path, check : bool) -> Result<String,Error> {
+        //! Writes the file and returns what was written.
+
+        if check && self.changed_on_disk(path)? {
+            return Err(SettingsError::ConcurrentModification { path : path.to_path_buf() }.into());
+        }
+        let buffer = self.ioconfig.to_string(&self.raw_to_save())?;
+        atomic::write(path,buffer.as_bytes())?;
+        self.stamp_contents(path,buffer.as_bytes())?;
+        self.changes.reset(self.global.clone());
+        Ok(buffer)
+    }
+
+    pub(crate) fn save_at(&self, path : &Path, check : bool) -> Result<String,Error> {
+        //! Saves to another file than the configured one, like a local
+        //! file or a layer, with the same lock and check as `save`.
+        //! Returns what was written.
+
+        let _lock = FileLock::exclusive(lock_file_for(&self.ioconfig,path)?,self.lock_timeout)?;
+        self.write_unlocked(path,check)
+    }
+
+    pub(crate) fn read_at(&self, path : &Path) -> Result<Vec<u8>,Error> {
+        //! Reads another file than the configured one under a shared lock,
+        //! or without it when the lock can't be made, like `load`.
+
+        let lock = lock_file_for(&self.ioconfig,path).and_then(|lock| FileLock::shared(lock,self.lock_timeout));
+        let _lock = unless_read_only(lock)?;
+        Ok(fs::read(path)?)
+    }
+
+    pub fn has_changed_on_disk(&self) -> Result<bool,Error> {
+        //! Checks if the file was changed by something else since it was
+        //! loaded or saved, by its modified time, size and contents. Always
+        //! `false` if it wasn't loaded from the file, or if the file was
+        //! deleted since.
+
+        self.changed_on_disk(&self.ioconfig.get_path_and_file()?)
+    }
+
+    fn changed_on_disk(&self, path : &Path) -> Result<bool,Error> {
+        match self.stamp.get() {
+            // a file that was deleted can be made again
+            Some(_) if !path.exists() => Ok(false),
+            Some(stamp) => Ok(!stamp.is_current(path)?),
+            None => Ok(false),
+        }
+    }
+
+    pub fn save_merged(&mut self) -> Result<(),Error> {
+        //! Saves only the keys that were changed or deleted since the file
+        //! was loaded, see [dirty_keys](#method.dirty_keys). The file is read
+        //! again and everything else in it is kept, so changes another
+        //! program made to other keys aren't lost.
+        //!
+        //! If a key was changed in the file too, to something else than
+        //! what we have, nothing is written and a
+        //! [SettingsError::Conflict](enum.SettingsError.html) lists the keys.
+        //! Use `save_force` to write ours anyway, or `load` to take theirs.
+        //!
+        //! ```rust,no_run
+        //! # extern crate settingsfile;
+        //! use settingsfile::{Settings,SettingsError};
+        //! use settingsfile::EmptyConfig; // dumb config for examples and testing
+        //!
+        //! let mut settings = Settings::new_and_load(EmptyConfig{});
+        //! settings.set_value("user.name","snsvrno").unwrap();
+        //! if let Err(error) = settings.save_merged() {
+        //!     match error.downcast_ref::<SettingsError>() {
+        //!         Some(SettingsError::Conflict { keys }) => println!("someone else changed {:?}",keys),
+        //!         _ => println!("{}",error),
+        //!     }
+        //! }
+        //! ```
+
+        let path = self.ioconfig.get_path_and_file()?;
+        info!("Saving changes to {}",path.display());
+        fs::create_dir_all(self.ioconfig.get_path()?)?;
+        let _lock = self.lock(true)?;
+
+        let mut merged = SettingsRaw::new();
+        if path.exists() {
+            let buffer = fs::read_to_string(&path)?;
+            if !buffer.is_empty() {
+                merged = self.ioconfig.from_str(&buffer)?;
+            }
+        }
+
+        let loaded = self.changes.loaded();
+        let mut conflicts : Vec<String> = Vec::new();
+        for key in self.changes.dirty().iter() {
+            let ours = get_path(&self.global,key);
+            let theirs = get_path(&merged,key);
+            if theirs != get_path(&loaded,key) && theirs != ours {
+                conflicts.push(key.to_string());
+                continue;
+            }
+            match ours.cloned() {
+                Some(value) => insert_path(&mut merged,key,value),
+                None => { remove_path(&mut merged,key); },
+            }
+        }
+        if !conflicts.is_empty() {
+            return Err(SettingsError::Conflict { keys : conflicts }.into());
+        }
+
+        let old = self.watched_snapshot();
+        self.global = merged;
+        self.notify_changes(old);
+        let buffer = self.ioconfig.to_string(&self.raw_to_save())?;
+        atomic::write(&path,buffer.as_bytes())?;
+        self.stamp_file(&path)?;
+        self.changes.reset(self.global.clone());
+        self.source = Some(buffer);
+        Ok(())
+    }
+
+    pub fn dirty_keys(&self) -> Vec<String> {
+        //! The keys that were set or deleted since the file was loaded or
+        //! saved, sorted. Cleared by `load` and every kind of save.
+
+        self.changes.dirty()
+    }
+
+    pub fn lock_for_update<'a>(&'a mut self) -> Result<UpdateGuard<'a,T>,Error> {
+        //! Locks the file so no other program can change it, and loads it
+        //! again, so the changes are made to what is on the disk.
+        //! 
+        //! The lock is kept until the guard is dropped, so use the guard's
+        //! `save` to write the changes.
+        //! 
+        //! ```rust,no_run
+        //! # extern crate settingsfile;
+        //! use settingsfile::{Settings,Type};
+        //! use settingsfile::EmptyConfig; // dumb config for examples and testing
+        //! 
+        //! let mut settings = Settings::new(EmptyConfig{});
+        //! {
+        //!     let mut update = settings.lock_for_update().unwrap();
+        //!     let runs = match update.get_value("runs") {
+        //!         Some(Type::Int(runs)) => runs,
+        //!         _ => 0,
+        //!     };
+        //!     update.set_value("runs",&(runs + 1)).unwrap();
+        //!     update.save().unwrap();
+        //! }
+        //! ```
+
+        let path = self.ioconfig.get_path_and_file()?;
+        fs::create_dir_all(self.ioconfig.get_path()?)?;
+        let lock = self.lock(true)?;
+        if path.exists() {
+            self.load_unlocked(&path)?;
+        }
+        Ok(UpdateGuard { settings : self, lock, path })
+    }
+
+    fn lock(&self, exclusive : bool) -> Result<FileLock,Error> {
+        let path = self.ioconfig.get_lock_path_and_file()?;
+        if exclusive {
+            FileLock::exclusive(path,self.lock_timeout)
+        } else {
+            FileLock::shared(path,self.lock_timeout)
+        }
+    }
+
+    pub fn set_lock_timeout(&mut self, timeout : Option<Duration>) {
+        //! How long to wait for other programs to release the lock on the
+        //! file before giving up with a [SettingsError::LockTimeout](enum.SettingsError.html),
+        //! `None` waits forever. The default is 10 seconds.
+
+        self.lock_timeout = timeout;
+    }
+
+    pub fn lock_timeout(&self) -> Option<Duration> {
+        self.lock_timeout
+    }
+
+    pub fn save_to_path(&self, path : &Path) -> Result<(),Error> {
+        //! Saves the setting to the file at `path`.
+        //! 
+        //! The file is replaced all at once, so a crash or a full disk
+        //! never leaves it half written. It keeps its permissions.
+
+        let buffer = self.ioconfig.to_string(&self.raw_to_save())?;
+        atomic::write(path,buffer.as_bytes())
+    }
+
+    pub fn path(&self) -> Result<PathBuf,Error> {
+        //! The file that `load` and `save` use.
+
+        self.ioconfig.get_path_and_file()
+    }
+
+    pub fn location(&self) -> Location {
+        //! Where the file is kept, `Location::Portable` if portable mode
+        //! was turned on by a file next to the executable.
+
+        self.ioconfig.active_location()
     }
 
     pub fn save_to(&self, mut file : &File) -> Result<(),Error> {
         //! saves the setting to a file buffer.
+        //! 
+        //! Keys that are the same as their default are left out if
+        //! [set_skip_defaults](#method.set_skip_defaults) is on.
 
-        match self.ioconfig.to_string(&self.global){
-            Err(error) => return Err(error),
+        match self.ioconfig.to_string(&self.raw_to_save()){
+            Err(error) => Err(error),
             Ok(settings_string) => {
-                match file.write(settings_string.as_bytes()){
+                match file.write_all(settings_string.as_bytes()){
                     Ok(_) => Ok(()),
                     Err(error) => Err(format_err!("{}",error)),
                 }
@@ -256,6 +592,24 @@ impl<T> Settings<T> where T : Format + Clone {
         }
     }
 
+    fn raw_to_save(&self) -> SettingsRaw {
+        //! The data that will be written to the file.
+
+        if !self.skip_defaults || self.defaults.is_empty() {
+            return self.global.clone();
+        }
+
+        let mut raw = SettingsRaw::new();
+        if let Type::Complex(flat) = Type::Complex(self.global.clone()).flatten(None) {
+            for (key,value) in flat {
+                if get_path(&self.defaults,&key) != Some(&value) {
+                    insert_path(&mut raw,&key,value);
+                }
+            }
+        }
+        raw
+    }
+
     // io - object functions ///////////////////////////////////////////////////////////////////
     // interactions with the `Settings` struct data
 
@@ -270,11 +624,7 @@ impl<T> Settings<T> where T : Format + Clone {
         //! will not work as it will attempt to split the key and it will find 
         //! nothing, this function will _NEVER_ split the key
         
-        if let Some(result) = self.global.get(key_path) {
-            return Some(result.clone());
-        } else {
-            return None;
-        }
+        self.global.get(key_path).cloned()
     }
 
     pub fn get_value(&self, key_path : &str) -> Option<Type> {
@@ -282,21 +632,219 @@ impl<T> Settings<T> where T : Format + Clone {
         //! 
         //! Looks for a `key_path` in dot notation and returns an `Option` 
         //! containing the value if it exists.
-        
+        //! 
+        //! If an [EnvSource](struct.EnvSource.html) or [Overrides](struct.Overrides.html)
+        //! are set, their values are used over the values from the file. If
+        //! the file doesn't have the key then the [default](#method.set_defaults)
+        //! is used. Keys [locked](#method.set_policy) by a policy always use
+        //! the policy's value.
+
+        let default = self.get_default(key_path);
+        let value = self.get_value_file(key_path);
+        let env = match self.env {
+            Some(ref env) => env.get_value(key_path),
+            None => None,
+        };
+        let overrides = match self.overrides {
+            Some(ref overrides) => overrides.get_value(key_path),
+            None => None,
+        };
+        let policy = self.get_policy_value(key_path);
+
+        let mut result : Option<Type> = None;
+        for value in vec![default,value,env,overrides,policy].into_iter().flatten() {
+            result = Some(match result {
+                None => value,
+                Some(lower) => MergeOptions::new().merge(key_path,lower,value),
+            });
+        }
+        result
+    }
+
+    pub fn set_env_source(&mut self, source : EnvSource) {
+        //! Uses the environment variables from `source` over the values
+        //! in the file. The environment is read only and is never saved.
+
+        self.env = Some(source);
+    }
+
+    pub fn env_source(&self) -> Option<&EnvSource> {
+        self.env.as_ref()
+    }
+
+    pub fn set_defaults(&mut self, defaults : SettingsRaw) {
+        //! Sets the values that are used when a key isn't in the file,
+        //! replacing any existing defaults.
+        //! 
+        //! ```rust
+        //! # extern crate settingsfile;
+        //! use settingsfile::{Settings,SettingsRaw,Type};
+        //! use settingsfile::EmptyConfig; // dumb config for examples and testing
+        //! 
+        //! let mut settings = Settings::new(EmptyConfig{});
+        //! settings.set_defaults(SettingsRaw::new());
+        //! settings.set_default("font.size",&12);
+        //! 
+        //! assert_eq!(settings.get_value("font.size"),Some(Type::Int(12)));
+        //! assert!(settings.is_default("font.size"));
+        //! 
+        //! settings.set_value("font.size",&14).unwrap();
+        //! assert!(!settings.is_default("font.size"));
+        //! 
+        //! settings.reset_to_default("font.size");
+        //! assert_eq!(settings.get_value("font.size"),Some(Type::Int(12)));
+        //! ```
+
+        self.defaults = defaults;
+    }
+
+    pub fn set_defaults_from_str(&mut self, buffer : &str) -> Result<(),Error> {
+        //! Parses the defaults with the `Format`, so they can be kept in
+        //! a file that is embedded with `include_str!`.
+
+        self.defaults = self.ioconfig.from_str(buffer)?;
+        Ok(())
+    }
+
+    pub fn set_default<A>(&mut self, key_path : &str, value : &A)
+        where A : SupportedType + ?Sized,
+    {
+        //! Sets the default value for one key.
+
+        insert_path(&mut self.defaults,key_path,value.wrap());
+    }
+
+    pub fn defaults(&self) -> &SettingsRaw {
+        &self.defaults
+    }
+
+    pub fn get_default(&self, key_path : &str) -> Option<Type> {
+        //! Gets the default value of `key_path`.
+
+        get_path(&self.defaults,key_path).cloned()
+    }
+
+    pub fn is_default(&self, key_path : &str) -> bool {
+        //! Checks if the value in the file is the default, either because
+        //! it isn't in the file or it is the same as the default.
+        //! 
+        //! Always false for keys that don't have a default.
+
+        match self.get_default(key_path) {
+            None => false,
+            Some(default) => match self.get_value_file(key_path) {
+                None => true,
+                Some(value) => MergeOptions::new().merge(key_path,default.clone(),value) == default,
+            }
+        }
+    }
+
+    pub fn reset_to_default(&mut self, key_path : &str) -> Option<Type> {
+        //! Removes the key from the file so the default is used again,
+        //! returns the value that was removed.
+
+        self.delete_key(key_path)
+    }
+
+    pub fn set_skip_defaults(&mut self, skip : bool) {
+        //! When `true`, keys that are the same as their default aren't
+        //! written when saving, so changing a default in a later version
+        //! changes it for everyone that didn't pick their own value.
+
+        self.skip_defaults = skip;
+    }
+
+    pub fn skips_defaults(&self) -> bool {
+        self.skip_defaults
+    }
+
+    pub fn set_policy(&mut self, policy : SettingsRaw) {
+        //! Sets the policy, the keys in it are locked. Their values are used
+        //! over everything else, and trying to set them returns a
+        //! [SettingsError::Locked](enum.SettingsError.html).
+        //! 
+        //! Meant for managed machines, where an administrator pins some
+        //! settings like `update.channel`.
+
+        self.policy = policy;
+    }
+
+    pub fn set_policy_from_str(&mut self, buffer : &str) -> Result<(),Error> {
+        //! Parses the policy with the `Format`.
+
+        self.policy = self.ioconfig.from_str(buffer)?;
+        Ok(())
+    }
+
+    pub fn set_policy_value<A>(&mut self, key_path : &str, value : &A)
+        where A : SupportedType + ?Sized,
+    {
+        //! Locks one key to the value.
+
+        insert_path(&mut self.policy,key_path,value.wrap());
+    }
+
+    pub fn policy(&self) -> &SettingsRaw {
+        &self.policy
+    }
+
+    pub fn get_policy_value(&self, key_path : &str) -> Option<Type> {
+        //! Gets the value the policy sets for `key_path`.
+
+        get_path(&self.policy,key_path).cloned()
+    }
+
+    pub fn is_locked(&self, key_path : &str) -> bool {
+        //! Checks if the value can't be changed because of the policy,
+        //! either because the policy sets the key, one of its parents,
+        //! or something inside of it.
+
+        if get_path(&self.policy,key_path).is_some() {
+            return true;
+        }
+
+        // a parent that is a value and not a section locks everything under it
+        let mut path = String::new();
+        for part in key_path.split('.') {
+            if !path.is_empty() { path.push('.'); }
+            path.push_str(part);
+            match get_path(&self.policy,&path) {
+                Some(value) if !value.is_complex() => return true,
+                _ => (),
+            }
+        }
+        false
+    }
+
+    pub fn set_overrides(&mut self, overrides : Overrides) {
+        //! Uses the command line `overrides` over every other value,
+        //! they are never saved.
+
+        self.overrides = Some(overrides);
+    }
+
+    pub fn overrides(&self) -> Option<&Overrides> {
+        self.overrides.as_ref()
+    }
+
+    pub fn get_value_file(&self, key_path : &str) -> Option<Type> {
+        //! Gets the value only from the file, without the defaults,
+        //! environment or overrides.
+
         let path_tree : Vec<&str> = key_path.split(".").collect();
         let mut subtree : &Type = &Type::Text("Empty".to_string());
 
         // TODO: need to fix this in order to have full unicode support. 
         // need to use .chars() instead of slice.
-        for i in 0..path_tree.len() {
+        for (i,key) in path_tree.iter().enumerate() {
             if i == 0 { 
-                if let Some(ref part) = self.global.get(&path_tree[i].to_string()) {
+                if let Some(part) = self.global.get(*key) {
                     subtree = part;
                 } else { return None }
             } else {
                 match *subtree {
                     Type::Complex(ref hash) => { 
-                        if let Some(ref part) = hash.get(&path_tree[i].to_string()) {
+                        if let Some(part) = hash.get(*key) {
                             subtree = part;
                         } else { return None }
                     },
@@ -305,11 +853,31 @@ impl<T> Settings<T> where T : Format + Clone {
             }
         }
 
-        return Some(subtree.clone());
+        Some(subtree.clone())
     }
 
-    pub fn get_value_or<A:?Sized>(&self, key_path : &str, default_value : &A) -> Type
-        where A : SupportedType, 
+    pub fn is_unset(&self, key_path : &str) -> bool {
+        //! Checks if `key_path`, or one of its parents, is a
+        //! [Type::Unset](enum.Type.html#variant.Unset).
+        //! 
+        //! A plain `Settings` returns the `Type::Unset` like any other value,
+        //! it is used by [ShadowSettings](struct.ShadowSettings.html) and
+        //! [LayeredSettings](struct.LayeredSettings.html) to hide the
+        //! values from lower priority files.
+
+        let mut path = String::new();
+        for part in key_path.split('.') {
+            if !path.is_empty() { path.push('.'); }
+            path.push_str(part);
+            if let Some(Type::Unset) = self.get_value(&path) {
+                return true;
+            }
+        }
+        false
+    }
+
+    pub fn get_value_or<A>(&self, key_path : &str, default_value : &A) -> Type
+        where A : SupportedType + ?Sized, 
     {
         //! Wraps `get_value` so instead of an `Option` the result will always be a type.
         //! 
@@ -321,11 +889,62 @@ impl<T> Settings<T> where T : Format + Clone {
         }
     }
 
-    pub fn set_value<A:?Sized>(&mut self, key_path : &str, value : &A) -> Result<(),Error> 
-        where A : SupportedType ,
+    pub fn get<A>(&self, key_path : &str) -> Result<A,Error>
+        where A : DeserializeOwned,
+    {
+        //! Gets the value at `key_path` and deserializes it into any type that
+        //! implements serde's `Deserialize`, so whole subtrees can be read straight
+        //! into a struct.
+        //!
+        //! If the key doesn't exist this will error, unless `A` can be built from
+        //! nothing (like an `Option`), so `get::<Option<A>>` can be used for optional keys.
+        //!
+        //! ```rust
+        //! # extern crate settingsfile;
+        //! # #[macro_use] extern crate serde_derive;
+        //! use settingsfile::Settings;
+        //! use settingsfile::EmptyConfig;
+        //!
+        //! #[derive(Serialize,Deserialize,PartialEq,Debug)]
+        //! struct Theme { name : String, size : i32 }
+        //!
+        //! # fn main() {
+        //! let mut settings = Settings::new(EmptyConfig{});
+        //! settings.set("ui.theme",&Theme { name : "dark".to_string(), size : 12 }).unwrap();
+        //!
+        //! let theme : Theme = settings.get("ui.theme").unwrap();
+        //! assert_eq!(theme.name,"dark");
+        //! assert_eq!(settings.get::<i32>("ui.theme.size").unwrap(),12);
+        //! assert_eq!(settings.get::<Option<i32>>("ui.theme.weight").unwrap(),None);
+        //! # }
+        //! ```
+
+        from_value(key_path,self.get_value(key_path))
+    }
+
+    pub fn set<A>(&mut self, key_path : &str, value : &A) -> Result<(),Error>
+        where A : Serialize + ?Sized,
+    {
+        //! Sets the value of a key using anything that implements serde's
+        //! `Serialize`, storing it as a tree of [Type](enum.Type.html)s.
+
+        let value = to_type(value).map_err(|error| error.under(key_path))?;
+        self.set_value(key_path,&value)
+    }
+
+    pub fn set_value<A>(&mut self, key_path : &str, value : &A) -> Result<(),Error> 
+        where A : SupportedType + ?Sized,
     {
         //! sets the value of a key, uses a generic that must implement
         //! the [SupportedType](traits.SupportedType.html) trait
+        //! 
+        //! Returns a [SettingsError::Locked](enum.SettingsError.html) if the
+        //! key is locked by the policy.
+
+        if self.is_locked(key_path) {
+            return Err(SettingsError::Locked { key : key_path.to_string() }.into());
+        }
+        let old = self.watched_snapshot();
         
         let mut global : Vec<Type> = Vec::new();
         let path_tree : Vec<&str> = key_path.split(".").collect();
@@ -333,7 +952,7 @@ impl<T> Settings<T> where T : Format + Clone {
         // goes through the split up key_path
         // will run even if there is only 1 element in the split
         // path.
-        for i in 0..path_tree.len()-1 {
+        for (i,key) in path_tree[..path_tree.len()-1].iter().enumerate() {
             // if this is the first part then we want to initalize
             // all the elements because we will be going into this element
             // deeper with each step down the key_path 
@@ -341,11 +960,9 @@ impl<T> Settings<T> where T : Format + Clone {
                 // checks if this is part of an existing setting,
                 // if it is then it will add it with pull it out of the setting
                 // and adde it to the global vector
-                if let Some(part) = self.global.remove(&path_tree[i].to_string()) {
-                    if let Type::Complex(hash) = part { 
-                        global.push(Type::Complex(hash)); 
-                    } else { global.push(Type::Complex(HashMap::new())); }
-                // if this doesn't exist then we will create a new item.
+                if let Some(Type::Complex(hash)) = self.global.remove(*key) {
+                    global.push(Type::Complex(hash)); 
+                // if this doesn't exist, or isn't a complex, then we will create a new item.
                 } else { global.push(Type::Complex(HashMap::new())); }
             // now for the rest we can work with the existing object
             // we pulled out where `i == 0`
@@ -356,10 +973,8 @@ impl<T> Settings<T> where T : Format + Clone {
                 //, if it isn't a complex then we will override whatever is there with
                 // a new blank complex.
                 if let Type::Complex(ref mut mut_parts) = global[index] {
-                    if let Some(part) = mut_parts.remove(&path_tree[i].to_string()) {
-                        if let Type::Complex(hash) = part { 
-                            push_me = Some(Type::Complex(hash));
-                        }
+                    if let Some(Type::Complex(hash)) = mut_parts.remove(*key) {
+                        push_me = Some(Type::Complex(hash));
                     }
                 }
                 // the above section pulled out the hashmap that exists (if one exists)
@@ -388,6 +1003,8 @@ impl<T> Settings<T> where T : Format + Clone {
         // inserts the last part of the global list into the 
         // settings
         self.global.insert(path_tree[0].to_string(),global.remove(0));
+        self.changes.mark(key_path);
+        self.notify_changes(old);
 
         Ok(())
     }
@@ -396,68 +1013,155 @@ impl<T> Settings<T> where T : Format + Clone {
         //! Deletes the key and returns the current value, 
         //! returns none if the key didn't exist.
         
-        let mut global : Vec<Type> = Vec::new();
-        let path_tree : Vec<&str> = key_path.split(".").collect();
-        let mut returned_value : Option<Type> = None;
+        let old = self.watched_snapshot();
+        let value = remove_path(&mut self.global,key_path);
+        if value.is_some() {
+            self.changes.mark(key_path);
+            self.notify_changes(old);
+        }
+        value
+    }
 
-        for i in 0..path_tree.len()-1 {
-            if i == 0 {
-                if let Some(part) = self.global.remove(&path_tree[i].to_string()) {
-                    if let Type::Complex(hash) = part { 
-                        global.push(Type::Complex(hash)); 
-                    } else { global.push(Type::Complex(HashMap::new())); }
-                } else { global.push(Type::Complex(HashMap::new())); }
-            } else {
-                let index = global.len()-1;
-                let mut push_me : Option<Type> = None;
-                if let Type::Complex(ref mut mut_parts) = global[index] {
-                    if let Some(part) = mut_parts.remove(&path_tree[i].to_string()) {
-                        if let Type::Complex(hash) = part { 
-                            push_me = Some(Type::Complex(hash));
-                        }
-                    }
-                }
-                match push_me {
-                    None => global.push(Type::None),
-                    Some(push_me) => global.push(push_me)
-                }
-            }
+    pub fn subscribe<F>(&self, pattern : &str, callback : F) -> Subscription
+        where F : FnMut(&str,Option<&Type>,Option<&Type>) + Send + 'static,
+    {
+        //! Calls `callback` with the key path, the old value and the new
+        //! value every time a value matching `pattern` changes, until the
+        //! returned [Subscription](struct.Subscription.html) is dropped.
+        //! 
+        //! `ui.theme` matches only that key, `keybindings.*` every key
+        //! under `keybindings` and `*` everything. A callback for `ui.theme`
+        //! is called with the whole table when a key under it, like
+        //! `ui.theme.color`, changes. Callbacks are called for
+        //! every key that changed by `set_value`, `delete_key`, `+=`, loading
+        //! and `save_merged`, with the values `get_value` gives, so a key that
+        //! is deleted gets its default. They can't use this `Settings`.
+        //! 
+        //! ```rust
+        //! # extern crate settingsfile;
+        //! use settingsfile::{Settings,Type};
+        //! use settingsfile::EmptyConfig; // dumb config for examples and testing
+        //! 
+        //! let mut settings = Settings::new(EmptyConfig{});
+        //! let subscription = settings.subscribe("ui.theme",|key,old,new| {
+        //!     println!("{} changed from {:?} to {:?}",key,old,new);
+        //! });
+        //! settings.set_value("ui.theme","dark").unwrap();
+        //! drop(subscription);
+        //! ```
+
+        self.subscribers.add(pattern,Box::new(callback))
+    }
+
+    pub(crate) fn take_subscribers(&mut self, old : &mut Settings<T>) -> Notifications {
+        //! Moves the callbacks of `old` to this `Settings` that replaces
+        //! it, returning the calls for the values that are different.
+
+        self.subscribers = mem::take(&mut old.subscribers);
+        let snapshot = old.watched_snapshot_of(&self.subscribers);
+        self.changes_since(snapshot)
+    }
+
+    fn watched_snapshot(&self) -> Option<SettingsRaw> {
+        //! The data before a change, if anyone subscribed to it.
+
+        self.watched_snapshot_of(&self.subscribers)
+    }
+
+    fn watched_snapshot_of(&self, subscribers : &Subscribers) -> Option<SettingsRaw> {
+        if subscribers.is_empty() {
+            None
+        } else {
+            Some(self.global.clone())
         }
+    }
+
+    fn notify_changes(&mut self, old : Option<SettingsRaw>) {
+        //! Calls the subscribers for the keys that are different from the
+        //! `old` data from `watched_snapshot`.
+
+        self.changes_since(old).send();
+    }
 
-        // if the global length is one, then there was nothing to split
-        // so we should just treat the key as an absolute path key
-        // and go directly to the `HashMap<_,_>::remove()` function
-        // to delete the key.
-        if path_tree.len() == 1 {
-            returned_value = self.global.remove(key_path);
-        } else if global.len() > 0 && path_tree.len() > 0 {
-            let index = global.len()-1;
-            if let Type::Complex(ref mut parts_two) = global[index] {
-                returned_value = parts_two.remove(path_tree[path_tree.len()-1]);
+    fn changes_since(&mut self, old : Option<SettingsRaw>) -> Notifications {
+        //! The calls to the subscribers for the keys that are different
+        //! from the `old` data, without calling them yet.
+
+        let old = match old {
+            Some(old) => old,
+            None => return Notifications::default(),
+        };
+
+        let flatten = |raw : &SettingsRaw| match Type::Complex(raw.clone()).flatten(None) {
+            Type::Complex(flat) => flat,
+            _ => HashMap::new(),
+        };
+        let (flat_old,flat_new) = (flatten(&old),flatten(&self.global));
+        let changed : Vec<&String> = flat_old.keys().chain(flat_new.keys())
+            .filter(|key| flat_old.get(*key) != flat_new.get(*key))
+            .collect();
+
+        // `false` for a changed key, `true` for a key above it that is
+        // subscribed to, like `ui.theme` when `ui.theme.color` changed
+        let mut keys : Vec<(String,bool)> = Vec::new();
+        for key in changed {
+            if self.subscribers.is_watched(key) {
+                keys.push((key.to_string(),false));
+            }
+            for (index,_) in key.match_indices('.') {
+                if self.subscribers.is_subscribed(&key[..index]) {
+                    keys.push((key[..index].to_string(),true));
+                }
             }
         }
+        if keys.is_empty() {
+            return Notifications::default();
+        }
+        keys.sort();
+        keys.dedup_by(|later,earlier| later.0 == earlier.0);
+
+        // the values as `get_value` gives them, with the defaults and
+        // everything else that is used over the file.
+        let new = mem::replace(&mut self.global,old);
+        let before : Vec<Option<Type>> = keys.iter().map(|(key,_)| self.get_value(key)).collect();
+        self.global = new;
+        let changes = keys.into_iter().zip(before)
+            .map(|((key,above),before)| {
+                let after = self.get_value(&key);
+                (key,above,before,after)
+            })
+            .filter(|(_,_,before,after)| before != after)
+            .collect();
+        self.subscribers.later(changes)
+    }
+
+    pub fn locate(&self, key_path : &str) -> Option<(usize,usize)> {
+        //! Finds the line and column where `key_path` was defined in the file
+        //! this `Settings` was loaded from.
+        //! 
+        //! Will only work if the `Format` implements `locate`, and will return
+        //! `None` if the `Settings` wasn't loaded from a file.
 
-        if global.len() > 0 {
-            self.global.insert(path_tree[0].to_string(),global.remove(0));
+        match self.source {
+            Some(ref source) => self.ioconfig.locate(source,key_path),
+            None => None,
         }
-        
-        returned_value
     }
 
     pub fn delete_file(&self) -> bool {
         //! Deletes the physical file from the disk
         
-        let path = self.ioconfig.get_path_and_file();
-        info!("Deleting {}",path);
-        match fs::remove_file(path){
-            Err(_) => false,
-            Ok(_) => true,
-        }
+        let path = match self.ioconfig.get_path_and_file() {
+            Ok(path) => path,
+            Err(_) => return false,
+        };
+        info!("Deleting {}",path.display());
+        fs::remove_file(path).is_ok()
     }
 
     pub fn keys(&self) -> Vec<String> {
         let mut keys : Vec<String> = Vec::new();
-        let flat = Settings::flatten(&self);
+        let flat = Settings::flatten(self);
         
         for k in flat.global.keys() {
             keys.push(k.to_string());
@@ -524,11 +1228,7 @@ impl<T> Settings<T> where T : Format + Clone {
         
         // if we are still going, then look at the length, if there aren't
         // any global then it shouldn't be considered flat because its empty.
-        if self.global.len() > 0 { 
-            true
-        } else {
-            false
-        }
+        !self.global.is_empty()
     }
 
     fn flatten(hash_to_flatten : &Settings<T>) -> Settings<T> {
@@ -553,15 +1253,51 @@ impl<T> Settings<T> where T : Format + Clone {
             }
         }
 
-        Settings { 
-            global : flat_hash,
-            ioconfig : hash_to_flatten.ioconfig.clone() 
-        }
+        let mut flat = Settings::new(hash_to_flatten.ioconfig.clone());
+        flat.global = flat_hash;
+        flat
     }
 }
 
 // other implementations /////////////////////////////////////////////////////////////////
 
+/// The `Settings` with the file locked, from `Settings::lock_for_update`
+///
+/// Works like the `Settings` it came from, other programs can't load or
+/// save the file until it is dropped.
+pub struct UpdateGuard<'a,T> where T : Format + Clone + 'a {
+    settings : &'a mut Settings<T>,
+    lock : FileLock,
+    path : PathBuf,
+}
+
+impl<'a,T> UpdateGuard<'a,T> where T : Format + Clone {
+    pub fn save(&self) -> Result<(),Error> {
+        //! Saves the changes, keeping the lock.
+
+        info!("Saving to {}",self.path.display());
+        self.settings.write_unlocked(&self.path,true)?;
+        Ok(())
+    }
+
+    pub fn reload(&mut self) -> Result<(),Error> {
+        //! Loads the file again, dropping any changes that weren't saved.
+
+        self.settings.load_unlocked(&self.path)
+    }
+
+    pub fn lock(&self) -> &FileLock { &self.lock }
+}
+
+impl<'a,T> Deref for UpdateGuard<'a,T> where T : Format + Clone {
+    type Target = Settings<T>;
+    fn deref(&self) -> &Settings<T> { self.settings }
+}
+
+impl<'a,T> DerefMut for UpdateGuard<'a,T> where T : Format + Clone {
+    fn deref_mut(&mut self) -> &mut Settings<T> { self.settings }
+}
+
 impl<T> Add for Settings<T> where T : Format + Clone {
     type Output = Settings<T>;
 
@@ -594,7 +1330,7 @@ impl<T> AddAssign for Settings<T> where T : Format + Clone {
         let flat_other = other.get_flat_hash();
 
         for (key,value) in flat_other.global.iter() {
-            let _ = self.set_value(&key,&value);
+            let _ = self.set_value(key,&value);
         }
     }
 }
@@ -602,14 +1338,16 @@ impl<T> AddAssign for Settings<T> where T : Format + Clone {
 // tests ////////////////////////////////////////////////////////////////////////////////
 #[cfg(test)]
 mod tests {
-    use SupportedType;
     use Format;
     use SettingsRaw;
     use Type;
     use Settings;
+    use SettingsError;
+    use structs::types::insert_path;
 
     use failure::Error;
     use std::collections::HashMap;
+    use std::path::PathBuf;
 
     // Dummy configuration, just enough to get it working.
     #[derive(Clone)]
@@ -618,10 +1356,10 @@ mod tests {
         fn filename(&self) -> String { "".to_string() }
         fn folder(&self) -> String { "".to_string() }
 
-        fn from_str<T>(&self,_:&str) -> Result<SettingsRaw,Error> where T : Format + Clone { 
+        fn from_str(&self,_:&str) -> Result<SettingsRaw,Error> { 
             Ok(HashMap::<String,Type>::new())
         }
-        fn to_string<T:?Sized>(&self,_:&T) -> Result<String,Error> where T : SupportedType { 
+        fn to_string(&self,_:&SettingsRaw) -> Result<String,Error> { 
             Ok("unimplemented".to_string())
         }
     }
@@ -632,10 +1370,10 @@ mod tests {
         //! confirms set and get functionality, that basic reading and writing works
         
         let mut test_obj = Settings::new(Configuration{});
-        assert_eq!(test_obj.set_value("a.b.c.d","mortan").is_ok(),true);
-        assert_eq!(test_obj.set_value("a.b.f",&4453).is_ok(),true);
-        assert_eq!(test_obj.set_value("a.is_enabled",&true).is_ok(),true);
-        assert_eq!(test_obj.set_value("single",&true).is_ok(),true);
+        assert!(test_obj.set_value("a.b.c.d","mortan").is_ok());
+        assert!(test_obj.set_value("a.b.f",&4453).is_ok());
+        assert!(test_obj.set_value("a.is_enabled",&true).is_ok());
+        assert!(test_obj.set_value("single",&true).is_ok());
 
         assert_eq!(test_obj.get_value("a.b.c.d"),Some(Type::Text("mortan".to_string())));
         assert_eq!(test_obj.get_value("a.b.f"),Some(Type::Int(4453)));
@@ -643,12 +1381,112 @@ mod tests {
         assert_eq!(test_obj.get_value("single"),Some(Type::Switch(true)));
     }
 
+    #[test]
+    fn environment() {
+        use {EnvSource,Overrides};
+
+        let mut test_obj = Settings::new(Configuration{});
+        assert!(test_obj.set_value("user.name","snsvrno").is_ok());
+        assert!(test_obj.set_value("user.email","someone@someplace.com").is_ok());
+        test_obj.set_env_source(EnvSource::new("TEST_").with_vars(vec![("TEST_USER__NAME","from env")]));
+
+        assert_eq!(test_obj.get_value("user.name"),Some(Type::Text("from env".to_string())));
+        let user = test_obj.get_value("user").unwrap().to_complex().unwrap();
+        assert_eq!(user.len(),2);
+        // the environment isn't part of the file
+        assert_eq!(test_obj.keys().len(),2);
+        assert_eq!(test_obj.get_flat_hash().global.get("user.name"),Some(&Type::Text("snsvrno".to_string())));
+
+        // and the command line is used over the environment
+        let (overrides,_) = Overrides::from_args(vec!["-c","user.name=from args"]).unwrap();
+        test_obj.set_overrides(overrides);
+        assert_eq!(test_obj.get_value("user.name"),Some(Type::Text("from args".to_string())));
+        assert_eq!(test_obj.keys().len(),2);
+    }
+
+    #[test]
+    fn defaults() {
+        let mut test_obj = Settings::new(Configuration{});
+        test_obj.set_default("user.name","nobody");
+        test_obj.set_default("user.email","nobody@nowhere");
+        test_obj.set_default("jobs",&4);
+        assert!(test_obj.set_value("user.name","snsvrno").is_ok());
+        assert!(test_obj.set_value("jobs",&4).is_ok());
+
+        assert_eq!(test_obj.get_value("user.name"),Some(Type::Text("snsvrno".to_string())));
+        assert_eq!(test_obj.get_value("user.email"),Some(Type::Text("nobody@nowhere".to_string())));
+        assert_eq!(test_obj.get_value("user").unwrap().to_complex().unwrap().len(),2);
+        assert_eq!(test_obj.get_value("other"),None);
+
+        assert!(!test_obj.is_default("user.name"));
+        assert!(test_obj.is_default("user.email"));
+        assert!(test_obj.is_default("jobs"));
+        assert!(!test_obj.is_default("user"));
+        assert!(!test_obj.is_default("other"));
+
+        // only keys that aren't the default are saved
+        assert_eq!(test_obj.raw_to_save().len(),2);
+        test_obj.set_skip_defaults(true);
+        let saved = test_obj.raw_to_save();
+        assert_eq!(saved.len(),1);
+        assert!(saved.contains_key("user"));
+
+        assert_eq!(test_obj.reset_to_default("user.name"),Some(Type::Text("snsvrno".to_string())));
+        assert_eq!(test_obj.get_value("user.name"),Some(Type::Text("nobody".to_string())));
+        assert!(test_obj.is_default("user"));
+    }
+
+    #[test]
+    fn policy() {
+        use SettingsError;
+
+        let mut test_obj = Settings::new(Configuration{});
+        assert!(test_obj.set_value("update.channel","beta").is_ok());
+        assert!(test_obj.set_value("update.check",&true).is_ok());
+        test_obj.set_policy_value("update.channel","stable");
+        test_obj.set_policy_value("telemetry.enabled",&false);
+
+        assert_eq!(test_obj.get_value("update.channel"),Some(Type::Text("stable".to_string())));
+        assert_eq!(test_obj.get_value("update.check"),Some(Type::Switch(true)));
+        assert_eq!(test_obj.get_value("telemetry.enabled"),Some(Type::Switch(false)));
+
+        assert!(test_obj.is_locked("update.channel"));
+        assert!(test_obj.is_locked("update"));
+        assert!(test_obj.is_locked("telemetry.enabled.thing"));
+        assert!(!test_obj.is_locked("update.check"));
+
+        let error = test_obj.set_value("telemetry.enabled",&true).unwrap_err();
+        assert_eq!(error.downcast_ref::<SettingsError>(),Some(&SettingsError::Locked { key : "telemetry.enabled".to_string() }));
+        assert!(test_obj.set_value("update",&false).is_err());
+        assert!(test_obj.set("update.channel","beta").is_err());
+        assert!(test_obj.set_value("update.check",&false).is_ok());
+    }
+
+    #[test]
+    fn get_and_set_with_serde() {
+        #[derive(Serialize,Deserialize,Debug,PartialEq)]
+        struct User { name : String, email : String, age : u8 }
+
+        let mut test_obj = Settings::new(Configuration{});
+        let user = User { name : "snsvrno".to_string(), email : "snsvrno@tuta.io".to_string(), age : 30 };
+        assert!(test_obj.set("user",&user).is_ok());
+
+        assert_eq!(test_obj.get_value("user.age"),Some(Type::Int(30)));
+        assert_eq!(test_obj.get::<User>("user").unwrap(),user);
+        assert_eq!(test_obj.get::<Option<User>>("other_user").unwrap(),None);
+        assert!(test_obj.get::<User>("other_user").is_err());
+
+        assert!(test_obj.set_value("user.age","thirty").is_ok());
+        let error = format!("{}",test_obj.get::<User>("user").unwrap_err());
+        assert!(error.starts_with("`user.age`"));
+    }
+
     #[test]
     fn get_value_or() {
         let mut test_obj = Settings::new(Configuration{});
 
-        assert_eq!(test_obj.set_value("a.b.c.d","mortan").is_ok(),true);
-        assert_eq!(test_obj.set_value("a.b.c.e","bobby lee").is_ok(),true);
+        assert!(test_obj.set_value("a.b.c.d","mortan").is_ok());
+        assert!(test_obj.set_value("a.b.c.e","bobby lee").is_ok());
 
         assert_eq!(test_obj.get_value_or("a.b.c.d", "not going to be used"),Type::Text("mortan".to_string()));
         assert_eq!(test_obj.get_value_or("a.b.c.f", "will be used"),Type::Text("will be used".to_string()));
@@ -661,8 +1499,8 @@ mod tests {
         //! `Settings` during the operations
         
         let mut test_obj = Settings::new(Configuration{});
-        assert_eq!(test_obj.set_value("other.count",&23).is_ok(),true);
-        assert_eq!(test_obj.set_value("other.thing",&false).is_ok(),true);
+        assert!(test_obj.set_value("other.count",&23).is_ok());
+        assert!(test_obj.set_value("other.thing",&false).is_ok());
 
         let mut test_obj2 = Settings::new(Configuration{});
         assert!(test_obj2.set_value("user.place","space").is_ok());
@@ -696,10 +1534,10 @@ mod tests {
         assert!(flat_gen.set_value("user.email","someone@someplace.com").is_ok());
         assert!(flat_gen.set_value("software.version",&23).is_ok());
         assert!(flat_gen.set_value("software.update_available",&false).is_ok());
-        assert!(flat_gen.is_flat() == false);
+        assert!(!flat_gen.is_flat());
 
         let flat = flat_gen.get_flat_hash();
-        assert!(flat.is_flat() == true);
+        assert!(flat.is_flat());
         assert_eq!(flat.get_value("user"),None);
         assert_eq!(flat.get_value("software"),None);
         // using this function to get the absolute key, so we don't try and split it
@@ -707,7 +1545,7 @@ mod tests {
         assert_eq!(flat.get_value_absolute("software.version"),Some(Type::Int(23)));
 
         let fluff = Settings::from_flat(&flat);
-        assert!(fluff.is_flat() == false);
+        assert!(!fluff.is_flat());
         assert_eq!(fluff.get_value_absolute("user.name"),None);
         assert_eq!(fluff.get_value("user.name"),Some(Type::Text("the username".to_string())));
         assert_eq!(fluff.get_value_absolute("software.version"),None);
@@ -760,6 +1598,270 @@ mod tests {
         assert_eq!(None,setting.get_value("user.name"));
         assert_eq!(None,setting.get_value("user.email"));
         assert_eq!(setting.get_value("software.update_available"),Some(Type::Switch(false)));
+        // deleting deeper down keeps the siblings
+        assert!(setting.set_value("a.b.c",&1).is_ok());
+        assert!(setting.set_value("a.b.d",&2).is_ok());
+        assert_eq!(setting.delete_key("a.b.c"),Some(Type::Int(1)));
+        assert_eq!(setting.get_value("a.b.d"),Some(Type::Int(2)));
+        assert_eq!(setting.delete_key("a.x.y"),None);
+    }
+
+    #[test]
+    fn subscribing() {
+        use std::sync::{Arc,Mutex};
+
+        let mut setting = Settings::new(Configuration{});
+        setting.set_default("ui.theme","light");
+        type Changes = Arc<Mutex<Vec<(String,Option<Type>,Option<Type>)>>>;
+
+        let changes : Changes = Arc::new(Mutex::new(Vec::new()));
+        let record = |changes : &Changes| {
+            let changes = changes.clone();
+            move |key : &str, old : Option<&Type>, new : Option<&Type>| {
+                changes.lock().unwrap().push((key.to_string(),old.cloned(),new.cloned()));
+            }
+        };
+        let theme = setting.subscribe("ui.theme",record(&changes));
+        let keys = setting.subscribe("keybindings.*",record(&changes));
+        let text = |text : &str| Some(Type::Text(text.to_string()));
+
+        assert!(setting.set_value("ui.theme","dark").is_ok());
+        assert!(setting.set_value("ui.font","mono").is_ok());
+        assert!(setting.set_value("ui.theme","dark").is_ok());
+        assert!(setting.set_value("keybindings.save","ctrl+s").is_ok());
+        assert_eq!(setting.delete_key("ui.theme"),text("dark"));
+        assert_eq!(changes.lock().unwrap().drain(..).collect::<Vec<_>>(),vec![
+            ("ui.theme".to_string(),text("light"),text("dark")),
+            ("keybindings.save".to_string(),None,text("ctrl+s")),
+            ("ui.theme".to_string(),text("dark"),text("light")),
+        ]);
+
+        // merging with `+=`
+        let mut other = Settings::new(Configuration{});
+        assert!(other.set_value("keybindings.save","ctrl+w").is_ok());
+        assert!(other.set_value("keybindings.open","ctrl+o").is_ok());
+        setting += other;
+        let mut merged = changes.lock().unwrap().drain(..).collect::<Vec<_>>();
+        merged.sort_by(|a,b| a.0.cmp(&b.0));
+        assert_eq!(merged,vec![
+            ("keybindings.open".to_string(),None,text("ctrl+o")),
+            ("keybindings.save".to_string(),text("ctrl+s"),text("ctrl+w")),
+        ]);
+
+        // dropping unsubscribes, and clones don't have the callbacks
+        drop(keys);
+        assert!(setting.clone().set_value("ui.theme","blue").is_ok());
+        assert!(setting.set_value("keybindings.save","ctrl+s").is_ok());
+        assert!(changes.lock().unwrap().is_empty());
+        theme.unsubscribe();
+        assert!(setting.set_value("ui.theme","blue").is_ok());
+        assert!(changes.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn subscribing_above() {
+        use std::sync::{Arc,Mutex};
+
+        let mut setting = Settings::new(Configuration{});
+        assert!(setting.set_value("ui.theme.color","blue").is_ok());
+        type Changes = Arc<Mutex<Vec<(String,Option<Type>,Option<Type>)>>>;
+        let changes : Changes = Arc::new(Mutex::new(Vec::new()));
+        let (theme,all) = (changes.clone(),changes.clone());
+        let _theme = setting.subscribe("ui.theme",move |key,old,new| {
+            theme.lock().unwrap().push((key.to_string(),old.cloned(),new.cloned()));
+        });
+        let _all = setting.subscribe("*",move |key,_,_| {
+            all.lock().unwrap().push((key.to_string(),None,None));
+        });
+        let theme = |color : &str| {
+            let mut theme = HashMap::new();
+            theme.insert("color".to_string(),Type::Text(color.to_string()));
+            Some(Type::Complex(theme))
+        };
+
+        // told about the whole theme, `*` only about the key that changed
+        assert!(setting.set_value("ui.theme.color","red").is_ok());
+        assert_eq!(changes.lock().unwrap().drain(..).collect::<Vec<_>>(),vec![
+            ("ui.theme".to_string(),theme("blue"),theme("red")),
+            ("ui.theme.color".to_string(),None,None),
+        ]);
+
+        // or when it isn't a table anymore
+        assert!(setting.set_value("ui.theme","dark").is_ok());
+        assert_eq!(changes.lock().unwrap().drain(..).collect::<Vec<_>>(),vec![
+            ("ui.theme".to_string(),theme("red"),Some(Type::Text("dark".to_string()))),
+            ("ui.theme".to_string(),None,None),
+            ("ui.theme.color".to_string(),None,None),
+        ]);
+    }
+
+    // `key = value` lines in a folder, so two `Settings` can share a file
+    #[derive(Clone)]
+    struct Lines { base : PathBuf }
+    impl Format for Lines {
+        fn filename(&self) -> String { "settings".to_string() }
+        fn folder(&self) -> String { "app".to_string() }
+        fn base_dir(&self) -> Result<PathBuf,Error> { Ok(self.base.clone()) }
+        fn from_str(&self,buffer:&str) -> Result<SettingsRaw,Error> {
+            let mut raw = SettingsRaw::new();
+            for line in buffer.lines() {
+                let mut parts = line.splitn(2," = ");
+                if let (Some(key),Some(value)) = (parts.next(),parts.next()) {
+                    insert_path(&mut raw,key,Type::infer(value));
+                }
+            }
+            Ok(raw)
+        }
+        fn to_string(&self,object:&SettingsRaw) -> Result<String,Error> {
+            let mut lines : Vec<String> = Vec::new();
+            if let Type::Complex(flat) = Type::Complex(object.clone()).flatten(None) {
+                for (key,value) in flat {
+                    lines.push(format!("{} = {}",key,value));
+                }
+            }
+            lines.sort();
+            Ok(lines.join("\n"))
+        }
+    }
+
+    #[test]
+    fn saving_merged() {
+        let dir = ::tempfile::tempdir().unwrap();
+        let format = Lines { base : dir.path().to_path_buf() };
+        let mut setup = Settings::new(format.clone());
+        assert!(setup.set_value("user.name","snsvrno").is_ok());
+        assert!(setup.set_value("user.email","old@place.com").is_ok());
+        assert!(setup.set_value("font.size",&12).is_ok());
+        assert!(setup.save().is_ok());
+
+        let mut first = Settings::new_and_load(format.clone());
+        let mut second = Settings::new_and_load(format.clone());
+        assert!(first.dirty_keys().is_empty());
+
+        assert!(first.set_value("font.size",&14).is_ok());
+        assert_eq!(first.delete_key("user.email"),Some(Type::Text("old@place.com".to_string())));
+        assert_eq!(first.dirty_keys(),vec!["font.size".to_string(),"user.email".to_string()]);
+        assert!(first.save_merged().is_ok());
+        assert!(first.dirty_keys().is_empty());
+
+        // the other program's change to another key is kept
+        assert!(second.set_value("user.name","someone").is_ok());
+        assert!(second.save_merged().is_ok());
+        assert_eq!(second.get_value("font.size"),Some(Type::Int(14)));
+        assert_eq!(second.get_value("user.email"),None);
+        assert!(first.load().is_ok());
+        assert_eq!(first.get_value("user.name"),Some(Type::Text("someone".to_string())));
+        assert_eq!(first.get_value("font.size"),Some(Type::Int(14)));
+
+        // both changed the same key
+        assert!(first.set_value("font.size",&16).is_ok());
+        assert!(second.set_value("font.size",&10).is_ok());
+        assert!(first.save_merged().is_ok());
+        let error = second.save_merged().unwrap_err();
+        assert_eq!(error.downcast_ref::<SettingsError>(),Some(&SettingsError::Conflict {
+            keys : vec!["font.size".to_string()],
+        }));
+        assert!(first.load().is_ok());
+        assert_eq!(first.get_value("font.size"),Some(Type::Int(16)));
+
+        // changing it to the same value isn't a conflict
+        assert!(second.set_value("font.size",&16).is_ok());
+        assert!(second.save_merged().is_ok());
+    }
+
+    #[test]
+    fn saving_merged_after_save() {
+        let dir = ::tempfile::tempdir().unwrap();
+        let format = Lines { base : dir.path().to_path_buf() };
+        let mut setup = Settings::new(format.clone());
+        assert!(setup.set_value("a",&1).is_ok());
+        assert!(setup.save().is_ok());
+
+        let mut settings = Settings::new_and_load(format.clone());
+        assert!(settings.set_value("a",&2).is_ok());
+        assert!(settings.save().is_ok());
+        assert!(settings.dirty_keys().is_empty());
+
+        // what was saved is what is on disk now, so it isn't a conflict
+        assert!(settings.set_value("a",&3).is_ok());
+        assert!(settings.save_merged().is_ok());
+        assert_eq!(Settings::new_and_load(format.clone()).get_value("a"),Some(Type::Int(3)));
+
+        // the same for the guard
+        {
+            let mut update = settings.lock_for_update().unwrap();
+            assert!(update.set_value("a",&4).is_ok());
+            assert!(update.save().is_ok());
+        }
+        assert!(settings.dirty_keys().is_empty());
+        assert!(settings.set_value("a",&5).is_ok());
+        assert!(settings.save_merged().is_ok());
+        assert_eq!(Settings::new_and_load(format).get_value("a"),Some(Type::Int(5)));
+    }
+
+    #[test]
+    fn subscribing_to_loads() {
+        use std::sync::{Arc,Mutex};
+
+        let dir = ::tempfile::tempdir().unwrap();
+        let format = Lines { base : dir.path().to_path_buf() };
+        let mut first = Settings::new(format.clone());
+        assert!(first.set_value("font.size",&12).is_ok());
+        assert!(first.save().is_ok());
+
+        let mut second = Settings::new_and_load(format.clone());
+        let changes : Arc<Mutex<Vec<String>>> = Arc::new(Mutex::new(Vec::new()));
+        let recorded = changes.clone();
+        let _subscription = second.subscribe("*",move |key,old,new| {
+            recorded.lock().unwrap().push(format!("{} {:?} {:?}",key,old,new));
+        });
+
+        assert!(first.set_value("font.size",&14).is_ok());
+        assert!(first.set_value("font.name","mono").is_ok());
+        assert!(first.save().is_ok());
+        assert!(second.load().is_ok());
+        assert_eq!(*changes.lock().unwrap(),vec![
+            format!("font.name {:?} {:?}",None::<Type>,Some(Type::Text("mono".to_string()))),
+            format!("font.size {:?} {:?}",Some(Type::Int(12)),Some(Type::Int(14))),
+        ]);
+    }
+
+    #[test]
+    fn changed_on_disk() {
+        let dir = ::tempfile::tempdir().unwrap();
+        let format = Lines { base : dir.path().to_path_buf() };
+        let mut first = Settings::new(format.clone());
+        assert!(!first.has_changed_on_disk().unwrap());
+        assert!(first.set_value("font.size",&12).is_ok());
+        assert!(first.save().is_ok());
+        // our own saves don't count
+        assert!(first.save().is_ok());
+        assert!(!first.has_changed_on_disk().unwrap());
+
+        let mut second = Settings::new_and_load(format.clone());
+        assert!(second.set_value("font.size",&14).is_ok());
+        assert!(second.save().is_ok());
+
+        assert!(first.has_changed_on_disk().unwrap());
+        assert!(first.set_value("font.size",&16).is_ok());
+        let error = first.save().unwrap_err();
+        assert_eq!(error.downcast_ref::<SettingsError>(),Some(&SettingsError::ConcurrentModification {
+            path : format.get_path_and_file().unwrap(),
+        }));
+        assert_eq!(Settings::new_and_load(format.clone()).get_value("font.size"),Some(Type::Int(14)));
+
+        assert!(first.save_force().is_ok());
+        assert!(!first.has_changed_on_disk().unwrap());
+        assert!(second.has_changed_on_disk().unwrap());
+        assert!(second.load().is_ok());
+        assert_eq!(second.get_value("font.size"),Some(Type::Int(16)));
+        assert!(!second.has_changed_on_disk().unwrap());
+
+        // a deleted file is made again
+        ::std::fs::remove_file(format.get_path_and_file().unwrap()).unwrap();
+        assert!(!second.has_changed_on_disk().unwrap());
+        assert!(second.save().is_ok());
+        assert!(format.get_path_and_file().unwrap().exists());
     }
 
 }
@@ -1,10 +1,29 @@
 use Format;
 use Type;
 use Settings;
+use Subscription;
 use SupportedType;
+use Origin;
+use SettingsRaw;
+use SettingsError;
+use MergeOptions;
+use EnvSource;
+use Overrides;
+use TrustStore;
+use LocalDiscovery;
+use convert::{to_type,from_value};
+use paths;
+use FileLock;
+use structs::lock::unless_read_only;
+use structs::subscribe::Notifications;
+use Location;
 
+use serde::ser::Serialize;
+use serde::de::DeserializeOwned;
 use std::fs;
 use std::fs::File;
+use std::path::{Path,PathBuf};
+use std::time::Duration;
 use failure::Error;
 
 /// Complex settings that pulls data from 2 locations
@@ -41,6 +60,36 @@ pub struct ShadowSettings<T> where T : Format + Clone {
     ioconfig: T,
     global : Settings<T>,
     local : Option<Settings<T>>,
+    #[serde(default)]
+    merge : MergeOptions,
+    #[serde(skip)]
+    env : Option<EnvSource>,
+    #[serde(skip)]
+    overrides : Option<Overrides>,
+    // read only system wide files, ordered from the
+    // lowest priority to the highest.
+    #[serde(skip)]
+    system : Vec<(PathBuf,Settings<T>)>,
+    // the key prefixes the local file may set, everything if `None`
+    #[serde(skip)]
+    local_allowlist : Option<Vec<String>>,
+    #[serde(skip)]
+    strict_local : bool,
+    // keys that were left out of the local file when it was loaded
+    #[serde(skip)]
+    rejected_local : Vec<String>,
+    // only loads local files that are in the trust store
+    #[serde(skip)]
+    require_trust : bool,
+    #[serde(skip)]
+    discovery : LocalDiscovery,
+    // where the local file was found
+    #[serde(skip)]
+    local_path : Option<PathBuf>,
+    // read only local files further up than the local file, ordered
+    // from the furthest to the nearest.
+    #[serde(skip)]
+    local_parents : Vec<(PathBuf,Settings<T>)>,
 }
 
 impl<T> ShadowSettings<T> where T : Format + Clone {
@@ -50,7 +99,18 @@ impl<T> ShadowSettings<T> where T : Format + Clone {
         ShadowSettings {
             ioconfig : config.clone(),
             global : Settings::new(config.clone()),
-            local : None
+            local : None,
+            merge : MergeOptions::new(),
+            env : None,
+            overrides : None,
+            system : Vec::new(),
+            local_allowlist : None,
+            strict_local : false,
+            rejected_local : Vec::new(),
+            require_trust : false,
+            discovery : LocalDiscovery::current_dir(),
+            local_path : None,
+            local_parents : Vec::new(),
         }
     }
 
@@ -71,45 +131,148 @@ impl<T> ShadowSettings<T> where T : Format + Clone {
         setting
     }
 
-    pub fn create_from(mut file : &File, config : T) -> Result<ShadowSettings<T>,Error> {
+    pub fn create_from(file : &File, config : T) -> Result<ShadowSettings<T>,Error> {
         //! Creates a new `ShadowSetting` and loads the file buffer into the global `Setting`.
         
         Ok(ShadowSettings {
             ioconfig : config.clone(),
-            global : Settings::create_from(&mut file,config.clone())?,
+            global : Settings::create_from(file,config.clone())?,
             local : None,
+            merge : MergeOptions::new(),
+            env : None,
+            overrides : None,
+            system : Vec::new(),
+            local_allowlist : None,
+            strict_local : false,
+            rejected_local : Vec::new(),
+            require_trust : false,
+            discovery : LocalDiscovery::current_dir(),
+            local_path : None,
+            local_parents : Vec::new(),
         })
     }
 
     pub fn load(&mut self) -> Result<(),Error> {
-        //! attempts to load both local and global
+        //! attempts to load the system, global and local files
         
-        let result_global = self.load_global();
-        let result_local = self.load_local();
+        let results = vec![
+            ("System",self.load_system()),
+            ("Global",self.load_global()),
+            ("Local",self.load_local()),
+        ];
 
         // combining the errors, doing this because if one of the loads fails it will
         // not finish the loading
-        if result_global.is_err() && result_local.is_err() {
-            return Err(format_err!("Global: {}, Local: {}",result_global.unwrap_err(),result_local.unwrap_err()));
-        } else if result_global.is_err() {
-            return Err(format_err!("Global: {}",result_global.unwrap_err()));
-        } else if result_local.is_err() {
-            return Err(format_err!("Local: {}",result_local.unwrap_err()));
+        let errors : Vec<String> = results.into_iter()
+            .filter_map(|(name,result)| result.err().map(|error| format!("{}: {}",name,error)))
+            .collect();
+        if !errors.is_empty() {
+            return Err(format_err!("{}",errors.join(", ")));
         }
 
         Ok(())
     }
 
+    pub fn reload(&mut self) -> Result<Vec<String>,Error> {
+        //! Loads the system, global and local files again, dropping any
+        //! changes that weren't saved, and returns the keys whose value
+        //! changed, sorted.
+        //! 
+        //! Files that were removed are dropped too. If a file can't be
+        //! read or parsed nothing is changed and the error is returned,
+        //! so a half written file never wipes the settings.
+
+        let (keys,notifications) = self.reload_unnotified()?;
+        notifications.send();
+        Ok(keys)
+    }
+
+    pub(crate) fn reload_unnotified(&mut self) -> Result<(Vec<String>,Notifications),Error> {
+        //! Reloads like `reload`, returning the calls to the subscribers
+        //! instead of calling them, so they can be called without a lock
+        //! on these settings.
+
+        let mut reloaded = self.clone();
+        reloaded.global = self.keep_global_options(Settings::new(self.ioconfig.clone()));
+        reloaded.local = None;
+        reloaded.local_path = None;
+        reloaded.local_parents = Vec::new();
+        reloaded.rejected_local = Vec::new();
+        reloaded.load()?;
+
+        let mut keys : Vec<String> = self.keys_with_origin().into_iter()
+            .chain(reloaded.keys_with_origin())
+            .map(|(key,_)| key)
+            .collect();
+        keys.sort();
+        keys.dedup();
+        keys.retain(|key| self.get_value(key) != reloaded.get_value(key));
+
+        let notifications = reloaded.global.take_subscribers(&mut self.global);
+        *self = reloaded;
+        Ok((keys,notifications))
+    }
+
+    pub fn load_system(&mut self) -> Result<(),Error> {
+        //! Loads the system wide files from `Format::get_system_paths`, these
+        //! are read only and are shadowed by the global file the same way the
+        //! global file is shadowed by the local file.
+        //! 
+        //! All the files that exist are used, the ones that come first in
+        //! `$XDG_CONFIG_DIRS` have priority and `/etc/{app}` is used last.
+
+        let paths = self.ioconfig.get_system_paths();
+        self.load_system_from(&paths)
+    }
+
+    pub fn load_system_from<P>(&mut self, paths : &[P]) -> Result<(),Error>
+        where P : AsRef<Path>,
+    {
+        //! Loads the system wide files from `paths`, the most important first.
+        //! Paths that don't exist are skipped.
+
+        let mut system = Vec::new();
+        for path in paths.iter().rev() {
+            let path = path.as_ref();
+            if let Ok(file) = File::open(path) {
+                info!("Using {} for system file",path.display());
+                let settings = Settings::create_from(&file,self.config_for(path))
+                    .map_err(|error| format_err!("{}: {}",path.display(),error))?;
+                system.push((path.to_path_buf(),settings));
+            }
+        }
+
+        self.system = system;
+        Ok(())
+    }
+
+    pub fn location(&self) -> Location {
+        //! Where the global file is kept, see [Settings::location](struct.Settings.html#method.location).
+
+        self.ioconfig.active_location()
+    }
+
+    pub fn system_paths(&self) -> Vec<&Path> {
+        //! The system files that were loaded, the most important first.
+
+        self.system.iter().rev().map(|(path,_)| path.as_path()).collect()
+    }
+
     pub fn load_global(&mut self) -> Result<(),Error> {
         //! Loads the global file, or errors
         //!
         //! Usually not a breaking error, since it mostly 
         //! errors because there isn't a file.
         
-        let global_path = self.ioconfig.get_path_and_file();
-        if let Ok(mut file) = File::open(&global_path) {
-            info!("Using {} for global file",global_path);
-            self.load_global_from(&mut file)?;
+        paths::migrate_legacy(&self.ioconfig)?;
+        let global_path = self.ioconfig.get_path_and_file()?;
+        if global_path.is_file() {
+            let _lock = unless_read_only(FileLock::shared(self.ioconfig.get_lock_path_and_file()?,self.global.lock_timeout()))?;
+            if let Ok(mut file) = File::open(&global_path) {
+                info!("Using {} for global file",global_path.display());
+                self.load_global_from(&mut file)?;
+                self.global.stamp_file(&global_path)?;
+            }
         }
 
         Ok(())
@@ -120,55 +283,429 @@ impl<T> ShadowSettings<T> where T : Format + Clone {
         //!
         //! Usually not a breaking error, since it mostly 
         //! errors because there isn't a file.
-        let local_path = self.ioconfig.get_local_path_and_filename();
-        if let Ok(mut file) = File::open(&local_path) {
-            info!("Using {} for local file",local_path);
-            self.load_local_from(&mut file)?;
+        //! 
+        //! Where the file is looked for is set with
+        //! [set_local_discovery](#method.set_local_discovery).
+
+        let paths = self.find_local_files()?;
+        if paths.is_empty() {
+            return Ok(());
         }
 
+        let store = if self.require_trust {
+            Some(TrustStore::open(self.ioconfig.get_trust_path_and_file()?)?)
+        } else {
+            None
+        };
+
+        let mut rejected = Vec::new();
+        let mut loaded = Vec::new();
+        for path in paths {
+            // checks the same contents that are used, so the file
+            // can't be swapped in between
+            let contents = fs::read(&path)?;
+            if let Some(ref store) = store {
+                if !store.is_trusted_contents(&path,&contents) {
+                    return Err(SettingsError::LocalNotTrusted { path }.into());
+                }
+            }
+
+            info!("Using {} for local file",path.display());
+            let local = String::from_utf8(contents.clone()).map_err(Error::from)
+                .and_then(|buffer| Settings::create_from_buffer(buffer,self.config_for(&path)))
+                .map_err(|error| format_err!("{}: {}",path.display(),error))?;
+            local.stamp_contents(&path,&contents)?;
+            rejected.append(&mut self.reject_local_keys(&local)?);
+            loaded.push((path,local));
+        }
+        rejected.sort();
+        rejected.dedup();
+
+        // the nearest is the one that gets saved to
+        let (path,local) = loaded.remove(0);
+        loaded.reverse();
+        self.local = Some(local);
+        self.local_path = Some(path);
+        self.local_parents = loaded;
+        self.rejected_local = rejected;
         Ok(())
     }
 
+    fn config_for(&self, path : &Path) -> T {
+        //! The configuration for reading the file at `path`, see
+        //! [Format::select_for](trait.Format.html#method.select_for).
+
+        let mut config = self.ioconfig.clone();
+        config.select_for(path);
+        config
+    }
+
+    fn find_local_files(&self) -> Result<Vec<PathBuf>,Error> {
+        //! The local files that exist, the nearest first.
+
+        let path = self.ioconfig.get_local_path_and_filename()?;
+        match (path.parent(),path.file_name()) {
+            (Some(start),Some(filename)) => Ok(self.discovery.find(start,filename)),
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    fn local_path(&self) -> Result<PathBuf,Error> {
+        //! The path of the local file, where it was loaded from or would
+        //! be loaded from, or in the current directory if there isn't one.
+
+        if let Some(ref path) = self.local_path {
+            return Ok(path.clone());
+        }
+
+        match self.find_local_files()?.into_iter().next() {
+            Some(path) => Ok(path),
+            None => self.ioconfig.get_local_path_and_filename(),
+        }
+    }
+
+    pub fn set_local_discovery(&mut self, discovery : LocalDiscovery) {
+        //! Sets how the local file is found, see [LocalDiscovery](struct.LocalDiscovery.html).
+        //! Used the next time the local file is loaded.
+
+        self.discovery = discovery;
+    }
+
+    pub fn local_discovery(&self) -> &LocalDiscovery {
+        &self.discovery
+    }
+
+    pub fn local_paths(&self) -> Vec<&Path> {
+        //! The local files that were loaded, the nearest first.
+
+        let mut paths : Vec<&Path> = self.local_path.iter().map(|path| path.as_path()).collect();
+        paths.extend(self.local_parents.iter().rev().map(|(path,_)| path.as_path()));
+        paths
+    }
+
+    pub fn file_paths(&self) -> Vec<PathBuf> {
+        //! The global file and the local files, or where they would be
+        //! if they don't exist yet.
+
+        let mut paths : Vec<PathBuf> = self.ioconfig.get_path_and_file().into_iter().collect();
+        match self.local_paths() {
+            ref local if local.is_empty() => paths.extend(self.local_path().ok()),
+            local => paths.extend(local.into_iter().map(|path| path.to_path_buf())),
+        }
+        paths
+    }
+
     pub fn load_global_from(&mut self, file : &mut File) -> Result<(),Error> {
         //! Loads the file buffer over the existing global `Settings`, replacing it.
         //! 
         //! Will fail if it cannot read the buffer.
 
-        self.global = Settings::create_from(file, self.ioconfig.clone())?;
+        let mut global = self.keep_global_options(Settings::create_from(file, self.ioconfig.clone())?);
+        let notifications = global.take_subscribers(&mut self.global);
+        self.global = global;
+        notifications.send();
         Ok(())
     }
 
+    pub fn subscribe_global<F>(&self, pattern : &str, callback : F) -> Subscription
+        where F : FnMut(&str,Option<&Type>,Option<&Type>) + Send + 'static,
+    {
+        //! Calls `callback` when a value of the global `Settings` matching
+        //! `pattern` changes, see [Settings::subscribe](struct.Settings.html#method.subscribe).
+        //! The callbacks are kept when the global file is loaded again, with
+        //! `load` or `reload`.
+
+        self.global.subscribe(pattern,callback)
+    }
+
+    fn keep_global_options(&self, mut global : Settings<T>) -> Settings<T> {
+        //! Copies what belongs to the program instead of the file over
+        //! to the new global `Settings`.
+
+        global.set_defaults(self.global.defaults().clone());
+        global.set_skip_defaults(self.global.skips_defaults());
+        global.set_policy(self.global.policy().clone());
+        global.set_lock_timeout(self.global.lock_timeout());
+        global
+    }
+
     pub fn load_local_from(&mut self, file : &mut File) -> Result<(),Error> {
         //! Loads the file buffer over the existing local `Settings`, replacing it.
         //! 
         //! Will fail if it cannot read the buffer.
         
-        self.local = Some(Settings::create_from(file, self.ioconfig.clone())?);
+        let local = Settings::create_from(file, self.ioconfig.clone())?;
+        self.rejected_local = self.reject_local_keys(&local)?;
+        self.local = Some(local);
         Ok(()) 
     }
 
+    fn reject_local_keys(&self, local : &Settings<T>) -> Result<Vec<String>,Error> {
+        //! The keys of the local settings that aren't in the allowlist, or
+        //! errors in strict mode. They are left in the local settings so
+        //! saving doesn't remove them from the file, `get_value` skips them.
+
+        let mut rejected : Vec<String> = local.keys().into_iter()
+            .filter(|key| !self.is_local_key_allowed(key))
+            .collect();
+        rejected.sort();
+
+        if rejected.is_empty() {
+            return Ok(rejected);
+        }
+
+        if self.strict_local {
+            return Err(SettingsError::LocalNotAllowed { keys : rejected }.into());
+        }
+
+        for key in rejected.iter() {
+            warn!("Ignoring '{}' in the local file, it isn't allowed to be set locally",key);
+        }
+        Ok(rejected)
+    }
+
+    pub fn set_local_allowlist<I,S>(&mut self, prefixes : I)
+        where I : IntoIterator<Item=S>, S : ToString,
+    {
+        //! Only lets the local file set keys that start with one of the
+        //! `prefixes`, so a local file in a cloned repository can't change
+        //! things like `credentials.token`.
+        //! 
+        //! A prefix matches whole parts of the key, so `editor` allows
+        //! `editor.theme` but not `editor_command`. Other keys in the local
+        //! file are ignored with a warning, or cause an error when loading
+        //! if [set_strict_local](#method.set_strict_local) is on.
+        //! 
+        //! Only applies to files loaded after it is set, keys set with
+        //! `set_value_local` are always allowed.
+        //! 
+        //! ```rust
+        //! # extern crate settingsfile;
+        //! use settingsfile::ShadowSettings;
+        //! use settingsfile::EmptyConfig; // dumb config for examples and testing
+        //! 
+        //! let mut settings = ShadowSettings::new(EmptyConfig{});
+        //! settings.set_local_allowlist(vec!["editor","build.jobs"]);
+        //! assert!(settings.is_local_key_allowed("editor.theme"));
+        //! assert!(!settings.is_local_key_allowed("credentials.token"));
+        //! ```
+
+        self.local_allowlist = Some(prefixes.into_iter().map(|prefix| prefix.to_string()).collect());
+    }
+
+    pub fn clear_local_allowlist(&mut self) {
+        //! Lets the local file set any key again.
+
+        self.local_allowlist = None;
+    }
+
+    pub fn set_strict_local(&mut self, strict : bool) {
+        //! When `true`, loading a local file with keys that aren't allowed
+        //! returns a [SettingsError::LocalNotAllowed](enum.SettingsError.html)
+        //! instead of ignoring those keys.
+
+        self.strict_local = strict;
+    }
+
+    pub fn is_local_key_allowed(&self, key_path : &str) -> bool {
+        //! Checks if the local file is allowed to set `key_path`.
+
+        match self.local_allowlist {
+            None => true,
+            Some(ref prefixes) => prefixes.iter().any(|prefix| {
+                key_path == prefix || key_path.starts_with(&format!("{}.",prefix))
+            }),
+        }
+    }
+
+    pub fn rejected_local_keys(&self) -> &[String] {
+        //! The keys that were in the local file but are ignored because
+        //! they aren't allowed, from the last time it was loaded. They are
+        //! still written back when the local file is saved.
+
+        &self.rejected_local
+    }
+
+    fn is_rejected(&self, key_path : &str) -> bool {
+        //! Checks if `key_path`, or one of its parents, was rejected.
+
+        self.rejected_local.iter().any(|key| {
+            key_path == key || key_path.starts_with(&format!("{}.",key))
+        })
+    }
+
+    fn allow_local_key(&mut self, key_path : &str) {
+        //! Stops ignoring `key_path` in the local file, along with the
+        //! keys inside of it and its parents, because it was set directly.
+
+        let prefix = format!("{}.",key_path);
+        self.rejected_local.retain(|key| {
+            key != key_path && !key.starts_with(&prefix) && !key_path.starts_with(&format!("{}.",key))
+        });
+    }
+
+    fn local_value(&self, local : &Settings<T>, key_path : &str) -> Option<Type> {
+        //! The value of a local file, without the keys that were rejected.
+
+        if self.is_rejected(key_path) {
+            return None;
+        }
+        let value = layer_value(local,key_path)?;
+        if self.rejected_local.is_empty() {
+            return Some(value);
+        }
+        self.without_rejected(key_path,value)
+    }
+
+    fn without_rejected(&self, key_path : &str, value : Type) -> Option<Type> {
+        //! Removes the rejected keys from inside of `value`, `None` if
+        //! nothing is left.
+
+        match value {
+            Type::Complex(hash) => {
+                let was_empty = hash.is_empty();
+                let kept : SettingsRaw = hash.into_iter()
+                    .filter_map(|(key,value)| {
+                        let path = format!("{}.{}",key_path,key);
+                        if self.is_rejected(&path) {
+                            return None;
+                        }
+                        self.without_rejected(&path,value).map(|value| (key,value))
+                    })
+                    .collect();
+                if kept.is_empty() && !was_empty {
+                    None
+                } else {
+                    Some(Type::Complex(kept))
+                }
+            },
+            other => Some(other),
+        }
+    }
+
+    pub fn set_require_trust(&mut self, require : bool) {
+        //! When `true`, `load_local` only uses the local file if the user
+        //! trusted it with [trust_local](#method.trust_local), the same way
+        //! `direnv allow` works. Otherwise the local file is skipped and
+        //! loading returns a [SettingsError::LocalNotTrusted](enum.SettingsError.html).
+        //! 
+        //! The trusted files are kept in `Format::get_trust_path_and_file`,
+        //! next to the global file. A file stops being trusted when it is
+        //! edited, unless it was trusted and saved by `save`.
+        //! 
+        //! Files loaded with `load_local_from` are always used.
+
+        self.require_trust = require;
+    }
+
+    pub fn requires_trust(&self) -> bool { self.require_trust }
+
+    pub fn trust_local(&self) -> Result<(),Error> {
+        //! Trusts the local files as they are now. When every file up the
+        //! directories is used (see [LocalDiscovery](struct.LocalDiscovery.html))
+        //! all of them are trusted.
+
+        let mut store = TrustStore::open(self.ioconfig.get_trust_path_and_file()?)?;
+        for path in self.local_files()? {
+            store.trust(path)?;
+        }
+        store.save()
+    }
+
+    pub fn untrust_local(&self) -> Result<(),Error> {
+        //! Removes the local files from the trusted files.
+
+        let mut store = TrustStore::open(self.ioconfig.get_trust_path_and_file()?)?;
+        let mut removed = false;
+        for path in self.local_files()? {
+            removed |= store.untrust(path);
+        }
+        if removed {
+            store.save()?;
+        }
+        Ok(())
+    }
+
+    pub fn is_local_trusted(&self) -> bool {
+        //! Checks if all the local files are trusted and haven't been
+        //! changed since.
+
+        let trusted = self.ioconfig.get_trust_path_and_file()
+            .and_then(|trust_path| Ok((TrustStore::open(trust_path)?,self.local_files()?)));
+        match trusted {
+            Ok((store,paths)) => paths.iter().all(|path| store.is_trusted(path)),
+            Err(error) => {
+                warn!("{}",error);
+                false
+            },
+        }
+    }
+
+    fn local_files(&self) -> Result<Vec<PathBuf>,Error> {
+        //! All the local files that would be loaded, or where the local
+        //! file would be if there aren't any.
+
+        let paths = self.find_local_files()?;
+        if paths.is_empty() {
+            Ok(vec![self.local_path()?])
+        } else {
+            Ok(paths)
+        }
+    }
+
     pub fn save(&self) -> Result<(),Error> {
-        //! saves the setting to a file, uses the `save_to` buffer function
+        //! saves the setting to a file, replacing each file all at once
+        //! the same way as [Settings::save_to_path](struct.Settings.html#method.save_to_path).
+        //! 
+        //! Both files are locked while they are written, the local one with
+        //! a file in the state folder, see [FileLock](struct.FileLock.html). A file that was changed by something
+        //! else since it was loaded isn't written over, a
+        //! [SettingsError::ConcurrentModification](enum.SettingsError.html)
+        //! is returned instead, like [Settings::save](struct.Settings.html#method.save).
          
-        let global_path = self.ioconfig.get_path_and_file();
-        // first makes sure all the directories exist before attempting to create
-        // the file, so it has a place to make it
-        fs::create_dir_all(self.ioconfig.get_path())?;
-        // creates the file, now that we know the directory exists
-        info!("Saving global to {}",global_path);
-        let mut file = File::create(global_path)?;
-        self.save_global_to(&mut file)?;
-
-        if self.local.is_some() {
-            let local_path = self.ioconfig.get_local_path_and_filename();
-            info!("Saving local to {}",local_path);
-            let mut local_file  = File::create(local_path)?;
-            self.save_local_to(&mut local_file)?;
+        self.global.save()?;
+        self.save_local(true)
+    }
+
+    pub fn save_force(&self) -> Result<(),Error> {
+        //! Saves like `save`, even if the files were changed since they
+        //! were loaded.
+
+        self.global.save_force()?;
+        self.save_local(false)
+    }
+
+    fn save_local(&self, check : bool) -> Result<(),Error> {
+        if let Some(ref local) = self.local {
+            let local_path = self.local_path()?;
+            info!("Saving local to {}",local_path.display());
+
+            // our own changes to a trusted file are trusted too
+            let mut trusted = None;
+            if self.require_trust {
+                let store = TrustStore::open(self.ioconfig.get_trust_path_and_file()?)?;
+                if store.is_trusted(&local_path) {
+                    trusted = Some(store);
+                }
+            }
+
+            let contents = local.save_at(&local_path,check)?;
+            if let Some(mut store) = trusted {
+                store.trust_contents(&local_path,contents.as_bytes())?;
+                store.save()?;
+            }
         }
 
         Ok(())
     }
 
+    pub fn set_lock_timeout(&mut self, timeout : Option<Duration>) {
+        //! How long to wait for the lock on the global file, see
+        //! [Settings::set_lock_timeout](struct.Settings.html#method.set_lock_timeout).
+
+        self.global.set_lock_timeout(timeout);
+    }
+
     pub fn save_global_to(&self, file : &File) -> Result<(),Error> {
         //! Saves the global `Setting` to a file buffer.
         
@@ -196,36 +733,296 @@ impl<T> ShadowSettings<T> where T : Format + Clone {
         //! 
         //! Since this is 'shadowing', `Settings` will look in both
         //! the local and global `Settings` to determine what value to
-        //! return. The local value will always override the global value.
+        //! return. The local value will always override the global value,
+        //! and the global value will always override the system files.
+        //! 
+        //! Complex values are merged at every depth, so setting `a.b.c.e`
+        //! locally doesn't hide `a.b.c.d` from the global. How arrays are
+        //! merged is set with [set_merge_options](#method.set_merge_options).
+        //! 
+        //! When every local file up the directories is used (see
+        //! [LocalDiscovery](struct.LocalDiscovery.html)) the nearer files
+        //! shadow the ones further up.
+        //! 
+        //! A key that is unset locally with [unset_local](#method.unset_local)
+        //! returns `None`, even if it is set globally.
+        //! 
+        //! Values from an [EnvSource](struct.EnvSource.html) are used over
+        //! the files, and [Overrides](struct.Overrides.html) from the command
+        //! line are used over those. Keys locked by a [policy](#method.set_policy)
+        //! are used over everything else.
         
+        let mut values = vec![self.global.get_default(key_path)];
+        for (_,system) in self.system.iter() {
+            values.push(layer_value(system,key_path));
+        }
+        values.push(layer_value(&self.global,key_path));
+        for (_,parent) in self.local_parents.iter() {
+            values.push(self.local_value(parent,key_path));
+        }
+        if let Some(ref local) = self.local {
+            values.push(self.local_value(local,key_path));
+        }
+        let env = match self.env {
+            Some(ref env) => env.get_value(key_path),
+            None => None,
+        };
+        let overrides = match self.overrides {
+            Some(ref overrides) => overrides.get_value(key_path),
+            None => None,
+        };
+
+        values.push(env);
+        values.push(overrides);
+        values.push(self.global.get_policy_value(key_path));
+
+        // ordered from the lowest priority to the highest
+        let mut result : Option<Type> = None;
+        for value in values.into_iter().flatten() {
+            result = Some(match result {
+                None => value,
+                Some(lower) => self.merge.merge(key_path,lower,value),
+            });
+        }
+
+        result?.without_unset()
+    }
+
+    pub fn set_env_source(&mut self, source : EnvSource) {
+        //! Uses the environment variables from `source` over both the local
+        //! and global values. The environment is read only and is never saved,
+        //! see [EnvSource](struct.EnvSource.html).
+
+        self.env = Some(source);
+    }
+
+    pub fn env_source(&self) -> Option<&EnvSource> {
+        self.env.as_ref()
+    }
+
+    pub fn set_overrides(&mut self, overrides : Overrides) {
+        //! Uses the command line `overrides` over every other value, they
+        //! are never saved, see [Overrides](struct.Overrides.html).
+
+        self.overrides = Some(overrides);
+    }
+
+    pub fn overrides(&self) -> Option<&Overrides> {
+        self.overrides.as_ref()
+    }
+
+    pub fn set_defaults(&mut self, defaults : SettingsRaw) {
+        //! Sets the values that are used when neither file has the key,
+        //! works the same as [Settings::set_defaults](struct.Settings.html#method.set_defaults).
+
+        self.global.set_defaults(defaults);
+    }
+
+    pub fn set_defaults_from_str(&mut self, buffer : &str) -> Result<(),Error> {
+        //! Parses the defaults with the `Format`.
+
+        self.global.set_defaults_from_str(buffer)
+    }
+
+    pub fn set_default<A>(&mut self, key_path : &str, value : &A)
+        where A : SupportedType + ?Sized,
+    {
+        self.global.set_default(key_path,value)
+    }
+
+    pub fn get_default(&self, key_path : &str) -> Option<Type> {
+        self.global.get_default(key_path)
+    }
+
+    pub fn is_default(&self, key_path : &str) -> bool {
+        //! Checks if the value is the default, so none of the files change it.
+
+        let local = match self.local {
+            Some(ref local) => self.local_value(local,key_path).is_none(),
+            None => true,
+        };
+        let system = self.system.iter().all(|(_,system)| system.get_value_file(key_path).is_none());
+        local && system && self.global.is_default(key_path)
+    }
+
+    pub fn reset_to_default(&mut self, key_path : &str) -> Option<Type> {
+        //! Removes the key from both the local and global settings so the
+        //! default is used again. Returns the value that was used before.
+
+        let value = self.get_value(key_path);
+        self.delete_key_local(key_path);
+        self.delete_key_global(key_path);
+        value
+    }
+
+    pub fn set_skip_defaults(&mut self, skip : bool) {
+        //! When `true`, global keys that are the same as their default
+        //! aren't saved. Local keys are always saved because they are
+        //! used over the global values.
+
+        self.global.set_skip_defaults(skip);
+    }
+
+    pub fn set_policy(&mut self, policy : SettingsRaw) {
+        //! Sets the policy, the keys in it are locked and can't be changed by
+        //! the global or local files, works the same as
+        //! [Settings::set_policy](struct.Settings.html#method.set_policy).
+
+        self.global.set_policy(policy);
+    }
+
+    pub fn set_policy_from_str(&mut self, buffer : &str) -> Result<(),Error> {
+        //! Parses the policy with the `Format`.
+
+        self.global.set_policy_from_str(buffer)
+    }
+
+    pub fn load_policy_from<P>(&mut self, path : P) -> Result<(),Error>
+        where P : AsRef<Path>,
+    {
+        //! Reads the policy from a file, like `/etc/{app}/policy.toml`.
+
+        let buffer = fs::read_to_string(path.as_ref())?;
+        self.global.set_policy_from_str(&buffer)
+            .map_err(|error| format_err!("{}: {}",path.as_ref().display(),error))
+    }
+
+    pub fn set_policy_value<A>(&mut self, key_path : &str, value : &A)
+        where A : SupportedType + ?Sized,
+    {
+        self.global.set_policy_value(key_path,value)
+    }
+
+    pub fn is_locked(&self, key_path : &str) -> bool {
+        //! Checks if the key is locked by the policy, so a UI can show
+        //! that it can't be changed.
+
+        self.global.is_locked(key_path)
+    }
+
+    pub fn set_merge_options(&mut self, options : MergeOptions) {
+        //! Sets how the local and global values are combined, see
+        //! [MergeOptions](struct.MergeOptions.html).
+
+        self.merge = options;
+    }
+
+    pub fn merge_options(&self) -> &MergeOptions {
+        &self.merge
+    }
+
+    pub fn get_value_with_origin(&self, key_path : &str) -> Option<(Type,Origin)> {
+        //! Gets the same value as `get_value`, along with where it came from.
+        //! 
+        //! When the value is a `Type::Complex` made from both files, the origin
+        //! is the local file since that is the one that has priority.
+        //! 
+        //! ```rust
+        //! # extern crate settingsfile;
+        //! use settingsfile::ShadowSettings;
+        //! use settingsfile::EmptyConfig; // dumb config for examples and testing
+        //! 
+        //! let settings = ShadowSettings::new(EmptyConfig{});
+        //! if let Some((value,origin)) = settings.get_value_with_origin("user.email") {
+        //!     println!("user.email is {}, set in {}",value,origin);
+        //! }
+        //! ```
+
+        let value = self.get_value(key_path)?;
+        Some((value,self.origin(key_path)))
+    }
+
+    pub fn keys_with_origin(&self) -> Vec<(String,Origin)> {
+        //! Lists every key along with where its value comes from, similar
+        //! to `git config --list --show-origin`. Sorted by key.
+
+        let mut keys = self.keys_global();
+        let env_keys = match self.env {
+            Some(ref env) => env.keys(),
+            None => Vec::new(),
+        };
+        let override_keys = match self.overrides {
+            Some(ref overrides) => overrides.keys(),
+            None => Vec::new(),
+        };
+        let default_keys = match Type::Complex(self.global.defaults().clone()).flatten(None) {
+            Type::Complex(flat) => flat.into_keys().collect(),
+            _ => Vec::new(),
+        };
+        let policy_keys = match Type::Complex(self.global.policy().clone()).flatten(None) {
+            Type::Complex(flat) => flat.into_keys().collect(),
+            _ => Vec::new(),
+        };
+        let system_keys : Vec<String> = self.system.iter().chain(self.local_parents.iter())
+            .flat_map(|(_,settings)| settings.keys())
+            .collect();
+        for key in self.keys_local().into_iter().chain(env_keys).chain(override_keys)
+            .chain(default_keys).chain(system_keys).chain(policy_keys)
+        {
+            if !keys.contains(&key) {
+                keys.push(key);
+            }
+        }
+        keys.retain(|key| self.get_value(key).is_some());
+        keys.sort();
+
+        keys.into_iter().map(|key| {
+            let origin = self.origin(&key);
+            (key,origin)
+        }).collect()
+    }
+
+    fn origin(&self, key_path : &str) -> Origin {
+        //! The origin of a value, assuming it exists.
+
+        if self.global.get_policy_value(key_path).is_some() {
+            return Origin::new("policy",None,None);
+        }
+
+        if let Some(ref overrides) = self.overrides {
+            if overrides.get_value(key_path).is_some() {
+                return Origin::new("command line",None,None);
+            }
+        }
+
+        if let Some(ref env) = self.env {
+            if env.get_value(key_path).is_some() {
+                return Origin::new("env",None,None);
+            }
+        }
+
         if let Some(ref local) = self.local {
-            match local.get_value(key_path) {
-                None => self.global.get_value(key_path),
-                Some(value) => { 
-                    // here we are creating a new complex that is a 
-                    // composite of the other two complexs (global,local)
-                    match value {
-                        Type::Complex(mut value) => {
-                            if let Some(Type::Complex(global)) = self.global.get_value(key_path) {
-                                for (k,v) in global {
-                                    if value.get(&k).is_none() {
-                                        value.insert(k, v);
-                                    }
-                                }
-                            }
-                            Some(Type::Complex(value))
-                        },
-                        _ => Some(value),
-                    } 
-                },
+            if self.local_value(local,key_path).is_some() {
+                return Origin::new("local",self.local_path().ok(),local.locate(key_path));
+            }
+        }
+
+        for (parent_path,parent) in self.local_parents.iter().rev() {
+            if self.local_value(parent,key_path).is_some() {
+                return Origin::new("local",Some(parent_path.clone()),parent.locate(key_path));
+            }
+        }
+
+        let path = self.ioconfig.get_path_and_file().ok();
+        if self.global.get_value_file(key_path).is_some() {
+            return Origin::new("global",path,self.global.locate(key_path));
+        }
+
+        for (system_path,system) in self.system.iter().rev() {
+            if system.get_value_file(key_path).is_some() {
+                return Origin::new("system",Some(system_path.clone()),system.locate(key_path));
             }
-        } else {
-            self.global.get_value(key_path)
         }
+
+        if self.global.get_default(key_path).is_some() {
+            return Origin::new("default",None,None);
+        }
+
+        Origin::new("global",path,self.global.locate(key_path))
     }
 
-    pub fn get_value_or<A:?Sized>(&self, key_path : &str, default_value : &A) -> Type
-        where A : SupportedType,
+    pub fn get_value_or<A>(&self, key_path : &str, default_value : &A) -> Type
+        where A : SupportedType + ?Sized,
     {
         match self.get_value(key_path) {
             Some(value) => value,
@@ -233,9 +1030,22 @@ impl<T> ShadowSettings<T> where T : Format + Clone {
         }
     }
     
+    pub fn get<A>(&self, key_path : &str) -> Result<A,Error>
+        where A : DeserializeOwned,
+    {
+        //! Gets the shadowed value at `key_path` and deserializes it into `A`.
+        //! Works the same as [Settings::get](struct.Settings.html#method.get).
+
+        from_value(key_path,self.get_value(key_path))
+    }
+
     pub fn get_value_local(&self, key_path : &str) -> Option<Type> {
+        //! The value in the local file, keys that aren't allowed locally
+        //! are skipped.
+
         match self.local {
-            Some(ref local) => local.get_value(key_path),
+            Some(ref local) if !self.is_rejected(key_path) => self.without_rejected(key_path,local.get_value(key_path)?),
+            Some(_) => None,
             None => None,
         } 
     }
@@ -244,16 +1054,28 @@ impl<T> ShadowSettings<T> where T : Format + Clone {
         self.global.get_value(key_path)
     }
 
-    pub fn set_value_local<A:?Sized>(&mut self, key_path : &str, value : &A) -> Result<(),Error> 
-        where A : SupportedType ,
+    pub fn set_value_local<A>(&mut self, key_path : &str, value : &A) -> Result<(),Error> 
+        where A : SupportedType + ?Sized,
     {
+        //! Sets the value in the local settings, returns a
+        //! [SettingsError::Locked](enum.SettingsError.html) if the
+        //! key is locked by the policy.
+
+        if self.is_locked(key_path) {
+            return Err(SettingsError::Locked { key : key_path.to_string() }.into());
+        }
+        self.allow_local_key(key_path);
+
         match self.local {
             Some(ref mut local) => local.set_value(key_path,value),
             None => {
                 // needs to make the local settings if they don't exist.
                 // the user doesn't care if it doesn't exist yet, they
                 // obviously want to use it because they are using this function
-                let mut local = Settings::new(self.ioconfig.clone());
+                let mut local = match self.local_path() {
+                    Ok(path) => Settings::new(self.config_for(&path)),
+                    Err(_) => Settings::new(self.ioconfig.clone()),
+                };
                 let result = local.set_value(key_path,value);
                 self.local = Some(local);
                 result
@@ -261,8 +1083,36 @@ impl<T> ShadowSettings<T> where T : Format + Clone {
         } 
     }
 
-    pub fn set_value_global<A:?Sized>(&mut self, key_path : &str, value : &A) -> Result<(),Error> 
-        where A : SupportedType ,
+    pub fn unset_local(&mut self, key_path : &str) -> Result<(),Error> {
+        //! Unsets the key locally, so `get_value` will return `None` even if
+        //! the key is set in the global file.
+        //! 
+        //! This is saved in the local file as a `Type::Unset`, use
+        //! `delete_key_local` to go back to using the global value.
+
+        self.set_value_local(key_path,&Type::Unset)
+    }
+
+    pub fn set_local<A>(&mut self, key_path : &str, value : &A) -> Result<(),Error>
+        where A : Serialize + ?Sized,
+    {
+        //! Serializes `value` and sets it in the local `Settings`.
+
+        let value = to_type(value).map_err(|error| error.under(key_path))?;
+        self.set_value_local(key_path,&value)
+    }
+
+    pub fn set_global<A>(&mut self, key_path : &str, value : &A) -> Result<(),Error>
+        where A : Serialize + ?Sized,
+    {
+        //! Serializes `value` and sets it in the global `Settings`.
+
+        let value = to_type(value).map_err(|error| error.under(key_path))?;
+        self.set_value_global(key_path,&value)
+    }
+
+    pub fn set_value_global<A>(&mut self, key_path : &str, value : &A) -> Result<(),Error> 
+        where A : SupportedType + ?Sized,
     {
         self.global.set_value(key_path,value)
     }
@@ -283,9 +1133,9 @@ impl<T> ShadowSettings<T> where T : Format + Clone {
     }
 
     pub fn delete_file_local(&self) -> bool {
-        match fs::remove_file(self.ioconfig.get_local_path_and_filename()){
+        match self.local_path() {
+            Ok(path) => fs::remove_file(path).is_ok(),
             Err(_) => false,
-            Ok(_) => true,
         }
     }
 
@@ -295,17 +1145,29 @@ impl<T> ShadowSettings<T> where T : Format + Clone {
 
     pub fn keys_local(&self) -> Vec<String> {
         if let Some(ref local) = self.local {
-            local.keys()
+            local.keys().into_iter().filter(|key| !self.is_rejected(key)).collect()
         } else {
             Vec::new()
         }
     }
 }
 
+fn layer_value<T>(settings : &Settings<T>, key_path : &str) -> Option<Type>
+    where T : Format + Clone,
+{
+    //! The value of one file, keys that are unset in the file, or have
+    //! a parent that is unset, are a `Type::Unset`.
+
+    if settings.is_unset(key_path) {
+        Some(Type::Unset)
+    } else {
+        settings.get_value_file(key_path)
+    }
+}
+
 // tests ////////////////////////////////////////////////////////////////////////////////
 #[cfg(test)]
 mod tests {
-    use SupportedType;
     use Format;
     use SettingsRaw;
     use Type;
@@ -313,6 +1175,7 @@ mod tests {
 
     use failure::Error;
     use std::collections::HashMap;
+    use std::path::PathBuf;
 
     // Dummy configuration, just enough to get it working.
     #[derive(Clone)]
@@ -321,26 +1184,51 @@ mod tests {
         fn filename(&self) -> String { "".to_string() }
         fn folder(&self) -> String { "".to_string() }
 
-        fn from_str<T>(&self,_:&str) -> Result<SettingsRaw,Error> where T : Format + Clone { 
+        fn from_str(&self,_:&str) -> Result<SettingsRaw,Error> { 
             Ok(HashMap::<String,Type>::new())
         }
-        fn to_string<T:?Sized>(&self,_:&T) -> Result<String,Error> where T : SupportedType { 
+        fn to_string(&self,_:&SettingsRaw) -> Result<String,Error> { 
             Ok("unimplemented".to_string())
         }
     }
 
+    // reads `key=value` lines, for tests that need to load files
+    fn read_lines(buffer : &str) -> SettingsRaw {
+        let mut raw = SettingsRaw::new();
+        for line in buffer.lines() {
+            let mut parts = line.splitn(2,'=');
+            if let (Some(key),Some(value)) = (parts.next(),parts.next()) {
+                ::structs::types::insert_path(&mut raw,key,Type::infer(value));
+            }
+        }
+        raw
+    }
+
+    // uses the `global` and `local` files in a folder
+    #[derive(Clone)]
+    struct Folder { path : PathBuf }
+    impl Format for Folder {
+        fn filename(&self) -> String { "global".to_string() }
+        fn folder(&self) -> String { "".to_string() }
+        fn local_filename(&self) -> Option<String> { Some("local".to_string()) }
+        fn base_dir(&self) -> Result<PathBuf,Error> { Ok(self.path.clone()) }
+        fn local_dir(&self) -> Result<PathBuf,Error> { Ok(self.path.clone()) }
+        fn from_str(&self,buffer:&str) -> Result<SettingsRaw,Error> { Ok(read_lines(buffer)) }
+        fn to_string(&self,_:&SettingsRaw) -> Result<String,Error> { Ok("".to_string()) }
+    }
+
     #[test]
     fn set_and_get_value() {
         //! confirms set and get functionality, that basic reading and writing works
         
         let mut test_obj = ShadowSettings::new(Configuration{});
         // setting global settings
-        assert_eq!(test_obj.set_value_global("a.b.c.d","mortan").is_ok(),true);
-        assert_eq!(test_obj.set_value_global("a.b.c.e","bobby lee").is_ok(),true);
-        assert_eq!(test_obj.set_value_global("a.b.f",&4453).is_ok(),true);
-        assert_eq!(test_obj.set_value_global("a.is_enabled",&true).is_ok(),true);
+        assert!(test_obj.set_value_global("a.b.c.d","mortan").is_ok());
+        assert!(test_obj.set_value_global("a.b.c.e","bobby lee").is_ok());
+        assert!(test_obj.set_value_global("a.b.f",&4453).is_ok());
+        assert!(test_obj.set_value_global("a.is_enabled",&true).is_ok());
         //setting local settings to override the global settings
-        assert_eq!(test_obj.set_value_local("a.b.c.d",&false).is_ok(),true);
+        assert!(test_obj.set_value_local("a.b.c.d",&false).is_ok());
         // checking all the global settings are accessible
         assert_eq!(test_obj.get_value_global("a.b.c.d"),Some(Type::Text("mortan".to_string())));
         assert_eq!(test_obj.get_value_global("a.b.c.e"),Some(Type::Text("bobby lee".to_string())));
@@ -359,8 +1247,8 @@ mod tests {
     fn get_value_or() {
         let mut test_obj = ShadowSettings::new(Configuration{});
 
-        assert_eq!(test_obj.set_value_global("a.b.c.d","mortan").is_ok(),true);
-        assert_eq!(test_obj.set_value_global("a.b.c.e","bobby lee").is_ok(),true);
+        assert!(test_obj.set_value_global("a.b.c.d","mortan").is_ok());
+        assert!(test_obj.set_value_global("a.b.c.e","bobby lee").is_ok());
 
         assert_eq!(test_obj.get_value_or("a.b.c.d", "not going to be used"),Type::Text("mortan".to_string()));
         assert_eq!(test_obj.get_value_or("a.b.c.f", "will be used"),Type::Text("will be used".to_string()));
@@ -375,13 +1263,425 @@ mod tests {
 
         let mut test_obj = ShadowSettings::new(Configuration{});
         // setting global settings
-        assert_eq!(test_obj.set_value_global("a.b.c.d","mortan").is_ok(),true);
-        assert_eq!(test_obj.set_value_global("a.b.c.e","bobby lee").is_ok(),true);
+        assert!(test_obj.set_value_global("a.b.c.d","mortan").is_ok());
+        assert!(test_obj.set_value_global("a.b.c.e","bobby lee").is_ok());
         // setting local settings
-        assert_eq!(test_obj.set_value_local("a.b.c.e","lee bo").is_ok(),true);
+        assert!(test_obj.set_value_local("a.b.c.e","lee bo").is_ok());
 
         let other_setting = test_obj.get_value("a.b.c").unwrap().to_complex().unwrap();
         assert_eq!(other_setting.get("d"), Some(&Type::Text("mortan".to_string())));
         assert_eq!(other_setting.get("e"), Some(&Type::Text("lee bo".to_string())));
+
+        // siblings deeper down are kept too
+        assert!(test_obj.set_value_global("a.b.f",&4453).is_ok());
+        let other_setting = test_obj.get_value("a").unwrap().flatten(None).to_complex().unwrap();
+        assert_eq!(other_setting.get("b.c.d"), Some(&Type::Text("mortan".to_string())));
+        assert_eq!(other_setting.get("b.c.e"), Some(&Type::Text("lee bo".to_string())));
+        assert_eq!(other_setting.get("b.f"), Some(&Type::Int(4453)));
+    }
+
+    #[test]
+    fn unsetting() {
+        let mut test_obj = ShadowSettings::new(Configuration{});
+        assert!(test_obj.set_value_global("user.name","snsvrno").is_ok());
+        assert!(test_obj.set_value_global("user.email","global email").is_ok());
+        assert!(test_obj.set_value_global("editor.theme","dark").is_ok());
+
+        assert!(test_obj.unset_local("user.email").is_ok());
+        assert!(test_obj.unset_local("editor").is_ok());
+
+        assert_eq!(test_obj.get_value("user.email"),None);
+        assert_eq!(test_obj.get_value("editor.theme"),None);
+        assert_eq!(test_obj.get_value("editor"),None);
+        assert_eq!(test_obj.get_value_local("user.email"),Some(Type::Unset));
+        assert_eq!(test_obj.get_value_global("user.email"),Some(Type::Text("global email".to_string())));
+
+        let user = test_obj.get_value("user").unwrap().to_complex().unwrap();
+        assert_eq!(user.len(),1);
+        assert_eq!(user.get("name"),Some(&Type::Text("snsvrno".to_string())));
+
+        let keys : Vec<String> = test_obj.keys_with_origin().into_iter().map(|(key,_)| key).collect();
+        assert_eq!(keys,vec!["user.name".to_string()]);
+
+        assert!(test_obj.delete_key_local("user.email").is_some());
+        assert_eq!(test_obj.get_value("user.email"),Some(Type::Text("global email".to_string())));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn environment() {
+        use EnvSource;
+
+        let mut test_obj = ShadowSettings::new(Configuration{});
+        assert!(test_obj.set_value_global("user.name","global name").is_ok());
+        assert!(test_obj.set_value_global("user.email","global email").is_ok());
+        assert!(test_obj.set_value_local("user.email","local email").is_ok());
+        test_obj.set_env_source(EnvSource::new("MYAPP_").with_vars(vec![
+            ("MYAPP_USER__EMAIL","env email"),
+            ("MYAPP_PORT","8080"),
+        ]));
+
+        assert_eq!(test_obj.get_value("user.email"),Some(Type::Text("env email".to_string())));
+        assert_eq!(test_obj.get_value("user.name"),Some(Type::Text("global name".to_string())));
+        assert_eq!(test_obj.get_value("port"),Some(Type::Int(8080)));
+        assert_eq!(test_obj.get_value_local("user.email"),Some(Type::Text("local email".to_string())));
+
+        let (_,origin) = test_obj.get_value_with_origin("port").unwrap();
+        assert_eq!(origin.layer(),"env");
+        assert_eq!(test_obj.keys_with_origin().len(),3);
+    }
+
+    #[test]
+    fn command_line() {
+        use {EnvSource,Overrides};
+
+        let mut test_obj = ShadowSettings::new(Configuration{});
+        assert!(test_obj.set_value_local("jobs",&2).is_ok());
+        test_obj.set_env_source(EnvSource::new("MYAPP_").with_vars(vec![("MYAPP_JOBS","3")]));
+        let (overrides,rest) = Overrides::from_args(vec!["app","-c","jobs=4","build"]).unwrap();
+        test_obj.set_overrides(overrides);
+
+        assert_eq!(rest,vec!["app","build"]);
+        assert_eq!(test_obj.get_value("jobs"),Some(Type::Int(4)));
+        assert_eq!(test_obj.get_value_local("jobs"),Some(Type::Int(2)));
+        assert_eq!(test_obj.get_value_with_origin("jobs").unwrap().1.layer(),"command line");
+    }
+
+    #[test]
+    fn defaults() {
+        let mut test_obj = ShadowSettings::new(Configuration{});
+        test_obj.set_default("editor.theme","light");
+        test_obj.set_default("editor.tabs",&4);
+        assert!(test_obj.set_value_global("editor.theme","dark").is_ok());
+        assert!(test_obj.set_value_local("editor.tabs",&2).is_ok());
+
+        assert_eq!(test_obj.get_value("editor.theme"),Some(Type::Text("dark".to_string())));
+        assert_eq!(test_obj.get_value("editor.tabs"),Some(Type::Int(2)));
+        assert!(!test_obj.is_default("editor.tabs"));
+        assert!(!test_obj.is_default("editor.theme"));
+
+        assert_eq!(test_obj.reset_to_default("editor.tabs"),Some(Type::Int(2)));
+        assert_eq!(test_obj.get_value("editor.tabs"),Some(Type::Int(4)));
+        assert!(test_obj.is_default("editor.tabs"));
+        assert_eq!(test_obj.get_value_with_origin("editor.tabs").unwrap().1.layer(),"default");
+        assert_eq!(test_obj.keys_with_origin().len(),2);
+
+        // still there after loading the global file
+        let mut file = ::tempfile::tempfile().unwrap();
+        assert!(test_obj.load_global_from(&mut file).is_ok());
+        assert_eq!(test_obj.get_value("editor.theme"),Some(Type::Text("light".to_string())));
+    }
+
+    #[test]
+    fn policy() {
+        use SettingsError;
+
+        let mut test_obj = ShadowSettings::new(Configuration{});
+        assert!(test_obj.set_value_local("update.channel","nightly").is_ok());
+        test_obj.set_policy_value("update.channel","stable");
+
+        assert!(test_obj.is_locked("update.channel"));
+        assert!(!test_obj.is_locked("update.check"));
+        assert_eq!(test_obj.get_value("update.channel"),Some(Type::Text("stable".to_string())));
+        assert_eq!(test_obj.get_value_with_origin("update.channel").unwrap().1.layer(),"policy");
+
+        let error = test_obj.set_value_local("update.channel","beta").unwrap_err();
+        assert_eq!(error.downcast_ref::<SettingsError>(),Some(&SettingsError::Locked { key : "update.channel".to_string() }));
+        assert!(test_obj.set_value_global("update.channel","beta").is_err());
+        assert!(test_obj.unset_local("update").is_err());
+        assert!(test_obj.set_value_local("update.check",&true).is_ok());
+
+        // still locked after loading the global file
+        let mut file = ::tempfile::tempfile().unwrap();
+        assert!(test_obj.load_global_from(&mut file).is_ok());
+        assert!(test_obj.is_locked("update.channel"));
+    }
+
+    #[test]
+    fn local_allowlist() {
+        use std::io::{Write,Seek,SeekFrom};
+        use SettingsError;
+
+        #[derive(Clone)]
+        struct Lines { }
+        impl Format for Lines {
+            fn filename(&self) -> String { "".to_string() }
+            fn folder(&self) -> String { "".to_string() }
+            fn from_str(&self,buffer:&str) -> Result<SettingsRaw,Error> { Ok(read_lines(buffer)) }
+            fn to_string(&self,_:&SettingsRaw) -> Result<String,Error> { Ok("".to_string()) }
+        }
+
+        let mut file = ::tempfile::tempfile().unwrap();
+        file.write_all(b"editor.theme=dark\neditor_command=rm\ncredentials.token=stolen\n").unwrap();
+
+        let mut test_obj = ShadowSettings::new(Lines{});
+        assert!(test_obj.set_value_global("credentials.token","secret").is_ok());
+        test_obj.set_local_allowlist(vec!["editor"]);
+
+        file.seek(SeekFrom::Start(0)).unwrap();
+        assert!(test_obj.load_local_from(&mut file).is_ok());
+        assert_eq!(test_obj.rejected_local_keys(),&["credentials.token".to_string(),"editor_command".to_string()]);
+        assert_eq!(test_obj.get_value("credentials.token"),Some(Type::Text("secret".to_string())));
+        assert_eq!(test_obj.get_value("editor.theme"),Some(Type::Text("dark".to_string())));
+        assert_eq!(test_obj.get_value("editor_command"),None);
+
+        test_obj.set_strict_local(true);
+        file.seek(SeekFrom::Start(0)).unwrap();
+        let error = test_obj.load_local_from(&mut file).unwrap_err();
+        assert_eq!(error.downcast_ref::<SettingsError>(),Some(&SettingsError::LocalNotAllowed {
+            keys : vec!["credentials.token".to_string(),"editor_command".to_string()],
+        }));
+    }
+
+    #[test]
+    fn keeping_rejected_keys() {
+        use std::fs;
+
+        // writes `key=value` lines so saving can be checked
+        #[derive(Clone)]
+        struct Lines { path : PathBuf }
+        impl Format for Lines {
+            fn filename(&self) -> String { "global".to_string() }
+            fn folder(&self) -> String { "".to_string() }
+            fn local_filename(&self) -> Option<String> { Some("local".to_string()) }
+            fn base_dir(&self) -> Result<PathBuf,Error> { Ok(self.path.clone()) }
+            fn local_dir(&self) -> Result<PathBuf,Error> { Ok(self.path.clone()) }
+            fn from_str(&self,buffer:&str) -> Result<SettingsRaw,Error> { Ok(read_lines(buffer)) }
+            fn to_string(&self,object:&SettingsRaw) -> Result<String,Error> {
+                let mut lines : Vec<String> = match Type::Complex(object.clone()).flatten(None) {
+                    Type::Complex(flat) => flat.iter().map(|(key,value)| format!("{}={}",key,value)).collect(),
+                    _ => Vec::new(),
+                };
+                lines.sort();
+                Ok(lines.join("\n"))
+            }
+        }
+
+        let dir = ::tempfile::tempdir().unwrap();
+        let local = dir.path().join("local");
+        fs::write(&local,"editor.theme=dark\ncredentials.token=stolen\n").unwrap();
+
+        let mut test_obj = ShadowSettings::new(Lines { path : dir.path().to_path_buf() });
+        test_obj.set_local_allowlist(vec!["editor"]);
+        assert!(test_obj.load_local().is_ok());
+        assert_eq!(test_obj.rejected_local_keys(),&["credentials.token".to_string()]);
+        assert_eq!(test_obj.get_value("credentials.token"),None);
+        assert_eq!(test_obj.get_value("credentials"),None);
+        assert_eq!(test_obj.get_value_local("credentials.token"),None);
+        assert_eq!(test_obj.keys_local(),vec!["editor.theme".to_string()]);
+
+        assert!(test_obj.set_value_local("editor.tabs",&4).is_ok());
+        assert!(test_obj.save().is_ok());
+        assert_eq!(fs::read_to_string(&local).unwrap(),"credentials.token=stolen\neditor.tabs=4\neditor.theme=dark");
+
+        // setting it directly allows it
+        assert!(test_obj.set_value_local("credentials.token","mine").is_ok());
+        assert_eq!(test_obj.get_value("credentials.token"),Some(Type::Text("mine".to_string())));
+        assert!(test_obj.rejected_local_keys().is_empty());
+    }
+
+    #[test]
+    fn saving_changed_local() {
+        use std::fs;
+        use SettingsError;
+
+        let dir = ::tempfile::tempdir().unwrap();
+        let local = dir.path().join("local");
+        fs::write(&local,"user.name=local\n").unwrap();
+
+        let mut test_obj = ShadowSettings::new(Folder { path : dir.path().to_path_buf() });
+        assert!(test_obj.load_local().is_ok());
+        assert!(test_obj.save().is_ok());
+        assert!(!dir.path().join("local.lock").exists());
+        assert_eq!(fs::read_dir(dir.path().join("locks")).unwrap().count(),1);
+
+        // someone else changed it
+        fs::write(&local,"user.name=someone else\n").unwrap();
+        let error = test_obj.save().unwrap_err();
+        assert_eq!(error.downcast_ref::<SettingsError>(),Some(&SettingsError::ConcurrentModification { path : local.clone() }));
+        assert_eq!(fs::read_to_string(&local).unwrap(),"user.name=someone else\n");
+
+        assert!(test_obj.save_force().is_ok());
+        assert_eq!(fs::read_to_string(&local).unwrap(),"");
+        assert!(test_obj.save().is_ok());
+    }
+
+    #[test]
+    fn trusting() {
+        use std::fs;
+        use SettingsError;
+
+        let dir = ::tempfile::tempdir().unwrap();
+        let local = dir.path().join("local");
+        fs::write(&local,"user.name=local\n").unwrap();
+
+        let mut test_obj = ShadowSettings::new(Folder { path : dir.path().to_path_buf() });
+        test_obj.set_require_trust(true);
+        assert!(!test_obj.is_local_trusted());
+        let error = test_obj.load_local().unwrap_err();
+        assert_eq!(error.downcast_ref::<SettingsError>(),Some(&SettingsError::LocalNotTrusted { path : local.clone() }));
+        assert_eq!(test_obj.get_value("user.name"),None);
+
+        assert!(test_obj.trust_local().is_ok());
+        assert!(test_obj.is_local_trusted());
+        assert!(dir.path().join("global.trusted").exists());
+        assert!(test_obj.load_local().is_ok());
+        assert_eq!(test_obj.get_value("user.name"),Some(Type::Text("local".to_string())));
+
+        // changing the file means it isn't trusted
+        fs::write(&local,"user.name=changed\n").unwrap();
+        assert!(!test_obj.is_local_trusted());
+        assert!(test_obj.load_local().is_err());
+
+        // saving it ourselves keeps it trusted
+        assert!(test_obj.trust_local().is_ok());
+        assert!(test_obj.load_local().is_ok());
+        assert!(test_obj.set_value_local("user.email","local").is_ok());
+        assert!(test_obj.save().is_ok());
+        assert_ne!(fs::read_to_string(&local).unwrap(),"user.name=changed\n");
+        assert!(test_obj.is_local_trusted());
+        assert!(test_obj.load_local().is_ok());
+
+        fs::write(&local,"user.name=changed\n").unwrap();
+        assert!(test_obj.untrust_local().is_ok());
+        assert!(!test_obj.is_local_trusted());
+
+        // without requiring trust it is used anyways
+        test_obj.set_require_trust(false);
+        assert!(test_obj.load_local().is_ok());
+        assert_eq!(test_obj.get_value("user.name"),Some(Type::Text("changed".to_string())));
+    }
+
+    #[test]
+    fn trusting_discovered() {
+        use std::fs;
+        use LocalDiscovery;
+
+        let home = ::tempfile::tempdir().unwrap();
+        let project = home.path().join("project");
+        let deep = project.join("src");
+        fs::create_dir_all(&deep).unwrap();
+        fs::write(project.join("local"),"user.name=project\n").unwrap();
+        fs::write(deep.join("local"),"user.email=src\n").unwrap();
+
+        let mut test_obj = ShadowSettings::new(Folder { path : deep.clone() });
+        test_obj.set_local_discovery(LocalDiscovery::walk_up().with_home(home.path()).with_merge_all(true));
+        test_obj.set_require_trust(true);
+        assert!(test_obj.load_local().is_err());
+
+        // trusts the parent too
+        assert!(test_obj.trust_local().is_ok());
+        assert!(test_obj.is_local_trusted());
+        assert!(test_obj.load_local().is_ok());
+        assert_eq!(test_obj.get_value("user.name"),Some(Type::Text("project".to_string())));
+
+        fs::write(project.join("local"),"user.name=changed\n").unwrap();
+        assert!(!test_obj.is_local_trusted());
+        assert!(test_obj.load_local().is_err());
+    }
+
+    #[test]
+    fn discovering() {
+        use std::fs;
+        use LocalDiscovery;
+
+        let home = ::tempfile::tempdir().unwrap();
+        let project = home.path().join("project");
+        let deep = project.join("src");
+        fs::create_dir_all(&deep).unwrap();
+        fs::write(home.path().join("local"),"user.name=home\n").unwrap();
+        fs::write(project.join("local"),"user.name=project\nuser.email=project\n").unwrap();
+        fs::write(deep.join("local"),"user.name=src\n").unwrap();
+
+        let mut test_obj = ShadowSettings::new(Folder { path : deep.clone() });
+        test_obj.set_local_discovery(LocalDiscovery::walk_up().with_home(home.path()).with_merge_all(true));
+        assert!(test_obj.load_local().is_ok());
+        assert_eq!(test_obj.local_paths(),vec![deep.join("local"),project.join("local")]);
+        assert_eq!(test_obj.get_value("user.name"),Some(Type::Text("src".to_string())));
+        assert_eq!(test_obj.get_value("user.email"),Some(Type::Text("project".to_string())));
+        assert_eq!(test_obj.get_value_with_origin("user.email").unwrap().1.path(),Some(project.join("local").as_path()));
+
+        // only the nearest
+        fs::remove_file(deep.join("local")).unwrap();
+        let mut test_obj = ShadowSettings::new(Folder { path : deep.clone() });
+        test_obj.set_local_discovery(LocalDiscovery::walk_up().with_home(home.path()));
+        assert!(test_obj.load_local().is_ok());
+        assert_eq!(test_obj.local_paths(),vec![project.join("local")]);
+        assert_eq!(test_obj.get_value("user.name"),Some(Type::Text("project".to_string())));
+    }
+
+    #[test]
+    fn reloading() {
+        use std::fs;
+        use std::sync::{Arc,Mutex};
+
+        let dir = ::tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("global"),"font.size=12\nui.theme=dark\n").unwrap();
+        fs::write(dir.path().join("local"),"ui.theme=light\n").unwrap();
+
+        let mut test_obj = ShadowSettings::new(Folder { path : dir.path().to_path_buf() });
+        assert!(test_obj.load().is_ok());
+        assert_eq!(test_obj.file_paths(),vec![dir.path().join("global"),dir.path().join("local")]);
+        assert!(test_obj.reload().unwrap().is_empty());
+
+        let sizes : Arc<Mutex<Vec<Option<Type>>>> = Arc::new(Mutex::new(Vec::new()));
+        let recorded = sizes.clone();
+        let _subscription = test_obj.subscribe_global("font.size",move |_,_,new| {
+            recorded.lock().unwrap().push(new.cloned());
+        });
+
+        fs::write(dir.path().join("global"),"font.size=14\nui.theme=dark\n").unwrap();
+        fs::remove_file(dir.path().join("local")).unwrap();
+        assert_eq!(test_obj.reload().unwrap(),vec!["font.size".to_string(),"ui.theme".to_string()]);
+        assert_eq!(test_obj.get_value("ui.theme"),Some(Type::Text("dark".to_string())));
+        assert!(test_obj.local_paths().is_empty());
+
+        // the callbacks are kept
+        fs::write(dir.path().join("global"),"font.size=16\nui.theme=dark\n").unwrap();
+        assert_eq!(test_obj.reload().unwrap(),vec!["font.size".to_string()]);
+        assert_eq!(*sizes.lock().unwrap(),vec![Some(Type::Int(14)),Some(Type::Int(16))]);
+    }
+
+    #[test]
+    fn merging_arrays() {
+        use {MergeOptions,ArrayMerge};
+
+        let mut test_obj = ShadowSettings::new(Configuration{});
+        assert!(test_obj.set_global("paths",&vec!["/usr/bin","/bin"]).is_ok());
+        assert!(test_obj.set_local("paths",&vec!["./bin"]).is_ok());
+        assert_eq!(test_obj.get_value("paths").unwrap().to_array().unwrap().len(),1);
+
+        test_obj.set_merge_options(MergeOptions::new().with_arrays(ArrayMerge::Append));
+        assert_eq!(test_obj.get_value("paths").unwrap().to_array().unwrap(),vec![
+            Type::Text("/usr/bin".to_string()),
+            Type::Text("/bin".to_string()),
+            Type::Text("./bin".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn origins() {
+        let mut test_obj = ShadowSettings::new(Configuration{});
+        assert!(test_obj.set_value_global("user.name","global name").is_ok());
+        assert!(test_obj.set_value_global("user.email","global email").is_ok());
+        assert!(test_obj.set_value_local("user.email","local email").is_ok());
+
+        let (value,origin) = test_obj.get_value_with_origin("user.name").unwrap();
+        assert_eq!(value,Type::Text("global name".to_string()));
+        assert_eq!(origin.layer(),"global");
+
+        let (value,origin) = test_obj.get_value_with_origin("user.email").unwrap();
+        assert_eq!(value,Type::Text("local email".to_string()));
+        assert_eq!(origin.layer(),"local");
+        assert_eq!(origin.line(),None);
+
+        assert!(test_obj.get_value_with_origin("user.other").is_none());
+
+        let keys : Vec<(String,String)> = test_obj.keys_with_origin().into_iter()
+            .map(|(key,origin)| (key,origin.layer().to_string()))
+            .collect();
+        assert_eq!(keys,vec![
+            ("user.email".to_string(),"local".to_string()),
+            ("user.name".to_string(),"global".to_string()),
+        ]);
+    }
+}
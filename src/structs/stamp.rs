@@ -0,0 +1,122 @@
+use failure::Error;
+use sha2::{Sha256,Digest};
+
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// What a file looked like when it was read, to tell if it changed since
+#[derive(Clone,Debug,PartialEq)]
+pub struct FileStamp {
+    modified : Option<SystemTime>,
+    len : u64,
+    hash : String,
+}
+
+impl FileStamp {
+    pub fn read(path : &Path) -> Result<Option<FileStamp>,Error> {
+        //! Stamps the file at `path`, `None` if it doesn't exist.
+
+        let metadata = match fs::metadata(path) {
+            Ok(metadata) => metadata,
+            Err(ref error) if error.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(error.into()),
+        };
+
+        Ok(Some(FileStamp {
+            modified : metadata.modified().ok(),
+            len : metadata.len(),
+            hash : hash(&fs::read(path)?),
+        }))
+    }
+
+    pub fn with_contents(path : &Path, contents : &[u8]) -> Result<FileStamp,Error> {
+        //! Stamps the file at `path` with `contents` that were already
+        //! read from it. If it changed since they were read it won't be
+        //! current.
+
+        let metadata = fs::metadata(path)?;
+        Ok(FileStamp {
+            modified : metadata.modified().ok(),
+            len : metadata.len(),
+            hash : hash(contents),
+        })
+    }
+
+    pub fn is_current(&self, path : &Path) -> Result<bool,Error> {
+        //! Checks that the file at `path` still has the same modified
+        //! time, size and contents.
+
+        let metadata = match fs::metadata(path) {
+            Ok(metadata) => metadata,
+            Err(ref error) if error.kind() == ErrorKind::NotFound => return Ok(false),
+            Err(error) => return Err(error.into()),
+        };
+
+        // no need to read it when these are already different
+        if metadata.modified().ok() != self.modified || metadata.len() != self.len {
+            return Ok(false);
+        }
+        // timestamps can be too coarse to see a quick edit
+        Ok(hash(&fs::read(path)?) == self.hash)
+    }
+}
+
+pub fn hash(contents : &[u8]) -> String {
+    //! The sha256 of `contents`, as hex.
+
+    Sha256::digest(contents).iter().map(|byte| format!("{:02x}",byte)).collect()
+}
+
+// the stamp of the last load or save, behind a lock so saving
+// can update it without needing `&mut`.
+#[derive(Debug,Default)]
+pub struct LastStamp(Mutex<Option<FileStamp>>);
+
+impl LastStamp {
+    pub fn get(&self) -> Option<FileStamp> {
+        match self.0.lock() {
+            Ok(stamp) => stamp.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+
+    pub fn set(&self, stamp : Option<FileStamp>) {
+        match self.0.lock() {
+            Ok(mut current) => *current = stamp,
+            Err(poisoned) => *poisoned.into_inner() = stamp,
+        }
+    }
+}
+
+impl Clone for LastStamp {
+    fn clone(&self) -> LastStamp {
+        LastStamp(Mutex::new(self.get()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FileStamp;
+    use std::fs;
+
+    #[test]
+    fn stamping() {
+        let dir = ::tempfile::tempdir().unwrap();
+        let path = dir.path().join("settings");
+        assert_eq!(FileStamp::read(&path).unwrap(),None);
+
+        fs::write(&path,"size = 12").unwrap();
+        let stamp = FileStamp::read(&path).unwrap().unwrap();
+        assert!(stamp.is_current(&path).unwrap());
+
+        // same size, and likely the same timestamp
+        fs::write(&path,"size = 14").unwrap();
+        assert!(!stamp.is_current(&path).unwrap());
+
+        fs::remove_file(&path).unwrap();
+        assert!(!stamp.is_current(&path).unwrap());
+    }
+}
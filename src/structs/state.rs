@@ -0,0 +1,366 @@
+use Format;
+use Settings;
+use SettingsError;
+use SettingsRaw;
+use SupportedType;
+use Type;
+use Location;
+use XdgDir;
+
+use serde::ser::Serialize;
+use serde::de::DeserializeOwned;
+use failure::Error;
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// A file for things the program keeps track of, not the user
+///
+/// Things like "last update check" or "recent projects" don't belong in the
+/// file the user edits. A `StateFile` keeps them in the state directory
+/// (`$XDG_STATE_HOME/{app_name}` with `Location::Xdg`, or next to the
+/// configuration) in a file called `{file_name}.state`.
+///
+/// It works like a [Settings](struct.Settings.html) with the same key paths,
+/// but it is never shadowed by local files and every change is saved right
+/// away. Only the keys that changed are written, so other instances of the
+/// program running at the same time keep their changes, and when two of them
+/// change the same key the newest change wins. It is written as JSON when the `json` feature is on, otherwise with
+/// the `Format` it was made with, use [with_format](#method.with_format) to
+/// pick something else.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// # extern crate settingsfile;
+/// use settingsfile::{StateFile,Type};
+/// use settingsfile::EmptyConfig; // dumb config for examples and testing
+///
+/// let mut state = StateFile::new(EmptyConfig{});
+/// state.set_value("update.last_check",&1546300800).unwrap();
+/// assert_eq!(state.get_value("update.last_check"),Some(Type::Int(1546300800)));
+/// ```
+#[derive(Clone)]
+pub struct StateFile<T> where T : Format + Clone {
+    settings : Settings<StateFormat<T>>,
+}
+
+impl<T> StateFile<T> where T : Format + Clone {
+    pub fn new(config : T) -> StateFile<T> {
+        //! Creates the state for `config`, loading the file if it exists.
+        //!
+        //! A file that can't be read is replaced the next time something
+        //! is set, with a `warn!()`.
+
+        StateFile::create(StateFormat { config, format : None })
+    }
+
+    pub fn with_format<F>(config : T, format : F) -> StateFile<T>
+        where F : Format + Send + Sync + 'static,
+    {
+        //! Creates the state for `config` but reads and writes it with
+        //! `format`. Only the format's `from_str` and `to_string` are used.
+
+        StateFile::create(StateFormat { config, format : Some(Arc::new(format)) })
+    }
+
+    fn create(format : StateFormat<T>) -> StateFile<T> {
+        let mut state = StateFile { settings : Settings::new(format) };
+        if let Err(error) = state.load() {
+            warn!("{}",error);
+        }
+        state
+    }
+
+    pub fn load(&mut self) -> Result<(),Error> {
+        //! Reads the file again, it isn't an error if it doesn't exist.
+
+        if self.path()?.exists() {
+            self.settings.load()?;
+        }
+        Ok(())
+    }
+
+    pub fn save(&self) -> Result<(),Error> {
+        //! Saves the file, this is already done every time something changes.
+        //!
+        //! Writes everything, even over changes another instance of the
+        //! program made since this one loaded or saved.
+
+        self.settings.save_force()
+    }
+
+    fn save_changes(&mut self) -> Result<(),Error> {
+        //! Writes the keys that were just changed over what is in the file.
+
+        match self.settings.save_merged() {
+            Err(ref error) if is_conflict(error) => (),
+            result => return result,
+        }
+
+        // the file was deleted, there is nothing to keep
+        if !self.path()?.exists() {
+            return self.settings.save_force();
+        }
+
+        // another instance changed the same keys, ours are newer
+        let changes : Vec<(String,Option<Type>)> = self.settings.dirty_keys().into_iter()
+            .map(|key| {
+                let value = self.settings.get_value(&key);
+                (key,value)
+            })
+            .collect();
+        self.settings.load()?;
+        for (key,value) in changes {
+            match value {
+                Some(value) => self.settings.set_value(&key,&value)?,
+                None => { self.settings.delete_key(&key); },
+            }
+        }
+        self.settings.save_merged()
+    }
+
+    pub fn path(&self) -> Result<PathBuf,Error> {
+        //! Where the state is saved.
+
+        self.settings.path()
+    }
+
+    pub fn get_value(&self, key_path : &str) -> Option<Type> {
+        self.settings.get_value(key_path)
+    }
+
+    pub fn get_value_or<A>(&self, key_path : &str, default_value : &A) -> Type
+        where A : SupportedType + ?Sized,
+    {
+        self.settings.get_value_or(key_path,default_value)
+    }
+
+    pub fn get<A>(&self, key_path : &str) -> Result<A,Error>
+        where A : DeserializeOwned,
+    {
+        //! Gets the value at `key_path` and deserializes it into `A`.
+        //! Works the same as [Settings::get](struct.Settings.html#method.get).
+
+        self.settings.get(key_path)
+    }
+
+    pub fn set_value<A>(&mut self, key_path : &str, value : &A) -> Result<(),Error>
+        where A : SupportedType + ?Sized,
+    {
+        //! Sets the value and saves the file.
+
+        self.settings.set_value(key_path,value)?;
+        self.save_changes()
+    }
+
+    pub fn set<A>(&mut self, key_path : &str, value : &A) -> Result<(),Error>
+        where A : Serialize + ?Sized,
+    {
+        //! Serializes `value`, sets it and saves the file.
+
+        self.settings.set(key_path,value)?;
+        self.save_changes()
+    }
+
+    pub fn delete_key(&mut self, key_path : &str) -> Result<Option<Type>,Error> {
+        //! Removes the key and saves the file, returns the old value.
+
+        let value = self.settings.delete_key(key_path);
+        if value.is_some() {
+            self.save_changes()?;
+        }
+        Ok(value)
+    }
+
+    pub fn keys(&self) -> Vec<String> {
+        self.settings.keys()
+    }
+}
+
+fn is_conflict(error : &Error) -> bool {
+    matches!(error.downcast_ref::<SettingsError>(),Some(SettingsError::Conflict { .. }))
+}
+
+// puts the state of a `Format` in the state directory, and reads and
+// writes it with a different format.
+#[derive(Clone)]
+struct StateFormat<T> where T : Format + Clone {
+    config : T,
+    format : Option<Arc<dyn Format + Send + Sync>>,
+}
+
+impl<T> Format for StateFormat<T> where T : Format + Clone {
+    fn filename(&self) -> String { format!("{}.state",self.config.filename()) }
+    fn folder(&self) -> String { self.config.folder() }
+    fn app_name(&self) -> String { self.config.app_name() }
+    fn location(&self) -> Location { self.config.location() }
+    fn var_os(&self, key : &str) -> Option<OsString> { self.config.var_os(key) }
+    fn base_dir(&self) -> Result<PathBuf,Error> { self.config.base_dir() }
+
+    fn extension(&self) -> Option<String> {
+        match self.format {
+            Some(ref format) => format.extension(),
+            None if cfg!(feature = "json") => Some("json".to_string()),
+            None => self.config.extension(),
+        }
+    }
+
+    fn get_path(&self) -> Result<PathBuf,Error> {
+        self.config.get_dir(XdgDir::State)
+    }
+
+    fn get_legacy_path_and_file(&self) -> Result<Option<PathBuf>,Error> {
+        // it never was anywhere else
+        Ok(None)
+    }
+
+    fn from_str(&self, buffer : &str) -> Result<SettingsRaw,Error> {
+        match self.format {
+            Some(ref format) => format.from_str(buffer),
+            None => self.default_from_str(buffer),
+        }
+    }
+
+    fn to_string(&self, object : &SettingsRaw) -> Result<String,Error> {
+        match self.format {
+            Some(ref format) => format.to_string(object),
+            None => self.default_to_string(object),
+        }
+    }
+}
+
+impl<T> StateFormat<T> where T : Format + Clone {
+    #[cfg(feature = "json")]
+    fn default_from_str(&self, buffer : &str) -> Result<SettingsRaw,Error> {
+        Ok(::serde_json::from_str(buffer)?)
+    }
+
+    #[cfg(not(feature = "json"))]
+    fn default_from_str(&self, buffer : &str) -> Result<SettingsRaw,Error> {
+        self.config.from_str(buffer)
+    }
+
+    #[cfg(feature = "json")]
+    fn default_to_string(&self, object : &SettingsRaw) -> Result<String,Error> {
+        Ok(::serde_json::to_string(object)?)
+    }
+
+    #[cfg(not(feature = "json"))]
+    fn default_to_string(&self, object : &SettingsRaw) -> Result<String,Error> {
+        self.config.to_string(object)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use Format;
+    use SettingsRaw;
+    use StateFile;
+    use Type;
+    use Location;
+
+    use failure::Error;
+    use std::ffi::OsString;
+    use std::path::{Path,PathBuf};
+
+    // a format that can only keep one `count`
+    #[derive(Clone)]
+    struct Counter { base : PathBuf }
+    impl Format for Counter {
+        fn filename(&self) -> String { "settings".to_string() }
+        fn folder(&self) -> String { ".app".to_string() }
+        fn location(&self) -> Location { Location::Xdg }
+        fn base_dir(&self) -> Result<PathBuf,Error> { Ok(self.base.clone()) }
+        fn var_os(&self, _ : &str) -> Option<OsString> { None }
+        fn from_str(&self,buffer:&str) -> Result<SettingsRaw,Error> {
+            let mut raw = SettingsRaw::new();
+            raw.insert("count".to_string(),Type::infer(buffer));
+            Ok(raw)
+        }
+        fn to_string(&self,object:&SettingsRaw) -> Result<String,Error> {
+            Ok(object.get("count").map(|count| count.to_string()).unwrap_or_default())
+        }
+    }
+
+    fn counter(base : &Path) -> Counter { Counter { base : base.to_path_buf() } }
+
+    // `key=value` lines, so it can keep more than one key
+    #[derive(Clone)]
+    struct Lines { }
+    impl Format for Lines {
+        fn filename(&self) -> String { "".to_string() }
+        fn folder(&self) -> String { "".to_string() }
+        fn from_str(&self,buffer:&str) -> Result<SettingsRaw,Error> {
+            let mut raw = SettingsRaw::new();
+            for line in buffer.lines() {
+                if let Some(index) = line.find('=') {
+                    raw.insert(line[..index].to_string(),Type::infer(&line[index+1..]));
+                }
+            }
+            Ok(raw)
+        }
+        fn to_string(&self,object:&SettingsRaw) -> Result<String,Error> {
+            let mut lines : Vec<String> = object.iter().map(|(key,value)| format!("{}={}",key,value)).collect();
+            lines.sort();
+            Ok(lines.join("\n"))
+        }
+    }
+
+    #[test]
+    fn saving() {
+        let base = ::tempfile::tempdir().unwrap();
+        let mut state = StateFile::new(counter(base.path()));
+        let path = state.path().unwrap();
+        assert!(path.starts_with(base.path().join(".local/state/app")));
+        assert!(path.file_name().unwrap().to_str().unwrap().starts_with("settings.state"));
+        assert!(!path.exists());
+
+        assert!(state.set_value("count",&3).is_ok());
+        assert!(path.exists());
+
+        let mut state = StateFile::new(counter(base.path()));
+        assert_eq!(state.get_value("count"),Some(Type::Int(3)));
+        assert_eq!(state.delete_key("count").unwrap(),Some(Type::Int(3)));
+
+        let state = StateFile::new(counter(base.path()));
+        assert_eq!(state.get_value("count"),None);
+    }
+
+    #[test]
+    fn formats() {
+        let base = ::tempfile::tempdir().unwrap();
+        let mut state = StateFile::with_format(counter(base.path()),counter(base.path()));
+        assert!(state.set_value("count",&7).is_ok());
+        assert_eq!(::std::fs::read_to_string(state.path().unwrap()).unwrap(),"7");
+        assert_eq!(state.path().unwrap(),base.path().join(".local/state/app/settings.state"));
+    }
+
+    #[test]
+    fn two_instances() {
+        use std::fs;
+
+        let base = ::tempfile::tempdir().unwrap();
+        let mut first = StateFile::with_format(counter(base.path()),Lines{});
+        let mut second = StateFile::with_format(counter(base.path()),Lines{});
+
+        assert!(first.set_value("count",&1).is_ok());
+        assert!(second.set_value("last_check",&100).is_ok());
+        assert!(first.set_value("count",&2).is_ok());
+        let state = StateFile::with_format(counter(base.path()),Lines{});
+        assert_eq!(state.get_value("count"),Some(Type::Int(2)));
+        assert_eq!(state.get_value("last_check"),Some(Type::Int(100)));
+
+        // the newest change to a key wins
+        assert!(second.set_value("count",&3).is_ok());
+        assert_eq!(second.get_value("last_check"),Some(Type::Int(100)));
+        let state = StateFile::with_format(counter(base.path()),Lines{});
+        assert_eq!(state.get_value("count"),Some(Type::Int(3)));
+        assert_eq!(state.get_value("last_check"),Some(Type::Int(100)));
+
+        // a deleted file is made again
+        fs::remove_file(first.path().unwrap()).unwrap();
+        assert!(first.set_value("count",&4).is_ok());
+        assert_eq!(fs::read_to_string(first.path().unwrap()).unwrap(),"count=4\nlast_check=100");
+    }
+}
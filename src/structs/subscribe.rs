@@ -0,0 +1,203 @@
+use Type;
+
+use std::sync::{Arc,Mutex,MutexGuard,Weak};
+
+type Callback = Box<dyn FnMut(&str,Option<&Type>,Option<&Type>) + Send>;
+
+struct Subscriber {
+    id : usize,
+    pattern : String,
+    // shared so it can be called without holding the list
+    callback : Arc<Mutex<Callback>>,
+}
+
+#[derive(Default)]
+struct List {
+    next_id : usize,
+    subscribers : Vec<Subscriber>,
+}
+
+/// Keeps a callback from [Settings::subscribe](struct.Settings.html#method.subscribe)
+/// subscribed, it is removed when this is dropped
+#[must_use = "the callback is unsubscribed when the subscription is dropped"]
+pub struct Subscription {
+    list : Weak<Mutex<List>>,
+    id : usize,
+}
+
+impl Subscription {
+    pub fn unsubscribe(self) {
+        //! Removes the callback now, the same as dropping it.
+
+        drop(self);
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        // the settings may already be gone
+        if let Some(list) = self.list.upgrade() {
+            let id = self.id;
+            lock(&list).subscribers.retain(|subscriber| subscriber.id != id);
+        }
+    }
+}
+
+// the callbacks of one `Settings`, a clone starts without any.
+#[derive(Default)]
+pub struct Subscribers {
+    list : Arc<Mutex<List>>,
+}
+
+impl Subscribers {
+    pub fn add(&self, pattern : &str, callback : Callback) -> Subscription {
+        let mut list = lock(&self.list);
+        let id = list.next_id;
+        list.next_id += 1;
+        list.subscribers.push(Subscriber { id, pattern : pattern.to_string(), callback : Arc::new(Mutex::new(callback)) });
+
+        Subscription { list : Arc::downgrade(&self.list), id }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        lock(&self.list).subscribers.is_empty()
+    }
+
+    pub fn is_watched(&self, key_path : &str) -> bool {
+        //! Checks if any callback is interested in `key_path`.
+
+        lock(&self.list).subscribers.iter().any(|subscriber| matches(&subscriber.pattern,key_path))
+    }
+
+    pub fn is_subscribed(&self, key_path : &str) -> bool {
+        //! Checks if a callback subscribed to exactly `key_path`.
+
+        lock(&self.list).subscribers.iter().any(|subscriber| subscriber.pattern == key_path)
+    }
+
+    pub fn notify(&self, key_path : &str, exactly : bool, old : Option<&Type>, new : Option<&Type>) {
+        //! Calls the callbacks for `key_path`, only the ones subscribed to
+        //! exactly that key if `exactly`.
+
+        // the list isn't locked while calling, so a callback can drop
+        // its `Subscription`, or another one
+        let callbacks : Vec<(usize,Arc<Mutex<Callback>>)> = lock(&self.list).subscribers.iter()
+            .filter(|subscriber| if exactly { subscriber.pattern == key_path } else { matches(&subscriber.pattern,key_path) })
+            .map(|subscriber| (subscriber.id,subscriber.callback.clone()))
+            .collect();
+
+        for (id,callback) in callbacks {
+            if !lock(&self.list).subscribers.iter().any(|subscriber| subscriber.id == id) {
+                continue;
+            }
+            let mut callback = match callback.lock() {
+                Ok(callback) => callback,
+                Err(poisoned) => poisoned.into_inner(),
+            };
+            (callback)(key_path,old,new);
+        }
+    }
+}
+
+// changes the callbacks haven't been called for yet, so they can be
+// called once nothing is locked anymore, like the settings of a watcher.
+#[derive(Default)]
+#[must_use = "the callbacks are only called by `send`"]
+pub struct Notifications {
+    subscribers : Subscribers,
+    changes : Vec<Change>,
+}
+
+// the key, if it is only above the key that changed, and the old and new value
+type Change = (String,bool,Option<Type>,Option<Type>);
+
+impl Notifications {
+    pub fn send(self) {
+        for (key_path,above,old,new) in self.changes.iter() {
+            self.subscribers.notify(key_path,*above,old.as_ref(),new.as_ref());
+        }
+    }
+}
+
+impl Subscribers {
+    pub fn later(&self, changes : Vec<Change>) -> Notifications {
+        //! Keeps the `changes` to call the callbacks for with `send`. The
+        //! keys above a changed key only call the callbacks subscribed to
+        //! exactly that key.
+
+        Notifications { subscribers : Subscribers { list : self.list.clone() }, changes }
+    }
+}
+
+impl Clone for Subscribers {
+    fn clone(&self) -> Subscribers {
+        Subscribers::default()
+    }
+}
+
+fn lock(list : &Mutex<List>) -> MutexGuard<'_,List> {
+    match list.lock() {
+        Ok(list) => list,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+pub fn matches(pattern : &str, key_path : &str) -> bool {
+    //! `ui.theme` only matches itself, `keybindings.*` matches every key
+    //! under `keybindings` and `*` matches everything.
+
+    if pattern == "*" || pattern == key_path {
+        return true;
+    }
+    match pattern.len().checked_sub(1) {
+        Some(end) if pattern.ends_with(".*") => key_path.starts_with(&pattern[..end]),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{matches,Subscribers,Subscription};
+    use std::sync::{Arc,Mutex};
+
+    #[test]
+    fn matching() {
+        assert!(matches("ui.theme","ui.theme"));
+        assert!(!matches("ui.theme","ui.theme.dark"));
+        assert!(!matches("ui","ui.theme"));
+        assert!(matches("keybindings.*","keybindings.save"));
+        assert!(matches("keybindings.*","keybindings.file.save"));
+        assert!(!matches("keybindings.*","keybindings"));
+        assert!(!matches("keybindings.*","keybindingsextra.save"));
+        assert!(matches("*","anything.at.all"));
+    }
+
+    #[test]
+    fn unsubscribing_in_a_callback() {
+        let subscribers = Subscribers::default();
+        let calls = Arc::new(Mutex::new(0));
+        let slot : Arc<Mutex<Option<Subscription>>> = Arc::new(Mutex::new(None));
+
+        let (counted,own) = (calls.clone(),slot.clone());
+        let subscription = subscribers.add("*",Box::new(move |_,_,_| {
+            *counted.lock().unwrap() += 1;
+            own.lock().unwrap().take();
+        }));
+        *slot.lock().unwrap() = Some(subscription);
+
+        // one that drops the one after it
+        let later : Arc<Mutex<Option<Subscription>>> = Arc::new(Mutex::new(None));
+        let other = later.clone();
+        let _first = subscribers.add("*",Box::new(move |_,_,_| { other.lock().unwrap().take(); }));
+        let called = Arc::new(Mutex::new(false));
+        let flag = called.clone();
+        *later.lock().unwrap() = Some(subscribers.add("*",Box::new(move |_,_,_| { *flag.lock().unwrap() = true; })));
+
+        subscribers.notify("ui.theme",false,None,None);
+        subscribers.notify("ui.theme",false,None,None);
+        assert_eq!(*calls.lock().unwrap(),1);
+        assert!(!*called.lock().unwrap());
+        assert!(slot.lock().unwrap().is_none());
+        assert!(later.lock().unwrap().is_none());
+    }
+}
@@ -0,0 +1,245 @@
+use atomic;
+use structs::stamp;
+
+use failure::Error;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path,PathBuf};
+
+/// The local files the user has trusted
+///
+/// Used by [ShadowSettings](struct.ShadowSettings.html) when it requires
+/// trust, works like `direnv allow`. The store is a plain text file with a
+/// line for every trusted file, the sha256 of its contents followed by its
+/// absolute path
+///
+/// ```text
+/// 9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08 /home/user/project/settings.toml
+/// ```
+///
+/// In the path `%`, control characters and bytes that aren't ASCII are
+/// written as `%` and their hex value, so any path fits on one line.
+///
+/// A file is only trusted while it still has the same contents, so editing
+/// it means it has to be trusted again.
+#[derive(Clone,Debug)]
+pub struct TrustStore {
+    path : PathBuf,
+    // absolute path of the trusted file and its hash
+    entries : BTreeMap<PathBuf,String>,
+}
+
+impl TrustStore {
+    pub fn open<P>(path : P) -> Result<TrustStore,Error> where P : AsRef<Path> {
+        //! Reads the store at `path`, which is empty if the file doesn't
+        //! exist yet.
+
+        let mut store = TrustStore {
+            path : path.as_ref().to_path_buf(),
+            entries : BTreeMap::new(),
+        };
+
+        if !store.path.exists() {
+            return Ok(store);
+        }
+
+        for line in fs::read_to_string(&store.path)?.lines() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            match line.find(' ').and_then(|space| Some((decode(&line[space+1..])?,&line[..space]))) {
+                Some((file,hash)) => { store.entries.insert(file,hash.to_string()); },
+                None => warn!("Ignoring '{}' in {}",line,store.path.display()),
+            }
+        }
+
+        Ok(store)
+    }
+
+    pub fn path(&self) -> &Path { &self.path }
+
+    pub fn hash_file<P>(file : P) -> Result<String,Error> where P : AsRef<Path> {
+        //! The sha256 of the contents of `file`, as hex.
+
+        Ok(stamp::hash(&fs::read(file)?))
+    }
+
+    pub fn is_trusted<P>(&self, file : P) -> bool where P : AsRef<Path> {
+        //! Checks that `file` was trusted and hasn't changed since.
+
+        match fs::read(file.as_ref()) {
+            Ok(contents) => self.is_trusted_contents(file,&contents),
+            Err(_) => false,
+        }
+    }
+
+    pub fn is_trusted_contents<P>(&self, file : P, contents : &[u8]) -> bool where P : AsRef<Path> {
+        //! Checks that `file` was trusted with these `contents`. Use this
+        //! with contents that were already read, so the file can't be
+        //! changed between checking it and using it.
+
+        match fs::canonicalize(file) {
+            Ok(file) => self.entries.get(&file) == Some(&stamp::hash(contents)),
+            Err(_) => false,
+        }
+    }
+
+    pub fn trust<P>(&mut self, file : P) -> Result<(),Error> where P : AsRef<Path> {
+        //! Trusts the current contents of `file`, it needs to exist.
+
+        let file = fs::canonicalize(file)?;
+        let hash = TrustStore::hash_file(&file)?;
+        self.entries.insert(file,hash);
+        Ok(())
+    }
+
+    pub fn trust_contents<P>(&mut self, file : P, contents : &[u8]) -> Result<(),Error> where P : AsRef<Path> {
+        //! Trusts `file` with these `contents`, used after writing the
+        //! file so what someone else writes after it isn't trusted.
+
+        self.entries.insert(fs::canonicalize(file)?,stamp::hash(contents));
+        Ok(())
+    }
+
+    pub fn untrust<P>(&mut self, file : P) -> bool where P : AsRef<Path> {
+        //! Removes `file` from the store, returns `false` if it wasn't there.
+
+        let file = file.as_ref();
+        let file = fs::canonicalize(file).unwrap_or_else(|_| file.to_path_buf());
+        self.entries.remove(&file).is_some()
+    }
+
+    pub fn save(&self) -> Result<(),Error> {
+        //! Writes the store, making the folder if needed.
+
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut buffer = String::new();
+        for (file,hash) in self.entries.iter() {
+            buffer.push_str(&format!("{} {}\n",hash,encode(file)));
+        }
+        atomic::write(&self.path,buffer.as_bytes())
+    }
+}
+
+#[cfg(unix)]
+fn path_bytes(path : &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    path.as_os_str().as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+fn path_bytes(path : &Path) -> Vec<u8> {
+    // paths that aren't unicode won't match, so they are never trusted
+    path.to_string_lossy().as_bytes().to_vec()
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes : Vec<u8>) -> Option<PathBuf> {
+    use std::ffi::OsString;
+    use std::os::unix::ffi::OsStringExt;
+    Some(PathBuf::from(OsString::from_vec(bytes)))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes : Vec<u8>) -> Option<PathBuf> {
+    String::from_utf8(bytes).ok().map(PathBuf::from)
+}
+
+fn encode(path : &Path) -> String {
+    //! Writes the path on one line, see [TrustStore](struct.TrustStore.html).
+
+    let mut encoded = String::new();
+    for byte in path_bytes(path) {
+        match byte {
+            b'%' | 0..=0x1f | 0x7f..=0xff => encoded.push_str(&format!("%{:02X}",byte)),
+            byte => encoded.push(byte as char),
+        }
+    }
+    encoded
+}
+
+fn decode(encoded : &str) -> Option<PathBuf> {
+    //! Reads a path written by `encode`, `None` if it is broken.
+
+    let mut bytes = Vec::new();
+    let mut rest = encoded.as_bytes();
+    while let Some((&byte,after)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = ::std::str::from_utf8(after.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex,16).ok()?);
+            rest = &after[2..];
+        } else {
+            bytes.push(byte);
+            rest = after;
+        }
+    }
+    path_from_bytes(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TrustStore,encode,decode};
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn trusting() {
+        let dir = ::tempfile::tempdir().unwrap();
+        let file = dir.path().join("settings");
+        let store_path = dir.path().join("config").join("trusted");
+        fs::write(&file,"user.name = snsvrno").unwrap();
+
+        let mut store = TrustStore::open(&store_path).unwrap();
+        assert!(!store.is_trusted(&file));
+        assert!(store.trust(&file).is_ok());
+        assert!(store.is_trusted(&file));
+        assert!(store.trust(dir.path().join("missing")).is_err());
+        assert!(store.save().is_ok());
+
+        // it is kept
+        let mut store = TrustStore::open(&store_path).unwrap();
+        assert!(store.is_trusted(&file));
+        assert!(store.is_trusted(dir.path().join(".").join("settings")));
+
+        // editing the file means it isn't trusted anymore
+        fs::write(&file,"user.name = someone else").unwrap();
+        assert!(!store.is_trusted(&file));
+        assert!(store.trust(&file).is_ok());
+        assert!(store.is_trusted(&file));
+
+        assert!(store.untrust(&file));
+        assert!(!store.untrust(&file));
+        assert!(!store.is_trusted(&file));
+    }
+
+    #[test]
+    fn encoding() {
+        let path = Path::new("/home/user/100% sure\nsettings.toml");
+        assert_eq!(encode(path),"/home/user/100%25 sure%0Asettings.toml");
+        assert_eq!(decode(&encode(path)).unwrap(),path);
+        assert_eq!(decode("/plain/path").unwrap(),Path::new("/plain/path"));
+        assert!(decode("/broken%4").is_none());
+        assert!(decode("/broken%zz").is_none());
+    }
+
+    #[test]
+    fn trusting_contents() {
+        let dir = ::tempfile::tempdir().unwrap();
+        let file = dir.path().join("100% sure");
+        fs::write(&file,"user.name = snsvrno").unwrap();
+
+        let mut store = TrustStore::open(dir.path().join("trusted")).unwrap();
+        assert!(store.trust(&file).is_ok());
+        assert!(store.save().is_ok());
+
+        let store = TrustStore::open(dir.path().join("trusted")).unwrap();
+        assert!(store.is_trusted(&file));
+        assert!(store.is_trusted_contents(&file,b"user.name = snsvrno"));
+        assert!(!store.is_trusted_contents(&file,b"user.name = someone else"));
+    }
+}
@@ -1,6 +1,12 @@
+use serde::ser::{Serialize,Serializer,SerializeMap};
+use serde::de::{Deserialize,Deserializer,Visitor,MapAccess,SeqAccess};
 use std::collections::HashMap;
 use std::fmt;
 
+/// The key used to write a [Type::Unset](enum.Type.html#variant.Unset)
+/// in a file, as `{ "$unset" : true }`.
+pub const UNSET_KEY : &str = "$unset";
+
 
 /// Generic type enum used to work with data inside a `Settings`
 /// 
@@ -21,8 +27,14 @@ use std::fmt;
 ///     _ => { }, // probably not valid because options in this case are strings
 /// }
 /// ```
-#[derive(Serialize,Deserialize,Debug,Clone,PartialEq)]
-#[serde(untagged)]
+/// 
+/// `Type::Unset` is a tombstone, it is used in a higher priority file
+/// (like a local file in a [ShadowSettings](struct.ShadowSettings.html))
+/// to say that a key isn't set even if a lower priority file sets it.
+/// In files it is written as a map with a single `"$unset" : true` entry,
+/// for example `name = { "$unset" = true }` in TOML or `name: { $unset: true }`
+/// in YAML.
+#[derive(Debug,Clone,PartialEq)]
 pub enum Type {
     Text(String),
     Switch(bool),
@@ -31,32 +43,33 @@ pub enum Type {
     Complex(HashMap<String,Type>),
     Array(Vec<Type>),
     None,
+    Unset,
 }
 
 impl Type {
     // Checking types to see if `Type` is what you think it is, or want it to be.
-    pub fn is_text(&self) -> bool { if let &Type::Text(_) = self { true } else { false } }
-    pub fn is_switch(&self) -> bool { if let &Type::Switch(_) = self { true } else { false } }
-    pub fn is_int(&self) -> bool { if let &Type::Int(_) = self { true } else { false } }
-    pub fn is_float(&self) -> bool { if let &Type::Float(_) = self { true } else { false } }
-    pub fn is_complex(&self) -> bool { if let &Type::Complex(_) = self { true } else { false } }
-    pub fn is_array(&self) -> bool { if let &Type::Array(_) = self { true } else { false } }
-    pub fn is_none(&self) -> bool { if let &Type::None = self { true } else { false } }
+    pub fn is_text(&self) -> bool { matches!(*self,Type::Text(_)) }
+    pub fn is_switch(&self) -> bool { matches!(*self,Type::Switch(_)) }
+    pub fn is_int(&self) -> bool { matches!(*self,Type::Int(_)) }
+    pub fn is_float(&self) -> bool { matches!(*self,Type::Float(_)) }
+    pub fn is_complex(&self) -> bool { matches!(*self,Type::Complex(_)) }
+    pub fn is_array(&self) -> bool { matches!(*self,Type::Array(_)) }
+    pub fn is_none(&self) -> bool { matches!(*self,Type::None) }
 
     // Casts to get the inner value of the type. If you cast to the wrong thing you will get a None.
     // These don't "use" the original data but instead clone it.
-    pub fn to_text(&self) -> Option<String> { if let &Type::Text(ref inner) = self { Some(inner.clone()) } else { None } }
-    pub fn to_switch(&self) -> Option<bool> { if let &Type::Switch(ref inner) = self { Some(inner.clone()) } else { None } }
-    pub fn to_int(&self) -> Option<i32> { if let &Type::Int(ref inner) = self { Some(inner.clone()) } else { None } }
-    pub fn to_float(&self) -> Option<f32> { if let &Type::Float(ref inner) = self { Some(inner.clone()) } else { None } }
-    pub fn to_complex(&self) -> Option<HashMap<String,Type>> { if let &Type::Complex(ref inner) = self { Some(inner.clone()) } else { None } }
-    pub fn to_array(&self) -> Option<Vec<Type>> { if let &Type::Array(ref inner) = self { Some(inner.clone()) } else { None } }
+    pub fn to_text(&self) -> Option<String> { if let Type::Text(inner) = self { Some(inner.clone()) } else { None } }
+    pub fn to_switch(&self) -> Option<bool> { if let Type::Switch(inner) = self { Some(*inner) } else { None } }
+    pub fn to_int(&self) -> Option<i32> { if let Type::Int(inner) = self { Some(*inner) } else { None } }
+    pub fn to_float(&self) -> Option<f32> { if let Type::Float(inner) = self { Some(*inner) } else { None } }
+    pub fn to_complex(&self) -> Option<HashMap<String,Type>> { if let Type::Complex(inner) = self { Some(inner.clone()) } else { None } }
+    pub fn to_array(&self) -> Option<Vec<Type>> { if let Type::Array(inner) = self { Some(inner.clone()) } else { None } }
+}
 
-    // pub fn move_it(self) -> Type { self }
+impl Type {
+    pub fn is_unset(&self) -> bool { matches!(*self,Type::Unset) }
 
-    pub fn to_string(&self) -> String {
-        format!("{}",self)
-    }
+    // pub fn move_it(self) -> Type { self }
 
     pub fn flatten(&self , parent_key : Option<String>) -> Type {
         //! Flattens the `Type`. 
@@ -69,13 +82,14 @@ impl Type {
         //! flatten an entire `Settings`
 
         match self {
-            &Type::Text(ref text) => Type::Text(text.clone()),
-            &Type::Switch(ref boolean) => Type::Switch(boolean.clone()),
-            &Type::Int(ref int) => Type::Int(int.clone()),
-            &Type::Float(ref float) => Type::Float(float.clone()),
-            &Type::Array(ref array) => Type::Array(array.clone()),
-            &Type::None => Type::None,
-            &Type::Complex(ref numb) => {
+            Type::Text(text) => Type::Text(text.clone()),
+            Type::Switch(boolean) => Type::Switch(*boolean),
+            Type::Int(int) => Type::Int(*int),
+            Type::Float(float) => Type::Float(*float),
+            Type::Array(array) => Type::Array(array.clone()),
+            Type::None => Type::None,
+            Type::Unset => Type::Unset,
+            Type::Complex(numb) => {
                 let mut flat : HashMap<String,Type> = HashMap::new();
 
                 for (key,value) in numb {
@@ -100,6 +114,76 @@ impl Type {
             }
         }
     }
+
+    pub fn without_unset(self) -> Option<Type> {
+        //! Removes all the `Type::Unset` from inside of complexes and
+        //! arrays, used after the layers have been merged. Returns `None`
+        //! if the value itself is a `Type::Unset`.
+
+        match self {
+            Type::Unset => None,
+            Type::Complex(hash) => Some(Type::Complex(hash.into_iter()
+                .filter_map(|(key,value)| value.without_unset().map(|value| (key,value)))
+                .collect())),
+            Type::Array(array) => Some(Type::Array(array.into_iter()
+                .filter_map(|value| value.without_unset())
+                .collect())),
+            other => Some(other),
+        }
+    }
+}
+
+pub fn insert_path(raw : &mut HashMap<String,Type>, key_path : &str, value : Type) {
+    //! Inserts the value at the dotted `key_path`, making all the parents
+    //! needed and replacing anything that isn't a `Type::Complex`.
+
+    let mut parts : Vec<&str> = key_path.split('.').collect();
+    let last = parts.pop().unwrap_or("");
+
+    let mut current = raw;
+    for part in parts {
+        let entry = current.entry(part.to_string()).or_insert_with(|| Type::Complex(HashMap::new()));
+        if !entry.is_complex() {
+            *entry = Type::Complex(HashMap::new());
+        }
+        current = match *entry {
+            Type::Complex(ref mut hash) => hash,
+            _ => unreachable!(),
+        };
+    }
+
+    current.insert(last.to_string(),value);
+}
+
+pub fn get_path<'a>(raw : &'a HashMap<String,Type>, key_path : &str) -> Option<&'a Type> {
+    //! Gets the value at the dotted `key_path`.
+
+    let mut parts = key_path.split('.');
+    let mut current = raw.get(parts.next()?)?;
+    for part in parts {
+        current = match *current {
+            Type::Complex(ref hash) => hash.get(part)?,
+            _ => return None,
+        };
+    }
+    Some(current)
+}
+
+pub fn remove_path(raw : &mut HashMap<String,Type>, key_path : &str) -> Option<Type> {
+    //! Removes the value at the dotted `key_path`, leaving everything
+    //! else in the tree as it is.
+
+    let mut parts : Vec<&str> = key_path.split('.').collect();
+    let last = parts.pop()?;
+
+    let mut current = raw;
+    for part in parts {
+        current = match current.get_mut(part) {
+            Some(&mut Type::Complex(ref mut hash)) => hash,
+            _ => return None,
+        };
+    }
+    current.remove(last)
 }
 
 impl fmt::Display for Type {
@@ -110,6 +194,7 @@ impl fmt::Display for Type {
             Type::Float(ref value) => write!(f,"{}",value),
             Type::Text(ref value) => write!(f,"{}",value),
             Type::None => write!(f,"[BLANK]"),
+            Type::Unset => write!(f,"[UNSET]"),
             Type::Array(ref value) => {
                 write!(f,"[ ")?;
                 for i in 0..value.len() {
@@ -131,6 +216,87 @@ impl fmt::Display for Type {
     }
 }
 
+impl Serialize for Type {
+    fn serialize<S>(&self, serializer : S) -> Result<S::Ok,S::Error> where S : Serializer {
+        match *self {
+            Type::Text(ref text) => serializer.serialize_str(text),
+            Type::Switch(switch) => serializer.serialize_bool(switch),
+            Type::Int(int) => serializer.serialize_i32(int),
+            Type::Float(float) => serializer.serialize_f32(float),
+            Type::Complex(ref hash) => hash.serialize(serializer),
+            Type::Array(ref array) => array.serialize(serializer),
+            Type::None => serializer.serialize_unit(),
+            Type::Unset => {
+                let mut map = serializer.serialize_map(Some(1))?;
+                map.serialize_entry(UNSET_KEY,&true)?;
+                map.end()
+            },
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Type {
+    fn deserialize<D>(deserializer : D) -> Result<Type,D::Error> where D : Deserializer<'de> {
+        deserializer.deserialize_any(TypeVisitor)
+    }
+}
+
+struct TypeVisitor;
+
+impl<'de> Visitor<'de> for TypeVisitor {
+    type Value = Type;
+
+    fn expecting(&self, formatter : &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a settings value")
+    }
+
+    fn visit_bool<E>(self, value : bool) -> Result<Type,E> { Ok(Type::Switch(value)) }
+    fn visit_i64<E>(self, value : i64) -> Result<Type,E> {
+        // numbers too big for an `Int` are kept as a `Float`, so a file
+        // with a timestamp or a file size in it can still be loaded
+        match value {
+            value if value >= i64::from(i32::MIN) && value <= i64::from(i32::MAX) => Ok(Type::Int(value as i32)),
+            value => Ok(Type::Float(value as f32)),
+        }
+    }
+    fn visit_u64<E>(self, value : u64) -> Result<Type,E> {
+        match value {
+            value if value <= i32::MAX as u64 => Ok(Type::Int(value as i32)),
+            value => Ok(Type::Float(value as f32)),
+        }
+    }
+    fn visit_f64<E>(self, value : f64) -> Result<Type,E> { Ok(Type::Float(value as f32)) }
+    fn visit_str<E>(self, value : &str) -> Result<Type,E> { Ok(Type::Text(value.to_string())) }
+    fn visit_string<E>(self, value : String) -> Result<Type,E> { Ok(Type::Text(value)) }
+    fn visit_unit<E>(self) -> Result<Type,E> { Ok(Type::None) }
+    fn visit_none<E>(self) -> Result<Type,E> { Ok(Type::None) }
+
+    fn visit_some<D>(self, deserializer : D) -> Result<Type,D::Error> where D : Deserializer<'de> {
+        Type::deserialize(deserializer)
+    }
+
+    fn visit_seq<A>(self, mut seq : A) -> Result<Type,A::Error> where A : SeqAccess<'de> {
+        let mut array = Vec::new();
+        while let Some(value) = seq.next_element()? {
+            array.push(value);
+        }
+        Ok(Type::Array(array))
+    }
+
+    fn visit_map<A>(self, mut map : A) -> Result<Type,A::Error> where A : MapAccess<'de> {
+        let mut hash = HashMap::new();
+        while let Some((key,value)) = map.next_entry::<String,Type>()? {
+            hash.insert(key,value);
+        }
+
+        if hash.len() == 1 && hash.get(UNSET_KEY) == Some(&Type::Switch(true)) {
+            Ok(Type::Unset)
+        } else {
+            Ok(Type::Complex(hash))
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use Type;
@@ -158,7 +324,7 @@ mod tests {
         }
 
         assert!(complex.to_complex().unwrap().get("b.a").unwrap().to_switch().unwrap());
-        assert!(complex.to_complex().unwrap().get("a") == None);
+        assert!(!complex.to_complex().unwrap().contains_key("a"));
         assert!(complex.to_complex().unwrap().get("b.int").unwrap().to_int().unwrap() == 10);
         assert!(complex.to_complex().unwrap().get("b.float").unwrap().to_float().unwrap() == 10.23);
     }
@@ -185,7 +351,48 @@ mod tests {
         println!("{}",test4);
         println!("{}",test5);
         println!("{}",test6);
+    }
+
+    #[test]
+    fn unset() {
+        use ron;
+
+        let mut hash : HashMap<String,Type> = HashMap::new();
+        hash.insert("name".to_string(),Type::Unset);
+        hash.insert("other".to_string(),Type::Int(1));
+        let complex = Type::Complex(hash);
+
+        let text = ron::ser::to_string(&complex).unwrap();
+        assert!(text.contains("$unset"));
+        let read : Type = ron::de::from_str(&text).unwrap();
+        assert_eq!(read,complex);
+
+        let cleaned = complex.without_unset().unwrap().to_complex().unwrap();
+        assert_eq!(cleaned.len(),1);
+        assert_eq!(cleaned.get("other"),Some(&Type::Int(1)));
+        assert_eq!(Type::Unset.without_unset(),None);
+
+        let mut inner : HashMap<String,Type> = HashMap::new();
+        inner.insert("name".to_string(),Type::Unset);
+        inner.insert("other".to_string(),Type::Int(2));
+        let array = Type::Array(vec![Type::Unset,Type::Int(1),Type::Complex(inner)]);
+        let cleaned = array.without_unset().unwrap().to_array().unwrap();
+        assert_eq!(cleaned.len(),2);
+        assert_eq!(cleaned[0],Type::Int(1));
+        assert_eq!(cleaned[1].to_complex().unwrap().len(),1);
+    }
+
+    #[test]
+    fn large_integers() {
+        use ron;
+
+        assert_eq!(ron::de::from_str::<Type>("2147483647").unwrap(),Type::Int(i32::MAX));
+        assert_eq!(ron::de::from_str::<Type>("2147483648").unwrap(),Type::Float(2147483648.0));
+        assert_eq!(ron::de::from_str::<Type>("-2147483649").unwrap(),Type::Float(-2147483649.0));
 
-        assert!(true);
+        // one large number doesn't stop the rest from loading
+        let raw = ron::de::from_str::<::SettingsRaw>("{\"modified\":1700000000000,\"name\":\"app\"}").unwrap();
+        assert_eq!(raw.get("modified"),Some(&Type::Float(1700000000000.0)));
+        assert_eq!(raw.get("name"),Some(&Type::Text("app".to_string())));
     }
 }
\ No newline at end of file
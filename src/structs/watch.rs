@@ -0,0 +1,337 @@
+use Format;
+use ShadowSettings;
+
+use failure::Error;
+use notify;
+use notify::{Event,EventKind,RecommendedWatcher,RecursiveMode,Watcher};
+
+use std::fs;
+use std::path::{Path,PathBuf};
+use std::sync::{Arc,RwLock,RwLockReadGuard,RwLockWriteGuard};
+use std::sync::mpsc::{channel,Receiver};
+
+/// What happened when a watched file changed
+#[derive(Debug)]
+pub enum WatchEvent {
+    /// The files were loaded again, these keys have a different value now.
+    Changed { keys : Vec<String> },
+    /// A file couldn't be loaded, the settings are left as they were.
+    Failed { error : Error },
+}
+
+/// Loads the files of a `ShadowSettings` again when they change
+///
+/// Needs the `watch` feature. The folders of the global file and the
+/// local files (see [ShadowSettings::file_paths](struct.ShadowSettings.html#method.file_paths))
+/// are watched, and whenever one of the files is written
+/// [ShadowSettings::reload](struct.ShadowSettings.html#method.reload) is
+/// used so every change is reported as a `WatchEvent`, to a callback or a
+/// channel. Changes that don't change any value aren't reported.
+///
+/// Folders that don't exist when the watcher is made aren't watched.
+///
+/// ```rust,no_run
+/// # extern crate settingsfile;
+/// use settingsfile::{ShadowSettings,SettingsWatcher,WatchEvent};
+/// use settingsfile::EmptyConfig; // dumb config for examples and testing
+///
+/// let settings = ShadowSettings::new_and_load(EmptyConfig{});
+/// let (watcher,events) = SettingsWatcher::with_channel(settings).unwrap();
+/// for event in events {
+///     match event {
+///         WatchEvent::Changed { keys } => println!("{:?} changed",keys),
+///         WatchEvent::Failed { error } => println!("keeping the old settings, {}",error),
+///     }
+///     println!("{:?}",watcher.read().get_value("user.name"));
+/// }
+/// ```
+pub struct SettingsWatcher<T> where T : Format + Clone {
+    settings : Arc<RwLock<ShadowSettings<T>>>,
+    // stops watching when dropped
+    _watcher : RecommendedWatcher,
+}
+
+impl<T> SettingsWatcher<T> where T : Format + Clone + Send + Sync + 'static {
+    pub fn new<F>(settings : ShadowSettings<T>, mut callback : F) -> Result<SettingsWatcher<T>,Error>
+        where F : FnMut(WatchEvent) + Send + 'static,
+    {
+        //! Starts watching the files of `settings`, calling `callback`
+        //! from the watcher's thread when they change. The settings aren't
+        //! locked while `callback` and the subscribers are called, so they
+        //! can read them.
+
+        let dirs = watched_dirs(&settings.file_paths());
+        let settings = Arc::new(RwLock::new(settings));
+
+        let shared = settings.clone();
+        let mut watcher = notify::recommended_watcher(move |event : notify::Result<Event>| {
+            let event = match event {
+                Ok(event) => event,
+                Err(error) => return callback(WatchEvent::Failed { error : error.into() }),
+            };
+            // reading the files doesn't change them
+            if let EventKind::Access(_) = event.kind {
+                return;
+            }
+
+            // the settings are only locked while loading, so the callbacks
+            // can use them
+            let reloaded = {
+                let mut settings = match shared.write() {
+                    Ok(settings) => settings,
+                    Err(poisoned) => poisoned.into_inner(),
+                };
+                // the same file can be named differently, like through a symlink
+                let files : Vec<PathBuf> = settings.file_paths().iter().map(|file| canonical(file)).collect();
+                if !event.paths.iter().any(|path| files.contains(&canonical(path))) {
+                    return;
+                }
+                settings.reload_unnotified()
+            };
+
+            match reloaded {
+                Ok((keys,notifications)) => {
+                    notifications.send();
+                    if !keys.is_empty() {
+                        callback(WatchEvent::Changed { keys });
+                    }
+                },
+                Err(error) => {
+                    warn!("Keeping the loaded settings, {}",error);
+                    callback(WatchEvent::Failed { error });
+                },
+            }
+        })?;
+
+        for dir in dirs {
+            info!("Watching {}",dir.display());
+            watcher.watch(&dir,RecursiveMode::NonRecursive)?;
+        }
+
+        Ok(SettingsWatcher { settings, _watcher : watcher })
+    }
+
+    pub fn with_channel(settings : ShadowSettings<T>) -> Result<(SettingsWatcher<T>,Receiver<WatchEvent>),Error> {
+        //! Starts watching the files of `settings`, sending the events to
+        //! the returned channel.
+
+        let (sender,receiver) = channel();
+        let watcher = SettingsWatcher::new(settings,move |event| {
+            // nobody is listening anymore
+            let _ = sender.send(event);
+        })?;
+        Ok((watcher,receiver))
+    }
+}
+
+impl<T> SettingsWatcher<T> where T : Format + Clone {
+    pub fn read(&self) -> RwLockReadGuard<'_,ShadowSettings<T>> {
+        //! The settings, files aren't reloaded while this is held.
+
+        match self.settings.read() {
+            Ok(settings) => settings,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    pub fn write(&self) -> RwLockWriteGuard<'_,ShadowSettings<T>> {
+        //! The settings to change or save, files aren't reloaded while
+        //! this is held.
+
+        match self.settings.write() {
+            Ok(settings) => settings,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    pub fn settings(&self) -> Arc<RwLock<ShadowSettings<T>>> {
+        //! The shared settings, for other threads.
+
+        self.settings.clone()
+    }
+}
+
+fn watched_dirs(files : &[PathBuf]) -> Vec<PathBuf> {
+    //! The folders of `files` that exist, watching the folders instead of
+    //! the files because saving replaces the file with a new one.
+
+    let mut dirs : Vec<PathBuf> = files.iter()
+        .filter_map(|file| file.parent())
+        .filter(|dir| dir.is_dir())
+        .map(canonical)
+        .collect();
+    dirs.sort();
+    dirs.dedup();
+    dirs
+}
+
+fn canonical(path : &Path) -> PathBuf {
+    //! The absolute path without symlinks. Files that don't exist, because
+    //! they were removed or aren't made yet, use their folder's.
+
+    if let Ok(path) = fs::canonicalize(path) {
+        return path;
+    }
+    match (path.parent().map(fs::canonicalize),path.file_name()) {
+        (Some(Ok(dir)),Some(name)) => dir.join(name),
+        _ => path.to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SettingsWatcher,WatchEvent};
+    use Format;
+    use SettingsRaw;
+    use ShadowSettings;
+    use Type;
+    use atomic;
+
+    use failure::Error;
+    use std::fs;
+    use std::path::{Path,PathBuf};
+    use std::time::Duration;
+
+    // `size = value` lines, the global file in `base` and the local one in `local`
+    #[derive(Clone)]
+    struct Folders { base : PathBuf, local : PathBuf }
+    impl Format for Folders {
+        fn filename(&self) -> String { "settings".to_string() }
+        fn folder(&self) -> String { "app".to_string() }
+        fn base_dir(&self) -> Result<PathBuf,Error> { Ok(self.base.clone()) }
+        fn local_dir(&self) -> Result<PathBuf,Error> { Ok(self.local.clone()) }
+        fn from_str(&self,buffer:&str) -> Result<SettingsRaw,Error> {
+            let mut raw = SettingsRaw::new();
+            for line in buffer.lines() {
+                match line.find(" = ") {
+                    Some(index) => { raw.insert(line[..index].to_string(),Type::infer(&line[index+3..])); },
+                    None => return Err(format_err!("Can't read '{}'",line)),
+                }
+            }
+            Ok(raw)
+        }
+        fn to_string(&self,object:&SettingsRaw) -> Result<String,Error> {
+            let mut lines : Vec<String> = object.iter().map(|(key,value)| format!("{} = {}",key,value)).collect();
+            lines.sort();
+            Ok(lines.join("\n"))
+        }
+    }
+
+    // replaces the file all at once, the way `save` does
+    fn write(path : &Path, contents : &str) {
+        atomic::write(path,contents.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn watching() {
+        let base = ::tempfile::tempdir().unwrap();
+        let local = ::tempfile::tempdir().unwrap();
+        let format = Folders { base : base.path().to_path_buf(), local : local.path().to_path_buf() };
+        let global_path = format.get_path_and_file().unwrap();
+        fs::create_dir_all(global_path.parent().unwrap()).unwrap();
+        fs::write(&global_path,"size = 12\ntheme = dark").unwrap();
+
+        let settings = ShadowSettings::new_and_load(format.clone());
+        let (watcher,events) = SettingsWatcher::with_channel(settings).unwrap();
+        let timeout = Duration::from_secs(5);
+
+        write(&global_path,"size = 14\ntheme = dark");
+        match events.recv_timeout(timeout).unwrap() {
+            WatchEvent::Changed { keys } => assert_eq!(keys,vec!["size".to_string()]),
+            WatchEvent::Failed { error } => panic!("{}",error),
+        }
+        assert_eq!(watcher.read().get_value("size"),Some(Type::Int(14)));
+
+        // a broken file keeps what was loaded
+        write(&global_path,"size =");
+        if let WatchEvent::Changed { keys } = events.recv_timeout(timeout).unwrap() {
+            panic!("{:?} changed",keys);
+        }
+        assert_eq!(watcher.read().get_value("size"),Some(Type::Int(14)));
+        assert_eq!(watcher.read().get_value("theme"),Some(Type::Text("dark".to_string())));
+
+        // making a local file
+        write(&local.path().join("settings"),"theme = light");
+        write(&global_path,"size = 14\ntheme = dark");
+        loop {
+            if let WatchEvent::Changed { keys } = events.recv_timeout(timeout).unwrap() {
+                assert_eq!(keys,vec!["theme".to_string()]);
+                break;
+            }
+        }
+        assert_eq!(watcher.read().get_value("theme"),Some(Type::Text("light".to_string())));
+    }
+
+    #[test]
+    fn reading_in_callbacks() {
+        use std::sync::{Arc,Mutex,RwLock};
+        use std::sync::mpsc::channel;
+
+        let base = ::tempfile::tempdir().unwrap();
+        let local = ::tempfile::tempdir().unwrap();
+        let format = Folders { base : base.path().to_path_buf(), local : local.path().to_path_buf() };
+        let global_path = format.get_path_and_file().unwrap();
+        fs::create_dir_all(global_path.parent().unwrap()).unwrap();
+        fs::write(&global_path,"size = 12").unwrap();
+
+        // filled in once the watcher is made
+        type Slot = Arc<Mutex<Option<Arc<RwLock<ShadowSettings<Folders>>>>>>;
+        let slot : Slot = Arc::new(Mutex::new(None));
+        let read = |slot : &Slot| {
+            let settings = slot.lock().unwrap().clone().unwrap();
+            let size = settings.read().unwrap().get_value("size");
+            size
+        };
+
+        let settings = ShadowSettings::new_and_load(format);
+        let (sender,sizes) = channel();
+        let (subscribed,subscriber_sender) = (slot.clone(),sender.clone());
+        let _subscription = settings.subscribe_global("size",move |_,_,_| {
+            let _ = subscriber_sender.send(("subscriber",read(&subscribed)));
+        });
+        let called = slot.clone();
+        let watcher = SettingsWatcher::new(settings,move |event| {
+            if let WatchEvent::Changed { .. } = event {
+                let _ = sender.send(("callback",read(&called)));
+            }
+        }).unwrap();
+        *slot.lock().unwrap() = Some(watcher.settings());
+
+        write(&global_path,"size = 14");
+        let timeout = Duration::from_secs(5);
+        assert_eq!(sizes.recv_timeout(timeout).unwrap(),("subscriber",Some(Type::Int(14))));
+        assert_eq!(sizes.recv_timeout(timeout).unwrap(),("callback",Some(Type::Int(14))));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn watching_through_a_symlink() {
+        use std::os::unix::fs::symlink;
+        use std::sync::mpsc::channel;
+
+        let real = ::tempfile::tempdir().unwrap();
+        let links = ::tempfile::tempdir().unwrap();
+        let local = ::tempfile::tempdir().unwrap();
+        let base = links.path().join("base");
+        symlink(real.path(),&base).unwrap();
+        let format = Folders { base, local : local.path().to_path_buf() };
+        let global_path = format.get_path_and_file().unwrap();
+        fs::create_dir_all(global_path.parent().unwrap()).unwrap();
+        fs::write(&global_path,"size = 12").unwrap();
+
+        let settings = ShadowSettings::new_and_load(format);
+        let (sender,sizes) = channel();
+        let _subscription = settings.subscribe_global("size",move |_,_,new| {
+            let _ = sender.send(new.cloned());
+        });
+        let (_watcher,events) = SettingsWatcher::with_channel(settings).unwrap();
+        let timeout = Duration::from_secs(5);
+
+        write(&global_path,"size = 14");
+        match events.recv_timeout(timeout).unwrap() {
+            WatchEvent::Changed { keys } => assert_eq!(keys,vec!["size".to_string()]),
+            WatchEvent::Failed { error } => panic!("{}",error),
+        }
+        assert_eq!(sizes.recv_timeout(timeout).unwrap(),Some(Type::Int(14)));
+    }
+}
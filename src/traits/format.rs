@@ -1,12 +1,13 @@
 use Type;
-use SupportedType;
 
 use failure::Error;
 use std::collections::HashMap;
-use serde::ser::Serialize;
 
-use dirs;
+use paths;
+use paths::{Location,XdgDir};
 use std::env;
+use std::ffi::OsString;
+use std::path::{Path,PathBuf};
 
 /// A convience type that is used to shorten the required return 
 /// type for the `Format` trait implemnetations. 
@@ -17,6 +18,10 @@ pub type SettingsRaw = HashMap<String,Type>;
 
 /// Trait for defining the physical properties of a `Settings`
 /// 
+/// `Format` is object safe, so it can be used as a `dyn Format` when the
+/// format needs to be picked at runtime, see
+/// [FormatRegistry](struct.FormatRegistry.html).
+/// 
 /// # Example Usage
 /// 
 /// This example uses the [ron](https://crates.io/crates/ron) crate
@@ -28,7 +33,7 @@ pub type SettingsRaw = HashMap<String,Type>;
 /// # extern crate settingsfile;
 /// # #[macro_use] extern crate failure; 
 /// use failure::Error;
-/// use settingsfile::{Format,Settings,SettingsRaw,SupportedType};
+/// use settingsfile::{Format,Settings,SettingsRaw};
 /// 
 /// #[derive(Clone)]
 /// struct BasicConfig { }
@@ -38,9 +43,7 @@ pub type SettingsRaw = HashMap<String,Type>;
 ///     fn filename(&self) -> String { "config.ron".to_string() }
 ///     fn folder(&self) -> String { ".config/app".to_string() }
 /// 
-///     fn from_str<T>(&self,buffer:&str) -> Result<SettingsRaw,Error> 
-///         where T : Format + Clone 
-///     {
+///     fn from_str(&self,buffer:&str) -> Result<SettingsRaw,Error> {
 ///         let result : Result<SettingsRaw,ron::de::Error> = ron::de::from_str(&buffer);
 ///
 ///         match result {
@@ -49,9 +52,7 @@ pub type SettingsRaw = HashMap<String,Type>;
 ///         }
 ///     }
 /// 
-///     fn to_string<T:Sized>(&self,object:&T) -> Result<String,Error>
-///         where T : SupportedType + serde::ser::Serialize, 
-///     {
+///     fn to_string(&self,object:&SettingsRaw) -> Result<String,Error> {
 ///         let result : Result<String,ron::ser::Error> = ron::ser::to_string(object);
 /// 
 ///         match result {
@@ -117,10 +118,8 @@ pub trait Format {
     /// ``` 
     fn folder(&self) -> String;
 
-    /// Returns the seralized form of the passed in `object`. 
-    /// Because this uses ***Serde.rs*** the `object` must have the 
-    /// `serde::de::Serialize` trait, and must also implement the 
-    /// `settingsfile::SupportedType` trait.
+    /// Returns the seralized form of the passed in `object`, which
+    /// is the raw data of a `Settings`.
     /// 
     /// Typically this is just a wrapped passthrough to the serde libray you are using. 
     /// Example using [ron-rs](https://github.com/alexcrichton/ron-rs):
@@ -134,9 +133,7 @@ pub trait Format {
     /// # struct Config { }
     /// # impl Config {
     /// # 
-    /// fn to_string<T:Sized>(&self,object:&T) -> Result<String,Error>
-    ///   where T : settingsfile::SupportedType + serde::ser::Serialize,
-    /// {
+    /// fn to_string(&self,object:&settingsfile::SettingsRaw) -> Result<String,Error> {
     ///   match ron::ser::to_string(object) {
     ///     Ok(string) => Ok(string),
     ///     Err(error) => Err(format_err!("{}",error))
@@ -147,7 +144,7 @@ pub trait Format {
     /// 
     /// You can see a working example in the test in the codebase 
     /// [testing_with_ron](https://github.com/snsvrno/settingsfile-rs/blob/master/tests/testing_with_ron.rs)
-    fn to_string<T>(&self,object:&T) -> Result<String,Error> where T : SupportedType + Serialize;
+    fn to_string(&self,object:&SettingsRaw) -> Result<String,Error>;
     
     /// The decoding function, will return a deserialized form 
     /// of a the `Settings` Rust Struct.
@@ -163,9 +160,7 @@ pub trait Format {
     /// # struct Config { }
     /// # impl Config {
     /// 
-    /// fn from_str<T>(&self,buffer:&str) -> Result<settingsfile::SettingsRaw,Error>
-    ///   where T : settingsfile::Format + Clone
-    /// {
+    /// fn from_str(&self,buffer:&str) -> Result<settingsfile::SettingsRaw,Error> {
     /// let result : Result<settingsfile::SettingsRaw,ron::de::Error> = ron::de::from_str(&buffer);
     ///   match result {
     ///     Ok(result) => Ok(result),
@@ -177,7 +172,9 @@ pub trait Format {
     /// 
     /// You can see a working example in the test in the codebase 
     /// [testing_with_ron](https://github.com/snsvrno/settingsfile-rs/blob/master/tests/testing_with_ron.rs)
-    fn from_str<T>(&self,buffer:&str) -> Result<SettingsRaw,Error> where T : Format + Clone;
+    // renaming it would break every `Format` out there.
+    #[allow(clippy::wrong_self_convention)]
+    fn from_str(&self,buffer:&str) -> Result<SettingsRaw,Error>;
 
     // have default implemntations ////////////////////////////////
     fn extension(&self) -> Option<String> {
@@ -198,11 +195,36 @@ pub trait Format {
         //! 
         //! If not defined then no extension will be used for the file.
         //! ***Settingsfile*** does not assume a file's format by its
-        //! extension so this is just a matter of user preference.
+        //! extension so this is just a matter of user preference, unless
+        //! you use a [FormatRegistry](struct.FormatRegistry.html).
          
         None
     }
 
+    fn locate(&self, _buffer : &str, _key_path : &str) -> Option<(usize,usize)> {
+        //! Option to find where `key_path` is written in the file's
+        //! contents, `buffer`. Returns the line and column, both starting
+        //! at 1.
+        //! 
+        //! Only used to report the origin of a value, for example with
+        //! `ShadowSettings::get_value_with_origin`. If not defined then
+        //! the origin will only have the file and not the position.
+        //! 
+        //! [formats](formats/index.html) has helpers that can be used here.
+        //! 
+        //! ```rust
+        //! # extern crate settingsfile;
+        //! # struct Config { }
+        //! # impl Config {
+        //! fn locate(&self, buffer : &str, key_path : &str) -> Option<(usize,usize)> {
+        //!     settingsfile::formats::locate_key(buffer,key_path)
+        //! }
+        //! # }
+        //! ```
+
+        None
+    }
+
     fn local_filename(&self) -> Option<String> {
         //! Option to allow for a different filename for a local
         //! file. only used with `ShadowSetting`. Functions the same 
@@ -219,38 +241,196 @@ pub trait Format {
         None
     }
 
+    fn select_for(&mut self, _path : &Path) {
+        //! Called with the path of a local or system file before it is
+        //! read, for formats that can read more than one kind of file,
+        //! like a [FormatRegistry](struct.FormatRegistry.html) picking the
+        //! format from the extension.
+        //! 
+        //! Does nothing by default.
+    }
+
+    fn app_name(&self) -> String {
+        //! The name of the application, used for the system wide folders.
+        //! 
+        //! Defaults to the last part of the `folder()` without the leading
+        //! '.', so `.config/app` is `app`.
+
+        paths::app_name(&self.folder())
+    }
+
+    fn location(&self) -> Location {
+        //! Where the files are kept, in `folder()` in the home directory
+        //! by default. See [Location](enum.Location.html).
+        //! 
+        //! ```rust
+        //! # extern crate settingsfile;
+        //! # use settingsfile::Location;
+        //! # struct Config { }
+        //! # impl Config {
+        //! fn location(&self) -> Location {
+        //!     // ~/.config/{app_name}/{file_name}.{extension}
+        //!     Location::Xdg
+        //! }
+        //! # }
+        //! # fn main() { }
+        //! ```
+
+        Location::Home
+    }
+
+    fn var_os(&self, key : &str) -> Option<OsString> {
+        //! Reads an environment variable, used for the XDG directories.
+        //! Can be changed for tests.
+
+        env::var_os(key)
+    }
+
+    fn portable_marker(&self) -> Option<String> {
+        //! The name of a file that turns on `Location::Portable` when it is
+        //! next to the executable, like `portable.txt`. A settings file next
+        //! to the executable turns it on too, even without a marker.
+        //! 
+        //! `None` by default, so only the settings file is looked for.
+
+        None
+    }
+
+    fn exe_dir(&self) -> Result<PathBuf,Error> {
+        //! The folder of the executable, used for `Location::Portable`.
+        //! Can be changed for tests.
+
+        paths::exe_dir()
+    }
+
+    fn active_location(&self) -> Location {
+        //! The location that is used, `Location::Portable` if the
+        //! [portable_marker](#method.portable_marker) or a settings file is
+        //! next to the executable, otherwise [location](#method.location).
+        //! 
+        //! The folder of the executable is only looked at the first time,
+        //! usually when the settings are loaded, and the same location is
+        //! used after that. This is done once for the whole program, so the
+        //! first format to look decides it.
+
+        if let Ok(dir) = self.exe_dir() {
+            let mut names = vec![self.get_filename()];
+            names.extend(self.portable_marker());
+            if paths::is_portable(&dir,&names) {
+                return Location::Portable;
+            }
+        }
+
+        self.location()
+    }
+
+    fn base_dir(&self) -> Result<PathBuf,Error> {
+        //! The directory that `folder()` is in, the home directory by default.
+        //! 
+        //! Can be changed to keep the files somewhere else, like a temporary
+        //! directory for tests or a sandbox.
+        //! 
+        //! ```rust
+        //! # extern crate failure;
+        //! # use failure::Error;
+        //! # use std::path::PathBuf;
+        //! # struct Config { }
+        //! # impl Config {
+        //! fn base_dir(&self) -> Result<PathBuf,Error> {
+        //!     Ok(PathBuf::from("/tmp/sandbox"))
+        //! }
+        //! # }
+        //! # fn main() { }
+        //! ```
+
+        paths::home_dir()
+    }
+
+    fn local_dir(&self) -> Result<PathBuf,Error> {
+        //! The directory the local file is in, the current directory by default.
+        //! Can be changed the same way as [base_dir](#method.base_dir).
+
+        paths::current_dir()
+    }
+
     // functions that shouldn't generally need to be implemented //
-    fn get_path(&self) -> String {
+    fn get_path(&self) -> Result<PathBuf,Error> {
         //! Will give the correct path depending on what was implemented
         //! in the configuration
+        //! 
+        //! Errors if there isn't a home directory instead of using the
+        //! current directory.
   
-        match dirs::home_dir() {
-            // probably not the most graceful way to do this, but
-            // i don't know a likely scenario where you won't have
-
-            None => "".to_string(),
-            Some(mut dir) => {
-                dir.push(format!("{}",self.folder()));
-                dir.display().to_string()
-            }
+        self.get_dir(XdgDir::Config)
+    }
+
+    fn get_dir(&self, kind : XdgDir) -> Result<PathBuf,Error> {
+        //! The folder for the `kind` of files, `$XDG_STATE_HOME/{app_name}`
+        //! for `XdgDir::State` with `Location::Xdg`. For the other locations
+        //! everything is in the same folder.
+
+        match self.active_location() {
+            Location::Portable => self.exe_dir(),
+            Location::Home => Ok(self.base_dir()?.join(self.folder())),
+            Location::Xdg => {
+                let dir = paths::xdg_dir(kind,self.var_os(kind.var()),&self.base_dir()?);
+                Ok(dir.join(self.app_name()))
+            },
         }
     }
 
-    // functions that shouldn't generally need to be implemented //
-    fn get_path_and_file(&self) -> String {
+    fn get_legacy_path_and_file(&self) -> Result<Option<PathBuf>,Error> {
+        //! Where the file was before using `Location::Xdg`, which is
+        //! `~/{folder}/{file_name}.{extension}`. `None` for the other locations.
+
+        match self.active_location() {
+            Location::Home | Location::Portable => Ok(None),
+            Location::Xdg => Ok(Some(self.base_dir()?.join(self.folder()).join(self.get_filename()))),
+        }
+    }
+
+    fn get_path_and_file(&self) -> Result<PathBuf,Error> {
         //! Will give the correct path including file depending on what was implemented
         //! in the configuration
   
-        match dirs::home_dir() {
-            // probably not the most graceful way to do this, but
-            // i don't know a likely scenario where you won't have
-            //
-            None => "".to_string(),
-            Some(mut dir) => {
-                dir.push(format!("{}/{}",self.folder(),self.get_filename()));
-                dir.display().to_string()
-            }
+        Ok(self.get_path()?.join(self.get_filename()))
+    }
+
+    fn get_trust_path_and_file(&self) -> Result<PathBuf,Error> {
+        //! The file that keeps the local files the user has trusted, next
+        //! to the global file and called `{file_name}.{extension}.trusted`.
+        //! 
+        //! Only used by `ShadowSettings` when it requires trust.
+
+        let mut path = self.get_path_and_file()?.into_os_string();
+        path.push(".trusted");
+        Ok(PathBuf::from(path))
+    }
+
+    fn get_lock_path_and_file(&self) -> Result<PathBuf,Error> {
+        //! The lock file used while loading and saving, next to the global
+        //! file and called `{file_name}.{extension}.lock`.
+
+        let mut path = self.get_path_and_file()?.into_os_string();
+        path.push(".lock");
+        Ok(PathBuf::from(path))
+    }
+
+    fn get_system_paths(&self) -> Vec<PathBuf> {
+        //! The system wide files, the most important first.
+        //! 
+        //! On unix these are `{dir}/{app_name}/{file_name}.{extension}` for
+        //! every directory in `$XDG_CONFIG_DIRS` (`/etc/xdg` when it isn't set)
+        //! and then `/etc/{app_name}/{file_name}.{extension}`. Other
+        //! platforms don't have any system files.
+
+        if !cfg!(unix) {
+            return Vec::new();
         }
+
+        paths::system_config_dirs(self.var_os("XDG_CONFIG_DIRS")).into_iter()
+            .map(|dir| dir.join(self.app_name()).join(self.get_filename()))
+            .collect()
     }
 
     fn get_filename(&self) -> String {
@@ -258,9 +438,9 @@ pub trait Format {
         //! the extension (if defined)
         
         if let Some(ext) = self.extension() {
-            return format!("{}.{}",self.filename(),ext);
+            format!("{}.{}",self.filename(),ext)
         } else {
-            return self.filename();
+            self.filename()
         }
     }
 
@@ -280,25 +460,184 @@ pub trait Format {
         }
     }
 
-    fn get_local_path_and_filename(&self) -> String {
+    fn get_local_path_and_filename(&self) -> Result<PathBuf,Error> {
         //! Returns the path where the local configuration
         //! would be.
+        //! 
+        //! Errors if the current directory can't be used, like when it
+        //! was deleted.
         
-        match env::current_dir() {
-            // panics on error because I don't think this is a credible scenario
-            // the user is in the current directory running this program, so
-            // they should have access to it, this will most probably be used
-            // for CLI applications and you must have permission to go inside 
-            // a directory in order to run it.
-            Err(_) => { panic!(); },
-            Ok(mut path) => {
-                if let Some(local_filename) = self.get_local_filename() {
-                    path.push(local_filename);
-                } else {
-                    path.push(self.get_filename());
-                }
-                return path.display().to_string();
+        let filename = match self.get_local_filename() {
+            Some(local_filename) => local_filename,
+            None => self.get_filename(),
+        };
+        Ok(self.local_dir()?.join(filename))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use Format;
+    use Settings;
+    use SettingsRaw;
+    use Type;
+
+    use Location;
+    use XdgDir;
+
+    use failure::Error;
+    use std::ffi::OsString;
+    use std::fs;
+    use std::path::{Path,PathBuf};
+
+    // keeps a single `value` in a folder
+    #[derive(Clone)]
+    struct Sandbox { base : PathBuf, location : Location, config_home : Option<PathBuf>, exe : Option<PathBuf>, marker : Option<String> }
+    impl Sandbox {
+        fn new(base : &Path) -> Sandbox {
+            Sandbox {
+                base : base.to_path_buf(),
+                location : Location::Home,
+                config_home : None,
+                exe : None,
+                marker : Some("portable".to_string()),
+            }
+        }
+    }
+    impl Format for Sandbox {
+        fn filename(&self) -> String { "settings".to_string() }
+        fn folder(&self) -> String { ".app".to_string() }
+        fn extension(&self) -> Option<String> { Some("txt".to_string()) }
+        fn location(&self) -> Location { self.location }
+        fn base_dir(&self) -> Result<PathBuf,Error> { Ok(self.base.clone()) }
+        fn var_os(&self, key : &str) -> Option<OsString> {
+            match key {
+                "XDG_CONFIG_HOME" => self.config_home.clone().map(|dir| dir.into_os_string()),
+                _ => None,
+            }
+        }
+        fn local_dir(&self) -> Result<PathBuf,Error> { Err(format_err!("no current directory")) }
+        fn portable_marker(&self) -> Option<String> { self.marker.clone() }
+        fn exe_dir(&self) -> Result<PathBuf,Error> {
+            match self.exe {
+                Some(ref exe) => Ok(exe.parent().unwrap().to_path_buf()),
+                None => Err(format_err!("no executable")),
             }
         }
+        fn from_str(&self,buffer:&str) -> Result<SettingsRaw,Error> {
+            let mut raw = SettingsRaw::new();
+            raw.insert("value".to_string(),Type::infer(buffer));
+            Ok(raw)
+        }
+        fn to_string(&self,object:&SettingsRaw) -> Result<String,Error> {
+            Ok(object.get("value").map(|value| value.to_string()).unwrap_or_default())
+        }
+    }
+
+    #[test]
+    fn paths() {
+        let base = ::tempfile::tempdir().unwrap();
+        let format = Sandbox::new(base.path());
+        assert_eq!(format.get_path().unwrap(),base.path().join(".app"));
+        assert_eq!(format.get_path_and_file().unwrap(),base.path().join(".app").join("settings.txt"));
+        assert_eq!(format.get_trust_path_and_file().unwrap(),base.path().join(".app").join("settings.txt.trusted"));
+        assert!(format.get_local_path_and_filename().is_err());
+
+        let mut settings = Settings::new(format.clone());
+        assert!(settings.set_value("value",&42).is_ok());
+        assert!(settings.save().is_ok());
+        assert!(format.get_path_and_file().unwrap().exists());
+
+        let mut loaded = Settings::new(format);
+        assert!(loaded.load().is_ok());
+        assert_eq!(loaded.get_value("value"),Some(Type::Int(42)));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_paths() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let base = ::tempfile::tempdir().unwrap();
+        let base = base.path().join(OsStr::from_bytes(b"caf\xe9"));
+        let format = Sandbox::new(&base);
+        assert!(format.get_path_and_file().unwrap().starts_with(&base));
+
+        let mut settings = Settings::new(format.clone());
+        assert!(settings.set_value("value",&true).is_ok());
+        assert!(settings.save().is_ok());
+
+        let mut loaded = Settings::new(format);
+        assert!(loaded.load().is_ok());
+        assert_eq!(loaded.get_value("value"),Some(Type::Switch(true)));
+    }
+
+    #[test]
+    fn xdg() {
+        let base = ::tempfile::tempdir().unwrap();
+        let mut format = Sandbox::new(base.path());
+        format.location = Location::Xdg;
+        assert_eq!(format.get_path_and_file().unwrap(),base.path().join(".config/app/settings.txt"));
+        assert_eq!(format.get_dir(XdgDir::State).unwrap(),base.path().join(".local/state/app"));
+        assert_eq!(format.get_dir(XdgDir::Cache).unwrap(),base.path().join(".cache/app"));
+        assert_eq!(format.get_dir(XdgDir::Data).unwrap(),base.path().join(".local/share/app"));
+        assert_eq!(format.get_legacy_path_and_file().unwrap(),Some(base.path().join(".app/settings.txt")));
+
+        format.config_home = Some(base.path().join("config"));
+        assert_eq!(format.get_path().unwrap(),base.path().join("config/app"));
+
+        // the old file is moved on the first load
+        fs::create_dir_all(base.path().join(".app")).unwrap();
+        fs::write(base.path().join(".app/settings.txt"),"legacy").unwrap();
+        let mut settings = Settings::new(format.clone());
+        assert!(settings.load().is_ok());
+        assert_eq!(settings.get_value("value"),Some(Type::Text("legacy".to_string())));
+        assert!(base.path().join("config/app/settings.txt").exists());
+        assert!(!base.path().join(".app/settings.txt").exists());
+
+        // not for `Location::Home`
+        assert_eq!(Sandbox::new(base.path()).get_legacy_path_and_file().unwrap(),None);
+    }
+
+    #[test]
+    fn portable() {
+        let base = ::tempfile::tempdir().unwrap();
+        let sandbox = |name : &str| {
+            let bin = base.path().join(name).join("bin");
+            fs::create_dir_all(&bin).unwrap();
+            let mut format = Sandbox::new(base.path());
+            format.exe = Some(bin.join("app.exe"));
+            (format,bin)
+        };
+
+        let (format,bin) = sandbox("installed");
+        assert_eq!(format.active_location(),Location::Home);
+        assert_eq!(format.get_path().unwrap(),base.path().join(".app"));
+        // a changed `exe_dir` is looked at every time
+        fs::write(bin.join("portable"),"").unwrap();
+        assert_eq!(format.active_location(),Location::Portable);
+        fs::remove_file(bin.join("portable")).unwrap();
+
+        // a marker
+        let (format,bin) = sandbox("usb");
+        fs::write(bin.join("portable"),"").unwrap();
+        assert_eq!(format.active_location(),Location::Portable);
+        assert_eq!(format.get_path_and_file().unwrap(),bin.join("settings.txt"));
+        assert_eq!(format.get_dir(XdgDir::Cache).unwrap(),bin);
+
+        let mut settings = Settings::new(format.clone());
+        assert!(settings.set_value("value",&1).is_ok());
+        assert!(settings.save().is_ok());
+        assert_eq!(settings.location(),Location::Portable);
+
+        // or the settings file itself, without a marker
+        let (mut format,bin) = sandbox("ci");
+        format.marker = None;
+        fs::write(bin.join("settings.txt"),"2").unwrap();
+        assert_eq!(format.active_location(),Location::Portable);
+        let mut settings = Settings::new(format);
+        assert!(settings.load().is_ok());
+        assert_eq!(settings.get_value("value"),Some(Type::Int(2)));
     }
-}
\ No newline at end of file
+}
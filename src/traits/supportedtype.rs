@@ -30,15 +30,15 @@ impl SupportedType for String {
 }
 
 impl SupportedType for bool {
-    fn wrap(&self) -> Type { Type::Switch(self.clone()) }
+    fn wrap(&self) -> Type { Type::Switch(*self) }
 }
 
 impl SupportedType for i32 {
-    fn wrap(&self) -> Type { Type::Int(self.clone()) }
+    fn wrap(&self) -> Type { Type::Int(*self) }
 }
 
 impl SupportedType for f32 {
-    fn wrap(&self) -> Type { Type::Float(self.clone()) }
+    fn wrap(&self) -> Type { Type::Float(*self) }
 }
 
 impl SupportedType for HashMap<String,Type> {
@@ -53,16 +53,17 @@ impl SupportedType for Type {
     fn wrap(&self) -> Type { self.clone() }
 }
 
-impl<'a> SupportedType for &'a Type {
+impl SupportedType for &Type {
     fn wrap(&self) -> Type { 
         match *self {
-            &Type::Text(ref inner) => Type::Text(inner.clone()),
-            &Type::Switch(ref inner) => Type::Switch(inner.clone()),
-            &Type::Int(ref inner) => Type::Int(inner.clone()),
-            &Type::Float(ref inner) => Type::Float(inner.clone()),
-            &Type::Array(ref inner) => Type::Array(inner.clone()),
-            &Type::Complex(ref inner) => Type::Complex(inner.clone()),
-            &Type::None => Type::None,
+            Type::Text(inner) => Type::Text(inner.clone()),
+            Type::Switch(inner) => Type::Switch(*inner),
+            Type::Int(inner) => Type::Int(*inner),
+            Type::Float(inner) => Type::Float(*inner),
+            Type::Array(inner) => Type::Array(inner.clone()),
+            Type::Complex(inner) => Type::Complex(inner.clone()),
+            Type::None => Type::None,
+            Type::Unset => Type::Unset,
         }
     }    
 }
@@ -71,6 +72,6 @@ impl SupportedType for str {
     fn wrap(&self) -> Type { Type::Text(self.to_string()) }
 }
 
-impl<'a> SupportedType for &'a str {
+impl SupportedType for &str {
     fn wrap(&self) -> Type { Type::Text(self.to_string()) }
 }
\ No newline at end of file
@@ -1,10 +1,9 @@
 extern crate settingsfile;
-use settingsfile::{ShadowSettings, Settings, SupportedType, SettingsRaw, Format, Type};
+use settingsfile::{ShadowSettings, Settings, SettingsRaw, Format, Type};
 
 
 #[macro_use] extern crate failure; use failure::Error;
 extern crate ron;
-extern crate serde;
 extern crate tempfile;
 use std::fs::File;
 use std::io::{Seek, SeekFrom};
@@ -18,10 +17,8 @@ impl Format for Configuration {
     fn filename(&self) -> String { "settings".to_string() }
     fn folder(&self) -> String { "program_app_folder".to_string() }
 
-    fn from_str<T>(&self,buffer:&str) -> Result<SettingsRaw,Error> 
-        where T : Format + Clone 
-    {
-        let result : Result<SettingsRaw,ron::de::Error> = ron::de::from_str(&buffer);
+    fn from_str(&self,buffer:&str) -> Result<SettingsRaw,Error> {
+        let result : Result<SettingsRaw,ron::de::Error> = ron::de::from_str(buffer);
         
         println!("from_str result: {:?}",result);
 
@@ -31,9 +28,7 @@ impl Format for Configuration {
         }
     }
 
-    fn to_string<T:Sized>(&self,object:&T) -> Result<String,Error>
-        where T : SupportedType + serde::ser::Serialize, 
-    {
+    fn to_string(&self,object:&SettingsRaw) -> Result<String,Error> {
         let result : Result<String,ron::ser::Error> = ron::ser::to_string(object);
             
         println!("to_string result: {:?}",result);
@@ -58,14 +53,14 @@ fn decoding_and_reencoding() {
     // uses `save_to` here so we can tell it what buffer to use,
     // if you use `save` it will automatically save it to the file
     // defined in the configuration
-    assert!(test.save_to(&mut tempfile).is_ok());
+    assert!(test.save_to(&tempfile).is_ok());
     // needed to reset the cursor to the front,
     // otherwise we will read from the end of the file 
     // and won't get any data.
     tempfile.seek(SeekFrom::Start(0)).unwrap(); 
 
     // now loading that temporary file that we just created
-    let loaded_setting = Settings::create_from(&mut tempfile,Configuration{}).unwrap();
+    let loaded_setting = Settings::create_from(&tempfile,Configuration{}).unwrap();
     assert_eq!(loaded_setting.get_value("user.name"),test.get_value("user.name"));
 
 }
@@ -79,15 +74,13 @@ fn file_reading_and_writing_settings() {
 
     assert!(test.set_value("user.name", "snsvrno").is_ok());
     if let Err(error) = test.save() {
-        println!("{:?}",error); 
-        assert!(false);
+        panic!("{:?}",error);
     }
 
     let mut other_test = Settings::new(Configuration{});
     
     if let Err(error) = other_test.load() {
-        println!("{:?}",error); 
-        assert!(false);
+        panic!("{:?}",error);
     }
 
     assert_eq!(other_test.get_value("user.name"),test.get_value("user.name"));
@@ -106,15 +99,13 @@ fn file_reading_and_writing_shadow_settings() {
     assert!(test.set_value_global("user.name", "other username").is_ok());
 
     if let Err(error) = test.save() {
-        println!("{:?}",error); 
-        assert!(false);
+        panic!("{:?}",error);
     }
     // loads the first case
     let mut other_test = ShadowSettings::new(Configuration{});
 
     if let Err(error) = other_test.load() {
-        println!("{:?}",error); 
-        assert!(false);
+        panic!("{:?}",error);
     };
 
     assert_eq!(other_test.get_value("user.name"),test.get_value("user.name"));
@@ -122,16 +113,59 @@ fn file_reading_and_writing_shadow_settings() {
     // set up second case
     assert!(test.set_value_local("user.name", "debug tester").is_ok());
     if let Err(error) = test.save() {
-        println!("{:?}",error); 
-        assert!(false);
+        panic!("{:?}",error);
     }
 
     if let Err(error) = other_test.load() {
-        println!("{:?}",error); 
-        assert!(false);
+        panic!("{:?}",error);
     };
 
     assert_eq!(other_test.get_value_local("user.name"),Some(Type::Text("debug tester".to_string())));
     assert_eq!(other_test.get_value_global("user.name"),Some(Type::Text("other username".to_string())));
     assert_eq!(other_test.get_value("user.name"),Some(Type::Text("debug tester".to_string())));
-}
\ No newline at end of file
+}
+#[test]
+fn defaults_from_embedded_text() {
+    let mut test = Settings::new(Configuration{});
+    assert!(test.set_defaults_from_str("{ \"user\" : { \"name\" : \"nobody\", \"shell\" : \"sh\" } }").is_ok());
+    assert!(test.set_value("user.name", "snsvrno").is_ok());
+    test.set_skip_defaults(true);
+
+    assert_eq!(test.get_value("user.shell"),Some(Type::Text("sh".to_string())));
+
+    let mut tempfile : File = tempfile::tempfile().unwrap();
+    assert!(test.save_to(&tempfile).is_ok());
+    tempfile.seek(SeekFrom::Start(0)).unwrap();
+
+    let loaded_setting = Settings::create_from(&tempfile,Configuration{}).unwrap();
+    assert_eq!(loaded_setting.get_value("user.name"),Some(Type::Text("snsvrno".to_string())));
+    assert_eq!(loaded_setting.get_value("user.shell"),None);
+}
+
+#[test]
+fn system_files_are_shadowed() {
+    use std::io::Write;
+
+    let folder = tempfile::tempdir().unwrap();
+    let xdg = folder.path().join("xdg_settings.ron");
+    let etc = folder.path().join("etc_settings.ron");
+    let missing = folder.path().join("missing.ron");
+    File::create(&xdg).unwrap().write_all(b"{ \"update\" : { \"channel\" : \"beta\" } }").unwrap();
+    File::create(&etc).unwrap().write_all(b"{ \"update\" : { \"channel\" : \"stable\", \"check\" : true }, \"theme\" : \"dark\" }").unwrap();
+
+    let mut test = ShadowSettings::new(Configuration{});
+    assert!(test.load_system_from(&[&xdg,&missing,&etc]).is_ok());
+    assert_eq!(test.system_paths(),vec![xdg.as_path(),etc.as_path()]);
+
+    // the first directory has priority
+    assert_eq!(test.get_value("update.channel"),Some(Type::Text("beta".to_string())));
+    assert_eq!(test.get_value("update.check"),Some(Type::Switch(true)));
+    assert_eq!(test.get_value_with_origin("update.channel").unwrap().1.path(),Some(xdg.as_path()));
+
+    // and the user's file shadows them
+    assert!(test.set_value_global("theme","light").is_ok());
+    assert_eq!(test.get_value("theme"),Some(Type::Text("light".to_string())));
+    assert_eq!(test.get_value_with_origin("theme").unwrap().1.layer(),"global");
+    assert!(test.unset_local("update.check").is_ok());
+    assert_eq!(test.get_value("update.check"),None);
+}